@@ -1,35 +1,86 @@
-use crate::controller::Controller;
+use crate::controller::ControllerPort;
+use crate::cpu::CpuBus;
+use crate::dma::{Dma, DmaCycle};
+use crate::expansion::{self, ExpansionPort};
 use crate::ppu::Ppu;
 use crate::rom::Rom;
 
 pub struct Bus {
     pub ram: [u8; 0x2000],
+    pub prg_ram: [u8; 0x2000], // cartridge RAM at 0x6000-0x7FFF
     pub rom: Rom,
     pub ppu: Ppu,
-    pub controller: Controller,
-    pub dma_in_progress: bool,
+    pub controllers: [Box<dyn ControllerPort>; 2],
+    pub expansion: Box<dyn ExpansionPort>,
+    // The microphone on the Famicom's second controller, held in for the coming frame
+    pub microphone: bool,
+    pub dma: Dma,
+    pub open_bus: u8, // the last value on the CPU data bus
+    // Address of the last CPU bus cycle, if it was a read. A DMA halt lands on that cycle and
+    // repeats it.
+    pub last_read: Option<u16>,
 }
 
 impl Bus {
-    pub fn new(rom: Rom, ppu: Ppu, controller: Controller) -> Self {
+    pub fn new(rom: Rom, ppu: Ppu, controllers: [Box<dyn ControllerPort>; 2]) -> Self {
         let mut res = Self {
             ram: [0u8; 0x2000],
+            prg_ram: [0u8; 0x2000],
             rom,
             ppu,
-            controller,
-            dma_in_progress: false,
+            controllers,
+            expansion: Box::new(expansion::Unplugged),
+            microphone: false,
+            dma: Dma::new(),
+            open_bus: 0,
+            last_read: None,
         };
 
-        for i in 0..res.rom.chr_rom.len() {
-            res.ppu.vram[i] = res.rom.chr_rom[i];
+        res.load_chr();
+
+        res
+    }
+
+    fn load_chr(&mut self) {
+        for i in 0..self.rom.chr_rom.len() {
+            self.ppu.vram[i] = self.rom.chr_rom[i];
         }
 
-        res.ppu.mirror_type = res.rom.mirroring;
+        self.ppu.mirror_type = self.rom.mirroring;
+    }
 
-        res
+    // Puts everything back the way it was when the console was switched on. Battery backed
+    // cartridge RAM keeps its contents, and the controllers stay plugged in.
+    pub fn power_cycle(&mut self) {
+        self.ram = [0u8; 0x2000];
+        if !self.rom.battery_backed_ram {
+            self.prg_ram = [0u8; 0x2000];
+        }
+        self.ppu = Ppu::new();
+        self.dma = Dma::new();
+        self.open_bus = 0;
+        self.last_read = None;
+        self.load_chr();
+    }
+
+    fn get_prg_rom_index(&self, addr: u16) -> usize {
+        let mut rom_access_addr = addr - 0x8000;
+        if self.rom.prg_rom.len() <= 0x4000 {
+            //if only one rom bank, should be mirrored
+            rom_access_addr %= 0x4000;
+        }
+        usize::from(rom_access_addr)
+    }
+
+    pub fn _set_word_at(&mut self, addr: u16, val: u16) {
+        let low = (val & 0b11111111) as u8;
+        let high = ((val >> 8) & 0b11111111) as u8;
+
+        self.set_byte_at(addr, low);
+        self.set_byte_at(addr + 1, high);
     }
 
-    pub fn get_byte_at(&mut self, addr: u16) -> u8 {
+    fn read_byte(&mut self, addr: u16) -> u8 {
         match addr {
             // 0x0000-0x07FF mirrored at 0x0800, 0x1000, and 0x18000.
             0x0000..=0x1FFF => {
@@ -50,7 +101,7 @@ impl Bus {
                         result
                     }
                     0x2003 => panic!("Not allowed to read from 0x2003"),
-                    0x2004 => self.ppu.get_oam_byte_at(self.ppu.oamaddr),
+                    0x2004 => self.ppu.read_oamdata(),
                     0x2005..=0x2006 => panic!("Not allowed to read from 0x{:04X}", addr),
                     0x2007 => {
                         let addr = self.ppu.ppuaddr;
@@ -103,37 +154,28 @@ impl Bus {
                     _ => unreachable!(),
                 }
             }
-            0x4000..=0x4017 => {
-                match addr {
-                    0x4015 => 0,
-                    0x4016 => self.controller.read() as u8,
-                    0x4017 => {
-                        //ignore read from controller 2
-                        0
+            0x4000..=0x4017 => match addr {
+                0x4015 => 0,
+                // The ports only drive D0-D4, the rest is whatever was last on the bus
+                0x4016 | 0x4017 => {
+                    let port = usize::from(addr - 0x4016);
+                    let mut data = self.controllers[port].read(&self.ppu)
+                        | (self.expansion.read(port, &self.ppu) & 0x1E);
+                    if port == 0 && self.microphone {
+                        data |= 1 << 2;
                     }
-                    _ => panic!("Don't know how to read from 0x{:05x}", addr),
-                }
-            }
-            0x4020..=0x7999 => 0x00, // emulate open bus behavior
-            0x8000..=0xFFFF => {
-                let mut rom_access_addr = addr - 0x8000;
-                if self.rom.prg_rom.len() <= 0x4000 {
-                    //if only one rom bank, should be mirrored
-                    rom_access_addr %= 0x4000;
+                    (self.open_bus & 0xE0) | (data & 0x1F)
                 }
-                self.rom.prg_rom[usize::from(rom_access_addr)]
-            }
+                _ => panic!("Don't know how to read from 0x{:05x}", addr),
+            },
+            0x4020..=0x5FFF => 0x00, // emulate open bus behavior
+            0x6000..=0x7FFF => self.prg_ram[usize::from(addr - 0x6000)],
+            0x8000..=0xFFFF => self.rom.prg_rom[self.get_prg_rom_index(addr)],
             _ => panic!("Don't know how to read from 0x{:05x}", addr),
         }
     }
 
-    pub fn get_word_at(&mut self, addr: u16) -> u16 {
-        let low = self.get_byte_at(addr);
-        let high = self.get_byte_at(addr + 1);
-        ((high as u16) << 8) | (low as u16)
-    }
-
-    pub fn set_byte_at(&mut self, addr: u16, val: u8) {
+    fn write_byte(&mut self, addr: u16, val: u8) {
         match addr {
             // 0x0000-0x07FF mirrored at 0x0800, 0x1000, and 0x18000.
             0x0000..=0x1FFF => {
@@ -199,39 +241,132 @@ impl Bus {
             }
             0x4000..=0x4017 => {
                 match addr {
-                    // Direct memory access (DMA)
-                    0x4014 => {
-                        let start_addr = (val as u16) << 8;
-                        for i in 0..=255 {
-                            let data = self.get_byte_at(start_addr + u16::from(i));
-                            self.ppu
-                                .set_oam_byte_at(self.ppu.oamaddr.wrapping_add(i), data);
-                        }
-                        self.dma_in_progress = true;
-                    }
+                    // Direct memory access (DMA). The copy itself happens over the following
+                    // CPU cycles, see dma_cycle.
+                    0x4014 => self.dma.start_oam(val),
+                    // One strobe line goes to both ports, and so do the other two output lines.
+                    // The expansion port gets all three too.
                     0x4016 => {
-                        self.controller.set_strobe(val & 0x1 != 0);
+                        for controller in &mut self.controllers {
+                            controller.set_strobe(val & 0x1 != 0);
+                            controller.set_out(val & 0x7);
+                        }
+                        self.expansion.write(val & 0x7);
                     }
                     _ => {}
                 }
             }
+            0x6000..=0x7FFF => self.prg_ram[usize::from(addr - 0x6000)] = val,
             0x8000..=0xFFFF => {
-                let mut rom_access_addr = addr - 0x8000;
-                if self.rom.prg_rom.len() <= 0x4000 {
-                    //if only one rom bank, should be mirrored
-                    rom_access_addr %= 0x4000;
+                let rom_access_addr = self.get_prg_rom_index(addr);
+                self.rom.prg_rom[rom_access_addr] = val;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl CpuBus for Bus {
+    fn get_byte_at(&mut self, addr: u16) -> u8 {
+        let val = self.read_byte(addr);
+        self.open_bus = val;
+        self.last_read = Some(addr);
+        val
+    }
+
+    fn set_byte_at(&mut self, addr: u16, val: u8) {
+        self.open_bus = val;
+        self.last_read = None;
+        self.write_byte(addr, val);
+    }
+
+    fn peek_byte_at(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[usize::from(addr % 0x0800)],
+            0x2000..=0x3FFF => match ((addr - 0x2000) % 0x8) + 0x2000 {
+                0x2000 => self.ppu.ppuctrl,
+                0x2001 => self.ppu.ppumask,
+                0x2002 => self.ppu.ppustatus,
+                0x2004 => self.ppu.read_oamdata(),
+                0x2007 => self.ppu.ppudata_buffer,
+                _ => 0x00,
+            },
+            0x6000..=0x7FFF => self.prg_ram[usize::from(addr - 0x6000)],
+            0x8000..=0xFFFF => self.rom.prg_rom[self.get_prg_rom_index(addr)],
+            _ => 0x00,
+        }
+    }
+
+    fn poll_nmi(&mut self) -> bool {
+        let nmi_waiting = self.ppu.nmi_waiting;
+        self.ppu.nmi_waiting = false;
+        nmi_waiting
+    }
+
+    fn ppu_position(&self) -> (u16, u16, u64) {
+        (self.ppu.scanline, self.ppu.cycle, self.ppu.frame)
+    }
+
+    fn dma_cycle(&mut self, cpu_cycle: u64) -> bool {
+        // Get cycles happen on even CPU cycles, put cycles on odd ones.
+        let dma_cycle = match self.dma.next_cycle(cpu_cycle % 2 == 0) {
+            Some(dma_cycle) => dma_cycle,
+            None => return false,
+        };
+
+        match dma_cycle {
+            // DMC DMA halts the CPU on a read cycle, and the read is done again once the DMA is
+            // over. A controller sees that as one more read and shifts out an extra bit, so the
+            // game loses a button: the DMC's "deleted bit" glitch.
+            DmaCycle::Halt => {
+                if let Some(addr @ (0x4016 | 0x4017)) = self.last_read {
+                    if self.dma.dmc_addr.is_some() {
+                        self.controllers[usize::from(addr - 0x4016)].read(&self.ppu);
+                    }
                 }
-                self.rom.prg_rom[usize::from(rom_access_addr)] = val;
             }
-            _ => print!("{}", char::from(val)),
+            DmaCycle::Idle => {}
+            DmaCycle::OamRead(addr) => {
+                let data = self.get_byte_at(addr);
+                self.dma.latch_oam_byte(data);
+            }
+            DmaCycle::OamWrite(data) => self.set_byte_at(0x2004, data),
+            DmaCycle::DmcRead(addr) => self.dma.dmc_sample = Some(self.get_byte_at(addr)),
+        }
+
+        true
+    }
+}
+
+/// A flat 64K block of RAM with no memory mapped devices, for running the CPU outside of a NES.
+pub struct FlatRam {
+    pub memory: Box<[u8; 0x10000]>,
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        Self {
+            memory: Box::new([0u8; 0x10000]),
         }
     }
+}
 
-    pub fn _set_word_at(&mut self, addr: u16, val: u16) {
-        let low = (val & 0b11111111) as u8;
-        let high = ((val >> 8) & 0b11111111) as u8;
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        self.set_byte_at(addr, low);
-        self.set_byte_at(addr + 1, high);
+impl CpuBus for FlatRam {
+    fn get_byte_at(&mut self, addr: u16) -> u8 {
+        self.memory[usize::from(addr)]
+    }
+
+    fn set_byte_at(&mut self, addr: u16, val: u8) {
+        self.memory[usize::from(addr)] = val;
+    }
+
+    fn peek_byte_at(&self, addr: u16) -> u8 {
+        self.memory[usize::from(addr)]
     }
 }
@@ -0,0 +1,533 @@
+// Key bindings for both players and the emulator's hotkeys, read from a TOML file:
+//
+//   [player1]
+//   a = "Z"
+//   select = "Right Shift"
+//
+//   [hotkeys]
+//   reset = "Ctrl+R"
+//   save_slot = ["Shift+F1", "Shift+F2", ...]
+//
+//   [player1.turbo]
+//   a = "C"
+//
+//   [turbo.a]               # how fast each button's turbo flips, in frames
+//   on = 2
+//   off = 2
+//
+//   [macros]
+//   dash = "Ctrl+1"
+//
+//   [power_pad]             # the mat's buttons 1-12, left to right and top to bottom
+//   buttons = ["1", "2", "3", "4", ...]
+//
+//   [games.gauntlet2]       # per game settings, by ROM file name without the extension
+//   port1 = "four_score"
+//   port2 = "four_score"
+//   expansion = "family_basic_keyboard"
+//
+// Anything left out keeps its default (see the Default impls). Keys are named the way SDL names
+// them, optionally prefixed with any of "Shift+", "Ctrl+" and "Alt+". Only the structure is checked
+// here, the frontend checks the key names themselves since it's the one that knows them.
+
+use crate::controller::{Buttons, Device, MAT_BUTTONS, PLAYERS};
+use crate::expansion::Expansion;
+use crate::input::TurboRates;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+pub const SLOTS: usize = 10;
+
+// A key, and the modifiers that have to be held down with it.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyChord {
+    pub key: String,
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl KeyChord {
+    pub fn key(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            shift: false,
+            ctrl: false,
+            alt: false,
+        }
+    }
+
+    pub fn parse(chord: &str) -> Result<Self, String> {
+        let mut parts = chord.split('+').collect::<Vec<_>>();
+        // "Shift++" is shift and the plus key
+        if chord.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+
+        let key = match parts.pop() {
+            Some(key) if !key.is_empty() => key,
+            _ => return Err(format!("no key in \"{}\"", chord)),
+        };
+
+        let mut parsed = Self::key(key);
+        for modifier in parts {
+            let held = match modifier {
+                "Shift" => &mut parsed.shift,
+                "Ctrl" => &mut parsed.ctrl,
+                "Alt" => &mut parsed.alt,
+                _ => {
+                    return Err(format!(
+                        "unknown modifier \"{}\" in \"{}\"",
+                        modifier, chord
+                    ))
+                }
+            };
+            *held = true;
+        }
+        Ok(parsed)
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = String;
+
+    fn try_from(chord: String) -> Result<Self, String> {
+        Self::parse(&chord)
+    }
+}
+
+impl From<KeyChord> for String {
+    fn from(chord: KeyChord) -> String {
+        chord.to_string()
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in [
+            (self.shift, "Shift"),
+            (self.ctrl, "Ctrl"),
+            (self.alt, "Alt"),
+        ]
+        .iter()
+        {
+            if *held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerKeys {
+    pub a: KeyChord,
+    pub b: KeyChord,
+    pub select: KeyChord,
+    pub start: KeyChord,
+    pub up: KeyChord,
+    pub down: KeyChord,
+    pub left: KeyChord,
+    pub right: KeyChord,
+    pub turbo: TurboKeys,
+}
+
+// Keys that hold down a button with turbo, for the buttons that have one.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TurboKeys {
+    pub a: Option<KeyChord>,
+    pub b: Option<KeyChord>,
+    pub select: Option<KeyChord>,
+    pub start: Option<KeyChord>,
+    pub up: Option<KeyChord>,
+    pub down: Option<KeyChord>,
+    pub left: Option<KeyChord>,
+    pub right: Option<KeyChord>,
+}
+
+impl TurboKeys {
+    // (button name, key, button) for every button that has a turbo key.
+    pub fn buttons(&self) -> Vec<(&'static str, &KeyChord, Buttons)> {
+        [
+            ("a", &self.a, Buttons::A),
+            ("b", &self.b, Buttons::B),
+            ("select", &self.select, Buttons::SELECT),
+            ("start", &self.start, Buttons::START),
+            ("up", &self.up, Buttons::UP),
+            ("down", &self.down, Buttons::DOWN),
+            ("left", &self.left, Buttons::LEFT),
+            ("right", &self.right, Buttons::RIGHT),
+        ]
+        .iter()
+        .filter_map(|&(name, key, button)| Some((name, key.as_ref()?, button)))
+        .collect()
+    }
+}
+
+impl PlayerKeys {
+    // (button name, key, button) for every button.
+    pub fn buttons(&self) -> [(&'static str, &KeyChord, Buttons); 8] {
+        [
+            ("a", &self.a, Buttons::A),
+            ("b", &self.b, Buttons::B),
+            ("select", &self.select, Buttons::SELECT),
+            ("start", &self.start, Buttons::START),
+            ("up", &self.up, Buttons::UP),
+            ("down", &self.down, Buttons::DOWN),
+            ("left", &self.left, Buttons::LEFT),
+            ("right", &self.right, Buttons::RIGHT),
+        ]
+    }
+
+    fn player2() -> Self {
+        Self {
+            a: KeyChord::key("G"),
+            b: KeyChord::key("F"),
+            select: KeyChord::key("Q"),
+            start: KeyChord::key("E"),
+            up: KeyChord::key("W"),
+            down: KeyChord::key("S"),
+            left: KeyChord::key("A"),
+            right: KeyChord::key("D"),
+            turbo: TurboKeys {
+                a: Some(KeyChord::key("T")),
+                b: Some(KeyChord::key("R")),
+                ..TurboKeys::default()
+            },
+        }
+    }
+
+    fn player3() -> Self {
+        Self {
+            a: KeyChord::key("O"),
+            b: KeyChord::key("U"),
+            select: KeyChord::key("7"),
+            start: KeyChord::key("8"),
+            up: KeyChord::key("I"),
+            down: KeyChord::key("K"),
+            left: KeyChord::key("J"),
+            right: KeyChord::key("L"),
+            turbo: TurboKeys::default(),
+        }
+    }
+
+    fn player4() -> Self {
+        Self {
+            a: KeyChord::key("Keypad 3"),
+            b: KeyChord::key("Keypad 1"),
+            select: KeyChord::key("Keypad 7"),
+            start: KeyChord::key("Keypad 9"),
+            up: KeyChord::key("Keypad 8"),
+            down: KeyChord::key("Keypad 5"),
+            left: KeyChord::key("Keypad 4"),
+            right: KeyChord::key("Keypad 6"),
+            turbo: TurboKeys::default(),
+        }
+    }
+}
+
+// Player 1's keys.
+impl Default for PlayerKeys {
+    fn default() -> Self {
+        Self {
+            a: KeyChord::key("Z"),
+            b: KeyChord::key("X"),
+            select: KeyChord::key("Right Shift"),
+            start: KeyChord::key("Return"),
+            up: KeyChord::key("Up"),
+            down: KeyChord::key("Down"),
+            left: KeyChord::key("Left"),
+            right: KeyChord::key("Right"),
+            turbo: TurboKeys {
+                a: Some(KeyChord::key("C")),
+                b: Some(KeyChord::key("V")),
+                ..TurboKeys::default()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hotkeys {
+    pub pause: KeyChord,
+    pub reset: KeyChord,
+    pub fast_forward: KeyChord, // held
+    pub rewind: KeyChord,       // held
+    pub microphone: KeyChord,   // held, blows into the Famicom's second controller
+    pub screenshot: KeyChord,
+    pub autofire: KeyChord,       // toggles autofire for both players
+    pub record_macro: KeyChord,   // starts recording, a macro's key then saves the recording to it
+    pub save_slot: Vec<KeyChord>, // one per slot
+    pub load_slot: Vec<KeyChord>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        let function_keys = (1..=SLOTS).map(|n| format!("F{}", n));
+        Self {
+            pause: KeyChord::key("P"),
+            reset: KeyChord::parse("Ctrl+R").unwrap(),
+            fast_forward: KeyChord::key("Tab"),
+            rewind: KeyChord::key("Backspace"),
+            microphone: KeyChord::key("M"),
+            screenshot: KeyChord::key("F12"),
+            autofire: KeyChord::parse("Ctrl+T").unwrap(),
+            record_macro: KeyChord::parse("Ctrl+M").unwrap(),
+            save_slot: function_keys
+                .clone()
+                .map(|key| KeyChord::parse(&format!("Shift+{}", key)).unwrap())
+                .collect(),
+            load_slot: function_keys.map(|key| KeyChord::key(&key)).collect(),
+        }
+    }
+}
+
+// Keys standing in for the buttons of a Power Pad or Family Trainer mat.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatKeys {
+    pub buttons: Vec<KeyChord>, // one per button
+}
+
+impl Default for MatKeys {
+    fn default() -> Self {
+        let keys = ["1", "2", "3", "4", "5", "6", "9", "0", "-", "=", "[", "]"];
+        Self {
+            buttons: keys.iter().map(|key| KeyChord::key(key)).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub player1: PlayerKeys,
+    pub player2: PlayerKeys,
+    pub player3: PlayerKeys,
+    pub player4: PlayerKeys,
+    pub hotkeys: Hotkeys,
+    pub turbo: TurboRates,
+    pub power_pad: MatKeys,
+    pub macros: BTreeMap<String, KeyChord>, // macro name to the key that plays it
+    pub games: BTreeMap<String, GameConfig>,
+}
+
+// Settings for one game. Anything not given is left to the command line and the defaults.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GameConfig {
+    pub port1: Option<Device>,
+    pub port2: Option<Device>,
+    pub expansion: Option<Expansion>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            player1: PlayerKeys::default(),
+            player2: PlayerKeys::player2(),
+            player3: PlayerKeys::player3(),
+            player4: PlayerKeys::player4(),
+            hotkeys: Hotkeys::default(),
+            turbo: TurboRates::default(),
+            power_pad: MatKeys::default(),
+            macros: BTreeMap::new(),
+            games: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    // Reads the config file if there is one, then applies the overrides on top. Each override is
+    // "<table>.<name>=<value>", as in "player1.a=Space" or "player1.turbo.b=N".
+    pub fn load(path: Option<&Path>, overrides: &[String]) -> io::Result<Self> {
+        let text = match path {
+            Some(path) => std::fs::read_to_string(path)?,
+            None => String::new(),
+        };
+        Self::parse(&text, overrides)
+    }
+
+    pub fn parse(text: &str, overrides: &[String]) -> io::Result<Self> {
+        // Whatever the file leaves out comes from the defaults. Filling them in field by field
+        // (rather than with serde's defaults) lets player 2 get player 2's defaults.
+        let mut table = toml::Table::try_from(Config::default()).unwrap();
+        merge(&mut table, text.parse::<toml::Table>().map_err(invalid)?);
+
+        for binding in overrides {
+            let usage = || invalid(format!("expected <table>.<name>=<value>, got {}", binding));
+            let (path, value) = binding.split_once('=').ok_or_else(usage)?;
+            let mut path = path.split('.').collect::<Vec<_>>();
+            let name = path.pop().unwrap();
+            if path.is_empty() {
+                return Err(usage());
+            }
+
+            let mut section = &mut table;
+            for part in path {
+                section = section
+                    .entry(part)
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                    .as_table_mut()
+                    .ok_or_else(|| invalid(format!("{} isn't a table", part)))?;
+            }
+            // Only numbers that already are numbers (from the defaults or the file) stay numbers,
+            // so digit keys can still be bound
+            let numeric = matches!(section.get(name), Some(toml::Value::Integer(_)));
+            let value = match value.parse() {
+                Ok(number) if numeric => toml::Value::Integer(number),
+                _ => toml::Value::String(value.to_string()),
+            };
+            section.insert(name.to_string(), value);
+        }
+
+        let config = Config::deserialize(table).map_err(invalid)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn players(&self) -> [(&'static str, &PlayerKeys); PLAYERS] {
+        [
+            ("player1", &self.player1),
+            ("player2", &self.player2),
+            ("player3", &self.player3),
+            ("player4", &self.player4),
+        ]
+    }
+
+    // Every binding, by its name in the config file.
+    pub fn bindings(&self) -> Vec<(String, &KeyChord)> {
+        let mut bindings = Vec::new();
+        for (player, keys) in self.players().iter() {
+            for (button, key, _) in keys.buttons().iter() {
+                bindings.push((format!("{}.{}", player, button), *key));
+            }
+            for (button, key, _) in keys.turbo.buttons() {
+                bindings.push((format!("{}.turbo.{}", player, button), key));
+            }
+        }
+
+        let hotkeys = &self.hotkeys;
+        bindings.push((String::from("hotkeys.pause"), &hotkeys.pause));
+        bindings.push((String::from("hotkeys.reset"), &hotkeys.reset));
+        bindings.push((String::from("hotkeys.fast_forward"), &hotkeys.fast_forward));
+        bindings.push((String::from("hotkeys.rewind"), &hotkeys.rewind));
+        bindings.push((String::from("hotkeys.microphone"), &hotkeys.microphone));
+        bindings.push((String::from("hotkeys.screenshot"), &hotkeys.screenshot));
+        bindings.push((String::from("hotkeys.autofire"), &hotkeys.autofire));
+        bindings.push((String::from("hotkeys.record_macro"), &hotkeys.record_macro));
+        for (slot, key) in hotkeys.save_slot.iter().enumerate() {
+            bindings.push((format!("hotkeys.save_slot[{}]", slot), key));
+        }
+        for (slot, key) in hotkeys.load_slot.iter().enumerate() {
+            bindings.push((format!("hotkeys.load_slot[{}]", slot), key));
+        }
+        for (button, key) in self.power_pad.buttons.iter().enumerate() {
+            bindings.push((format!("power_pad.buttons[{}]", button), key));
+        }
+        for (name, key) in &self.macros {
+            bindings.push((format!("macros.{}", name), key));
+        }
+        bindings
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        for (name, slots) in [
+            ("save_slot", &self.hotkeys.save_slot),
+            ("load_slot", &self.hotkeys.load_slot),
+        ]
+        .iter()
+        {
+            if slots.len() != SLOTS {
+                return Err(invalid(format!(
+                    "hotkeys.{} needs {} keys, got {}",
+                    name,
+                    SLOTS,
+                    slots.len()
+                )));
+            }
+        }
+
+        if self.power_pad.buttons.len() != MAT_BUTTONS {
+            return Err(invalid(format!(
+                "power_pad.buttons needs {} keys, got {}",
+                MAT_BUTTONS,
+                self.power_pad.buttons.len()
+            )));
+        }
+
+        for (name, game) in &self.games {
+            let devices = [
+                game.port1.unwrap_or(Device::Standard),
+                game.port2.unwrap_or(Device::Standard),
+            ];
+            Device::check_ports(devices)
+                .map_err(|err| invalid(format!("games.{}: {}", name, err)))?;
+        }
+
+        for (button, _, rate) in self.turbo.buttons().iter() {
+            if rate.on == 0 || rate.off == 0 {
+                return Err(invalid(format!(
+                    "turbo.{0}.on and turbo.{0}.off have to be at least 1 frame",
+                    button
+                )));
+            }
+        }
+
+        // Buttons (and the microphone) are read from the keys held down, modifiers don't mean
+        // anything there.
+        let bindings = self.bindings();
+        let held = bindings.iter().filter(|(name, _)| {
+            !name.starts_with("hotkeys.") && !name.starts_with("macros.")
+                || name == "hotkeys.microphone"
+        });
+        for (name, key) in held {
+            if key.shift || key.ctrl || key.alt {
+                return Err(invalid(format!(
+                    "{} can't use modifiers, got {}",
+                    name, key
+                )));
+            }
+        }
+
+        // Chords only clash if the key and the modifiers are all the same, so F1 and Shift+F1 can
+        // do different things.
+        for (i, (name, key)) in bindings.iter().enumerate() {
+            if let Some((other, _)) = bindings[..i].iter().find(|(_, other)| other == key) {
+                return Err(invalid(format!(
+                    "{} and {} are both bound to {}",
+                    other, name, key
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Copies `from` over `into`, going into tables that are in both instead of replacing them.
+fn merge(into: &mut toml::Table, from: toml::Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(toml::Value::Table(into)), toml::Value::Table(from)) => merge(into, from),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+fn invalid(err: impl ToString) -> Error {
+    Error::new(ErrorKind::InvalidData, err.to_string())
+}
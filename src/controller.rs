@@ -1,75 +1,823 @@
-use sdl2::keyboard::{Keycode, Scancode};
+use crate::ppu::{Ppu, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::savestate::{StateReader, StateWriter};
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use serde::{Deserialize, Serialize};
 
-pub struct Controller {
-    input: Rc<RefCell<sdl2::EventPump>>,
-    strobe: bool, // strobe tracks whether this controller is "latched" or not
-    state: Vec<bool>,
+use std::convert::TryFrom;
+use std::io;
+use std::ops::{BitOr, BitOrAssign};
+
+// Buttons held down on a standard controller, one bit per button in the order the controller
+// reports them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Buttons(pub u8);
+
+impl Buttons {
+    pub const NONE: Buttons = Buttons(0);
+    pub const A: Buttons = Buttons(1 << 0);
+    pub const B: Buttons = Buttons(1 << 1);
+    pub const SELECT: Buttons = Buttons(1 << 2);
+    pub const START: Buttons = Buttons(1 << 3);
+    pub const UP: Buttons = Buttons(1 << 4);
+    pub const DOWN: Buttons = Buttons(1 << 5);
+    pub const LEFT: Buttons = Buttons(1 << 6);
+    pub const RIGHT: Buttons = Buttons(1 << 7);
+
+    pub fn contains(self, buttons: Buttons) -> bool {
+        self.0 & buttons.0 == buttons.0
+    }
+}
+
+impl BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, rhs: Buttons) -> Buttons {
+        Buttons(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Buttons {
+    fn bitor_assign(&mut self, rhs: Buttons) {
+        self.0 |= rhs.0;
+    }
+}
+
+// Where the mouse points on the screen, in NES pixels, and whether its button is held. A y of
+// SCREEN_HEIGHT or more is off the screen, like a light gun aimed away from the TV.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pointer {
+    pub x: u8,
+    pub y: u8,
+    pub button: bool,
+}
+
+impl Pointer {
+    pub fn on_screen(self) -> bool {
+        usize::from(self.y) < SCREEN_HEIGHT
+    }
+}
+
+// Buttons on a Power Pad or Family Trainer mat. The mat's input is a u16 with bit n set while
+// button n + 1 is stepped on, numbering left to right and top to bottom:
+//
+//    1  2  3  4
+//    5  6  7  8
+//    9 10 11 12
+pub const MAT_BUTTONS: usize = 12;
+
+// The most players the console can take, with a four player adapter. Players 1 and 3 go through
+// port 1, players 2 and 4 through port 2.
+pub const PLAYERS: usize = 4;
+
+// A device plugged into one of the controller ports. The CPU strobes both ports at once through
+// $4016 and reads port 1 at $4016 and port 2 at $4017. Whoever drives the emulator hands it input
+// once per frame.
+pub trait ControllerPort {
+    // Called on writes to $4016 with bit 0 of the value.
+    fn set_strobe(&mut self, strobe: bool);
+
+    // Also called on writes to $4016, with the three output lines OUT0-OUT2 (bits 0-2). OUT0 is
+    // the strobe, only a few devices look at the other two.
+    fn set_out(&mut self, _out: u8) {}
+
+    // Called on reads of the port's register. Returns the data lines the device drives: D0-D4,
+    // with bit 0 as D0. The upper bits are open bus and filled in by the bus. The PPU is there for
+    // devices that look at the picture, like light guns.
+    fn read(&mut self, ppu: &Ppu) -> u8;
+
+    // How many players' buttons the device takes.
+    fn players(&self) -> usize {
+        0
+    }
+
+    // Buttons held down for the coming frame by one of the device's players (0 for the first).
+    // Devices without buttons ignore them.
+    fn set_buttons(&mut self, _player: usize, _buttons: Buttons) {}
+
+    fn buttons(&self, _player: usize) -> Buttons {
+        Buttons::NONE
+    }
+
+    // Where the mouse points for the coming frame. Devices that aren't aimed ignore it.
+    fn set_pointer(&mut self, _pointer: Pointer) {}
+
+    // None for devices that aren't aimed.
+    fn pointer(&self) -> Option<Pointer> {
+        None
+    }
+
+    // The mat buttons stepped on for the coming frame, see MAT_BUTTONS. Devices without a mat
+    // ignore them.
+    fn set_mat(&mut self, _mat: u16) {}
+
+    // None for devices without a mat.
+    fn mat(&self) -> Option<u16> {
+        None
+    }
+
+    fn device(&self) -> Device;
+
+    // Only the state inside the device; what's physically held down isn't part of the machine.
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()>;
+}
+
+// The devices that can be plugged into a controller port, by the name used to pick them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Device {
+    None,
+    Standard,
+    FourScore,         // plugs into both ports
+    FamicomFourPlayer, // the Famicom's expansion port controllers, see FamicomFourPlayer
+    Zapper,
+    Arkanoid,        // the NES Vaus controller
+    FamicomArkanoid, // the Famicom Vaus controller, plugs into both ports
+    PowerPad,
+    FamilyTrainer, // the Famicom's Power Pad, only in port 2
+}
+
+impl Device {
+    pub const NAMES: &'static [(&'static str, Device)] = &[
+        ("none", Device::None),
+        ("standard", Device::Standard),
+        ("four_score", Device::FourScore),
+        ("famicom_four_player", Device::FamicomFourPlayer),
+        ("zapper", Device::Zapper),
+        ("arkanoid", Device::Arkanoid),
+        ("famicom_arkanoid", Device::FamicomArkanoid),
+        ("power_pad", Device::PowerPad),
+        ("family_trainer", Device::FamilyTrainer),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Device> {
+        Self::NAMES
+            .iter()
+            .find(|&&(device_name, _)| device_name == name)
+            .map(|&(_, device)| device)
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|&&(_, device)| device == self)
+            .unwrap()
+            .0
+    }
+
+    // The device as seen from the given port (0 or 1).
+    pub fn create(self, port: usize) -> Box<dyn ControllerPort> {
+        match self {
+            Device::None => Box::new(Unplugged),
+            Device::Standard => Box::new(StandardController::new()),
+            Device::FourScore => Box::new(FourScore::new(port)),
+            Device::FamicomFourPlayer => Box::new(FamicomFourPlayer::default()),
+            Device::Zapper => Box::new(Zapper::new()),
+            Device::Arkanoid => Box::new(Arkanoid::new()),
+            Device::FamicomArkanoid => Box::new(FamicomArkanoid::new(port)),
+            Device::PowerPad => Box::new(PowerPad::new()),
+            Device::FamilyTrainer => Box::new(FamilyTrainer::new()),
+        }
+    }
+
+    // Checks the devices make sense together.
+    pub fn check_ports(devices: [Device; 2]) -> Result<(), String> {
+        for &device in &[Device::FourScore, Device::FamicomArkanoid] {
+            if (devices[0] == device) != (devices[1] == device) {
+                return Err(format!("{} has to be in both ports", device.name()));
+            }
+        }
+        if devices[0] == Device::FamilyTrainer {
+            return Err(String::from("family_trainer only goes in port 2"));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Device {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, String> {
+        Self::from_name(&name).ok_or_else(|| format!("unknown device \"{}\"", name))
+    }
+}
+
+impl From<Device> for String {
+    fn from(device: Device) -> String {
+        device.name().to_string()
+    }
+}
+
+// An empty port. Nothing drives the data lines, so reads see 0.
+pub struct Unplugged;
+
+impl ControllerPort for Unplugged {
+    fn set_strobe(&mut self, _strobe: bool) {}
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        0
+    }
+
+    fn device(&self) -> Device {
+        Device::None
+    }
+
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// A standard controller is a 4021 shift register. While strobe is high it keeps reloading from
+// the buttons, so every read sees A. Once strobe goes low each read shifts out the next button, in
+// the order A, B, Select, Start, Up, Down, Left, Right. The serial input is tied high, so after all
+// 8 buttons have been read it reports 1s.
+#[derive(Default)]
+pub struct StandardController {
+    buttons: Buttons,
+    strobe: bool,
+    shift: u8,
 }
 
-impl Controller {
-    pub fn new(input: Rc<RefCell<sdl2::EventPump>>) -> Self {
+impl StandardController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ControllerPort for StandardController {
+    fn set_strobe(&mut self, strobe: bool) {
+        // Strobe going low latches whatever was held at that moment.
+        if self.strobe {
+            self.shift = self.buttons.0;
+        }
+        self.strobe = strobe;
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        if self.strobe {
+            self.shift = self.buttons.0;
+            return self.shift & 1;
+        }
+
+        let bit = self.shift & 1;
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+
+    fn players(&self) -> usize {
+        1
+    }
+
+    fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        if player == 0 {
+            self.buttons = buttons;
+        }
+    }
+
+    fn buttons(&self, player: usize) -> Buttons {
+        if player == 0 {
+            self.buttons
+        } else {
+            Buttons::NONE
+        }
+    }
+
+    fn device(&self) -> Device {
+        Device::Standard
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.strobe);
+        writer.write_u8(self.shift);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.strobe = reader.read_bool()?;
+        self.shift = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+// The NES Four Score, seen from one of its two ports: the port's two controllers daisy chained,
+// then a signature so games can tell it's there. Each read after strobe returns the next of
+//
+//   reads 0-7    the first controller (player 1 or 2)
+//   reads 8-15   the second controller (player 3 or 4)
+//   reads 16-23  the signature: a 1 on read 19 for port 1, on read 18 for port 2
+//   after that   1s
+pub struct FourScore {
+    controllers: [StandardController; 2],
+    signature: u8,
+    strobe: bool,
+    reads: u8,
+}
+
+impl FourScore {
+    pub fn new(port: usize) -> Self {
         Self {
-            input,
+            controllers: Default::default(),
+            signature: if port == 0 { 1 << 3 } else { 1 << 2 },
             strobe: false,
-            state: Vec::new(),
-        }
-    }
-
-    // Standard controller reports values as follows:
-    // 0 - A
-    // 1 - B
-    // 2 - Select
-    // 3 - Start
-    // 4 - Up
-    // 5 - Down
-    // 6 - Left
-    // 7 - Right
-    pub fn set_strobe(&mut self, strobe: bool) {
-        // If this isn't actually a change, do nothing
-        if self.strobe == strobe {
-            return;
-        }
-
-        // If setting strobe to true, just keep track of that. If setting to false, we need to
-        // actually latch the values of each pressed key.
-        match strobe {
-            true => self.strobe = true,
-            false => {
-                self.strobe = false;
-                self.state = [
-                    Keycode::Z,      // A
-                    Keycode::X,      // B
-                    Keycode::RShift, // SELECT
-                    Keycode::Return, // START
-                    Keycode::Up,     // UP
-                    Keycode::Down,   // DOWN
-                    Keycode::Left,   // LEFT
-                    Keycode::Right,  // RIGHT
-                ]
-                .iter()
-                .map(|&keycode| {
-                    self.input
-                        .borrow()
-                        .keyboard_state()
-                        .is_scancode_pressed(Scancode::from_keycode(keycode).unwrap())
-                })
-                .rev()
-                .collect::<Vec<_>>();
+            reads: 0,
+        }
+    }
+}
+
+impl ControllerPort for FourScore {
+    fn set_strobe(&mut self, strobe: bool) {
+        for controller in &mut self.controllers {
+            controller.set_strobe(strobe);
+        }
+        self.strobe = strobe;
+        if strobe {
+            self.reads = 0;
+        }
+    }
+
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        if self.strobe {
+            return self.controllers[0].read(ppu);
+        }
+
+        let bit = match self.reads {
+            0..=7 => self.controllers[0].read(ppu),
+            8..=15 => self.controllers[1].read(ppu),
+            16..=23 => (self.signature >> (self.reads - 16)) & 1,
+            _ => 1,
+        };
+        self.reads = (self.reads + 1).min(24);
+        bit
+    }
+
+    fn players(&self) -> usize {
+        2
+    }
+
+    fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        self.controllers[player].set_buttons(0, buttons);
+    }
+
+    fn buttons(&self, player: usize) -> Buttons {
+        self.controllers[player].buttons(0)
+    }
+
+    fn device(&self) -> Device {
+        Device::FourScore
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        for controller in &self.controllers {
+            controller.save_state(writer);
+        }
+        writer.write_bool(self.strobe);
+        writer.write_u8(self.reads);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        for controller in &mut self.controllers {
+            controller.load_state(reader)?;
+        }
+        self.strobe = reader.read_bool()?;
+        self.reads = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+// Four players on a Famicom: the built in controller on D0, and a controller plugged into the
+// expansion port on D1 of the same register. Games that support it read both bits.
+#[derive(Default)]
+pub struct FamicomFourPlayer {
+    controllers: [StandardController; 2],
+}
+
+impl ControllerPort for FamicomFourPlayer {
+    fn set_strobe(&mut self, strobe: bool) {
+        for controller in &mut self.controllers {
+            controller.set_strobe(strobe);
+        }
+    }
+
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        self.controllers[0].read(ppu) | (self.controllers[1].read(ppu) << 1)
+    }
+
+    fn players(&self) -> usize {
+        2
+    }
+
+    fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        self.controllers[player].set_buttons(0, buttons);
+    }
+
+    fn buttons(&self, player: usize) -> Buttons {
+        self.controllers[player].buttons(0)
+    }
+
+    fn device(&self) -> Device {
+        Device::FamicomFourPlayer
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        for controller in &self.controllers {
+            controller.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        for controller in &mut self.controllers {
+            controller.load_state(reader)?;
+        }
+        Ok(())
+    }
+}
+
+// How close to where the Zapper is aimed a pixel has to be to light up its photodiode.
+const LIGHT_RADIUS: i32 = 2;
+// How many scanlines the photodiode stays lit after the beam has drawn something bright.
+const LIGHT_SCANLINES: i32 = 20;
+// How bright that something has to be, as Color::brightness.
+const LIGHT_BRIGHTNESS: u8 = 0x80;
+
+// The Zapper light gun. It ignores strobe and every read reports
+//
+//   D3  0 while the photodiode sees light, 1 otherwise
+//   D4  1 while the trigger is pulled
+//
+// The photodiode looks at the TV, so it only sees light just after the beam has drawn something
+// bright around where the gun is aimed. Games flash the targets white for a frame and poll the
+// light sense while that frame is being drawn.
+#[derive(Default)]
+pub struct Zapper {
+    pointer: Pointer,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn sees_light(&self, ppu: &Ppu) -> bool {
+        if !self.pointer.on_screen() {
+            return false;
+        }
+
+        let scanline = i32::from(ppu.scanline);
+        let cycle = i32::from(ppu.cycle);
+        let (aim_x, aim_y) = (i32::from(self.pointer.x), i32::from(self.pointer.y));
+        (aim_y - LIGHT_RADIUS..=aim_y + LIGHT_RADIUS).any(|y| {
+            (aim_x - LIGHT_RADIUS..=aim_x + LIGHT_RADIUS).any(|x| {
+                if x < 0 || y < 0 || x >= SCREEN_WIDTH as i32 || y >= SCREEN_HEIGHT as i32 {
+                    return false;
+                }
+                // The pixel at dot x is drawn on PPU cycle x + 2. Before that, what's in the
+                // framebuffer is from the last frame and long faded.
+                let drawn = scanline > y || (scanline == y && cycle > x + 2);
+                drawn
+                    && scanline - y < LIGHT_SCANLINES
+                    && ppu.pixel_color(x as usize, y as usize).brightness() >= LIGHT_BRIGHTNESS
+            })
+        })
+    }
+}
+
+impl ControllerPort for Zapper {
+    fn set_strobe(&mut self, _strobe: bool) {}
+
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        let light = if self.sees_light(ppu) { 0 } else { 1 << 3 };
+        let trigger = if self.pointer.button { 1 << 4 } else { 0 };
+        light | trigger
+    }
+
+    fn set_pointer(&mut self, pointer: Pointer) {
+        self.pointer = pointer;
+    }
+
+    fn pointer(&self) -> Option<Pointer> {
+        Some(self.pointer)
+    }
+
+    fn device(&self) -> Device {
+        Device::Zapper
+    }
+
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The range of the Vaus's potentiometer, from the knob turned all the way left to all the way
+// right. Arkanoid keeps the paddle on the screen over about this range.
+const PADDLE_MIN: u8 = 0x54;
+const PADDLE_MAX: u8 = 0xF4;
+
+// The knob and fire button of a Vaus controller, turned with the mouse. When strobe goes low the
+// knob's position is latched into a shift register, which reads back MSB first and inverted.
+#[derive(Default)]
+struct Paddle {
+    pointer: Pointer,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Paddle {
+    fn position(&self) -> u8 {
+        let range = u16::from(PADDLE_MAX - PADDLE_MIN);
+        PADDLE_MIN + (u16::from(self.pointer.x) * range / 0xFF) as u8
+    }
+
+    fn set_strobe(&mut self, strobe: bool) {
+        if self.strobe {
+            self.shift = !self.position();
+        }
+        self.strobe = strobe;
+    }
+
+    fn read_data(&mut self) -> u8 {
+        if self.strobe {
+            self.shift = !self.position();
+        }
+        let bit = self.shift >> 7;
+        if !self.strobe {
+            self.shift <<= 1;
+        }
+        bit
+    }
+
+    fn fire(&self) -> u8 {
+        u8::from(self.pointer.button)
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.strobe);
+        writer.write_u8(self.shift);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.strobe = reader.read_bool()?;
+        self.shift = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+// The NES Vaus controller that came with Arkanoid:
+//
+//   D3  1 while fire is pressed
+//   D4  the knob's position, see Paddle
+#[derive(Default)]
+pub struct Arkanoid {
+    paddle: Paddle,
+}
+
+impl Arkanoid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ControllerPort for Arkanoid {
+    fn set_strobe(&mut self, strobe: bool) {
+        self.paddle.set_strobe(strobe);
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        (self.paddle.fire() << 3) | (self.paddle.read_data() << 4)
+    }
+
+    fn set_pointer(&mut self, pointer: Pointer) {
+        self.paddle.pointer = pointer;
+    }
+
+    fn pointer(&self) -> Option<Pointer> {
+        Some(self.paddle.pointer)
+    }
+
+    fn device(&self) -> Device {
+        Device::Arkanoid
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.paddle.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.paddle.load_state(reader)
+    }
+}
+
+// The Famicom Vaus controller, seen from one port. It plugs into the expansion port and shows up
+// on D1 of both registers, next to the built in controllers on D0:
+//
+//   $4016 D1  1 while fire is pressed
+//   $4017 D1  the knob's position, see Paddle
+pub struct FamicomArkanoid {
+    controller: StandardController,
+    paddle: Paddle,
+    port: usize,
+}
+
+impl FamicomArkanoid {
+    pub fn new(port: usize) -> Self {
+        Self {
+            controller: StandardController::new(),
+            paddle: Paddle::default(),
+            port,
+        }
+    }
+}
+
+impl ControllerPort for FamicomArkanoid {
+    fn set_strobe(&mut self, strobe: bool) {
+        self.controller.set_strobe(strobe);
+        self.paddle.set_strobe(strobe);
+    }
+
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        let paddle = if self.port == 0 {
+            self.paddle.fire()
+        } else {
+            self.paddle.read_data()
+        };
+        self.controller.read(ppu) | (paddle << 1)
+    }
+
+    fn players(&self) -> usize {
+        1
+    }
+
+    fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        self.controller.set_buttons(player, buttons);
+    }
+
+    fn buttons(&self, player: usize) -> Buttons {
+        self.controller.buttons(player)
+    }
+
+    fn set_pointer(&mut self, pointer: Pointer) {
+        self.paddle.pointer = pointer;
+    }
+
+    fn pointer(&self) -> Option<Pointer> {
+        Some(self.paddle.pointer)
+    }
+
+    fn device(&self) -> Device {
+        Device::FamicomArkanoid
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.controller.save_state(writer);
+        self.paddle.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.controller.load_state(reader)?;
+        self.paddle.load_state(reader)
+    }
+}
+
+// Which mat buttons the Power Pad shifts out on D3 and D4, in order. D4 only has four, after them
+// it reads 1s like D3 does after its eight.
+const POWER_PAD_D3: [usize; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const POWER_PAD_D4: [usize; 4] = [4, 3, 12, 8];
+
+// The Power Pad mat: two 4021 shift registers like the standard controller's, read out at the
+// same time on D3 and D4. A button reads 1 while it's stepped on.
+#[derive(Default)]
+pub struct PowerPad {
+    mat: u16,
+    strobe: bool,
+    shift: [u8; 2], // D3, D4
+}
+
+impl PowerPad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn latch(&mut self) {
+        let pressed = |button: usize| u8::from(self.mat & (1 << (button - 1)) != 0);
+        let d3 = POWER_PAD_D3
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &button)| bits | (pressed(button) << i));
+        let d4 = POWER_PAD_D4
+            .iter()
+            .enumerate()
+            .fold(0xF0, |bits, (i, &button)| bits | (pressed(button) << i));
+        self.shift = [d3, d4];
+    }
+}
+
+impl ControllerPort for PowerPad {
+    fn set_strobe(&mut self, strobe: bool) {
+        if self.strobe {
+            self.latch();
+        }
+        self.strobe = strobe;
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        let bits = ((self.shift[0] & 1) << 3) | ((self.shift[1] & 1) << 4);
+        if !self.strobe {
+            for shift in &mut self.shift {
+                *shift = (*shift >> 1) | 0x80;
             }
         }
+        bits
+    }
+
+    fn set_mat(&mut self, mat: u16) {
+        self.mat = mat;
+    }
+
+    fn mat(&self) -> Option<u16> {
+        Some(self.mat)
+    }
+
+    fn device(&self) -> Device {
+        Device::PowerPad
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.strobe);
+        writer.write_u8(self.shift[0]);
+        writer.write_u8(self.shift[1]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.strobe = reader.read_bool()?;
+        self.shift = [reader.read_u8()?, reader.read_u8()?];
+        Ok(())
+    }
+}
+
+// The Family Trainer, the Power Pad's Famicom twin. Instead of shift registers it's scanned as a
+// matrix: each of OUT0-OUT2 that's low selects a row of four buttons, and $4017 D1-D4 read 0 for
+// the buttons stepped on in the selected rows,
+//
+//   OUT0 low  D4-D1 = buttons 1-4
+//   OUT1 low  D4-D1 = buttons 5-8
+//   OUT2 low  D4-D1 = buttons 9-12
+#[derive(Default)]
+pub struct FamilyTrainer {
+    mat: u16,
+    out: u8,
+}
+
+impl FamilyTrainer {
+    pub fn new() -> Self {
+        Self::default()
     }
+}
 
-    pub fn read(&mut self) -> bool {
-        match self.strobe {
-            true => self
-                .input
-                .borrow()
-                .keyboard_state()
-                .is_scancode_pressed(Scancode::from_keycode(Keycode::A).unwrap()),
-            false => self.state.pop().unwrap_or(false),
+impl ControllerPort for FamilyTrainer {
+    fn set_strobe(&mut self, _strobe: bool) {}
+
+    fn set_out(&mut self, out: u8) {
+        self.out = out;
+    }
+
+    fn read(&mut self, _ppu: &Ppu) -> u8 {
+        let mut pressed = 0;
+        for row in 0..3 {
+            if self.out & (1 << row) == 0 {
+                let buttons = (self.mat >> (row * 4)) & 0xF;
+                // Button 1 of the row on D4 down to button 4 on D1
+                pressed |= (0..4)
+                    .filter(|&button| buttons & (1 << button) != 0)
+                    .fold(0, |bits, button| bits | (1 << (4 - button)));
+            }
         }
+        !pressed & 0x1E
+    }
+
+    fn set_mat(&mut self, mat: u16) {
+        self.mat = mat;
+    }
+
+    fn mat(&self) -> Option<u16> {
+        Some(self.mat)
+    }
+
+    fn device(&self) -> Device {
+        Device::FamilyTrainer
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.out);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.out = reader.read_u8()?;
+        Ok(())
     }
 }
@@ -1,6 +1,45 @@
-use crate::bus::Bus;
+use crate::savestate::{StateReader, StateWriter};
+use crate::trace::Tracer;
 
-pub struct Cpu {
+use std::io;
+
+/// Everything the CPU needs from the outside world. The NES `Bus` is one implementation, but any
+/// 6502 system (or a flat block of RAM in tests) can drive the CPU by implementing this.
+pub trait CpuBus {
+    fn get_byte_at(&mut self, addr: u16) -> u8;
+
+    fn set_byte_at(&mut self, addr: u16, val: u8);
+
+    // Read a byte without triggering any side effects (clearing latches, advancing buffers, etc.)
+    fn peek_byte_at(&self, addr: u16) -> u8;
+
+    fn get_word_at(&mut self, addr: u16) -> u16 {
+        let low = self.get_byte_at(addr);
+        let high = self.get_byte_at(addr.wrapping_add(1));
+        (u16::from(high) << 8) | u16::from(low)
+    }
+
+    // Called once for every cycle the CPU completes.
+    fn tick(&mut self) {}
+
+    // Returns true (and acknowledges it) if an NMI has been signalled since the last poll.
+    fn poll_nmi(&mut self) -> bool {
+        false
+    }
+
+    // Current PPU scanline, dot and frame number, used when tracing.
+    fn ppu_position(&self) -> (u16, u16, u64) {
+        (0, 0, 0)
+    }
+
+    // Called on every cycle the CPU is between instructions. Returns true if DMA is using this
+    // cycle, in which case the CPU stays halted.
+    fn dma_cycle(&mut self, _cpu_cycle: u64) -> bool {
+        false
+    }
+}
+
+pub struct Cpu<B: CpuBus> {
     pub pc: u16,
     pub sp: u8,
     pub accumulator: u8,
@@ -14,7 +53,9 @@ pub struct Cpu {
     pub decimal: bool,
     pub overflow: bool,
     pub sign: bool,
-    pub bus: Bus,
+    pub variant: Variant,
+    pub tracer: Option<Tracer>,
+    pub bus: B,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -110,6 +151,15 @@ pub struct Instruction {
     pub page_cross_cost: bool,
 }
 
+// Which flavor of 6502 is being emulated. The only difference today is decimal mode: the 2A03 in
+// the NES has its BCD logic disconnected, while a stock NMOS 6502 honors the decimal flag in adc
+// and sbc.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Ricoh2A03,
+    Nmos6502,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Interrupt {
     Irq,   // maskable interrupt
@@ -117,8 +167,12 @@ pub enum Interrupt {
     Reset, // reset interrupt
 }
 
-impl Cpu {
-    pub fn new(bus: Bus) -> Self {
+impl<B: CpuBus> Cpu<B> {
+    pub fn new(bus: B) -> Self {
+        Self::with_variant(bus, Variant::Ricoh2A03)
+    }
+
+    pub fn with_variant(bus: B, variant: Variant) -> Self {
         Cpu {
             pc: 0x0,
             sp: 0x0,
@@ -133,43 +187,35 @@ impl Cpu {
             decimal: false,
             overflow: false,
             sign: false,
+            variant,
+            tracer: None,
             bus,
         }
     }
 
     pub fn step(&mut self) {
+        self.bus.tick();
+
         if self.cycles_left > 0 {
             self.cycles_left -= 1;
             self.cycles_completed += 1;
             return;
         }
 
-        let _pc = self.pc;
-        let _processor_status: u8 = ((self.sign as u8) << 7)
-            | ((self.overflow as u8) << 6)
-            | ((1 as u8) << 5)
-            | ((1 as u8) << 4)
-            | ((self.decimal as u8) << 3)
-            | ((self.interrupt as u8) << 2)
-            | ((self.zero as u8) << 1)
-            | (self.carry as u8);
-        let _a = self.accumulator;
-        let _x = self.x;
-        let _y = self.y;
-        let _sp = self.sp;
-        if self.bus.dma_in_progress {
-            self.bus.dma_in_progress = false;
-            self.cycles_left = 513;
-            if !self.bus.ppu.even_frame {
-                self.cycles_left += 1; // on odd CPU cycles DMA takes 1 extra cycle
-            }
-        } else if self.bus.ppu.nmi_waiting {
-            self.bus.ppu.nmi_waiting = false;
+        // DMA halts the CPU between instructions for as long as it needs the bus.
+        if self.bus.dma_cycle(self.cycles_completed) {
+            self.cycles_completed += 1;
+            return;
+        }
+
+        if self.bus.poll_nmi() {
             self.interrupt(Interrupt::Nmi);
         } else {
+            if self.tracer.is_some() {
+                self.trace_next_instruction();
+            }
+
             let next_instruction = self.fetch_next_instruction();
-            //println!("{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{:<3} SL:{:<3} CPU Cycle:{} Status:0b{:08b}", pc, a, x, y, processor_status, sp, self.bus.ppu.cycle, self.bus.ppu.scanline, self.cycles_completed, self.bus.ppu.ppustatus);
-            //println!("{:04x?} -> {:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{:<3} SL:{:<3} CPU Cycle:{}", next_instruction, pc, a, x, y, processor_status, sp, self.bus.ppu.cycle, self.bus.ppu.scanline, self.cycles_completed);
             self.execute_instruction(next_instruction);
 
             self.cycles_left += u16::from(next_instruction.cycles);
@@ -186,6 +232,71 @@ impl Cpu {
         self.cycles_completed += 1;
     }
 
+    // Hands the instruction at pc to the tracer. Everything is peeked, so this can't disturb the
+    // emulation.
+    fn trace_next_instruction(&mut self) {
+        let opcode = self.bus.peek_byte_at(self.pc);
+        let byte_after_opcode = self.bus.peek_byte_at(self.pc.wrapping_add(1));
+        let word_after_opcode = (u16::from(self.bus.peek_byte_at(self.pc.wrapping_add(2))) << 8)
+            | u16::from(byte_after_opcode);
+
+        if let Some(instruction) = Instruction::decode(opcode, byte_after_opcode, word_after_opcode)
+        {
+            if let Some(mut tracer) = self.tracer.take() {
+                tracer.trace(self, &instruction);
+                self.tracer = Some(tracer);
+            }
+        }
+    }
+
+    // The processor status register as it would be pushed to the stack. Bit 5 always reads as set,
+    // bit 4 (the "B flag") is only set when pushed by php or brk.
+    pub fn get_status(&self, break_flag: bool) -> u8 {
+        ((self.sign as u8) << 7)
+            | ((self.overflow as u8) << 6)
+            | (1 << 5)
+            | ((break_flag as u8) << 4)
+            | ((self.decimal as u8) << 3)
+            | ((self.interrupt as u8) << 2)
+            | ((self.zero as u8) << 1)
+            | (self.carry as u8)
+    }
+
+    // Registers and cycle counters. The bus saves its own state.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.pc);
+        writer.write_u8(self.sp);
+        writer.write_u8(self.accumulator);
+        writer.write_u8(self.x);
+        writer.write_u8(self.y);
+        writer.write_u8(self.get_status(false));
+        writer.write_u16(self.cycles_left);
+        writer.write_u64(self.cycles_completed);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.pc = reader.read_u16()?;
+        self.sp = reader.read_u8()?;
+        self.accumulator = reader.read_u8()?;
+        self.x = reader.read_u8()?;
+        self.y = reader.read_u8()?;
+        let status = reader.read_u8()?;
+        self.set_status(status);
+        self.cycles_left = reader.read_u16()?;
+        self.cycles_completed = reader.read_u64()?;
+        Ok(())
+    }
+
+    // Loads the flags from a status byte. Bits 4 and 5 don't exist in the CPU and are ignored.
+    pub fn set_status(&mut self, status: u8) {
+        self.sign = (status & (1 << 7)) != 0;
+        self.overflow = (status & (1 << 6)) != 0;
+        self.decimal = (status & (1 << 3)) != 0;
+        self.interrupt = (status & (1 << 2)) != 0;
+        self.zero = (status & (1 << 1)) != 0;
+        self.carry = (status & (1 << 0)) != 0;
+    }
+
     fn push_byte(&mut self, val: u8) {
         self.bus.set_byte_at(0x100 + u16::from(self.sp), val);
         self.sp = self.sp.wrapping_sub(1);
@@ -230,1480 +341,11 @@ impl Cpu {
     pub fn fetch_next_instruction(&mut self) -> Instruction {
         let opcode: u8 = self.bus.get_byte_at(self.pc);
         let byte_after_opcode: u8 = self.bus.get_byte_at(self.pc + 1);
-        let signed_byte_after_opcode: i8 = byte_after_opcode as i8;
         let word_after_opcode: u16 = self.bus.get_word_at(self.pc + 1);
-        //println!("Opcode: 0x{:02x}", opcode);
-        let result = match opcode {
-            /* Add */
-            0x69 => Instruction {
-                opcode: Opcode::Add,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x65 => Instruction {
-                opcode: Opcode::Add,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x75 => Instruction {
-                opcode: Opcode::Add,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x6D => Instruction {
-                opcode: Opcode::Add,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x7D => Instruction {
-                opcode: Opcode::Add,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x79 => Instruction {
-                opcode: Opcode::Add,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x61 => Instruction {
-                opcode: Opcode::Add,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x71 => Instruction {
-                opcode: Opcode::Add,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: true,
-            },
-            /* And */
-            0x29 => Instruction {
-                opcode: Opcode::And,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x25 => Instruction {
-                opcode: Opcode::And,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x35 => Instruction {
-                opcode: Opcode::And,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x2D => Instruction {
-                opcode: Opcode::And,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x3D => Instruction {
-                opcode: Opcode::And,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x39 => Instruction {
-                opcode: Opcode::And,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x21 => Instruction {
-                opcode: Opcode::And,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x31 => Instruction {
-                opcode: Opcode::And,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            /* Asl */
-            0x0A => Instruction {
-                opcode: Opcode::Asl,
-                mode: AddressingMode::Accumulator,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x06 => Instruction {
-                opcode: Opcode::Asl,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x16 => Instruction {
-                opcode: Opcode::Asl,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x0E => Instruction {
-                opcode: Opcode::Asl,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x1E => Instruction {
-                opcode: Opcode::Asl,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Bcc */
-            0x90 => Instruction {
-                opcode: Opcode::Bcc,
-                mode: AddressingMode::Relative(signed_byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: true,
-            },
-            /* Bcs */
-            0xB0 => Instruction {
-                opcode: Opcode::Bcs,
-                mode: AddressingMode::Relative(signed_byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: true,
-            },
-            /* Beq */
-            0xF0 => Instruction {
-                opcode: Opcode::Beq,
-                mode: AddressingMode::Relative(signed_byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: true,
-            },
-            /* Bit */
-            0x24 => Instruction {
-                opcode: Opcode::Bit,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x2C => Instruction {
-                opcode: Opcode::Bit,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            /* Bmi */
-            0x30 => Instruction {
-                opcode: Opcode::Bmi,
-                mode: AddressingMode::Relative(signed_byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false, /* Is this right? */
-            },
-            /* Bne */
-            0xD0 => Instruction {
-                opcode: Opcode::Bne,
-                mode: AddressingMode::Relative(signed_byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: true,
-            },
-            /* Bpl */
-            0x10 => Instruction {
-                opcode: Opcode::Bpl,
-                mode: AddressingMode::Relative(signed_byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: true,
-            },
-            /* Brk */
-            0x00 => Instruction {
-                opcode: Opcode::Brk,
-                mode: AddressingMode::Implicit,
-                cycles: 0, // this generates an interrupt which adds the right number of cycles itself
-                page_cross_cost: false,
-            },
-            /* Bvc */
-            0x50 => Instruction {
-                opcode: Opcode::Bvc,
-                mode: AddressingMode::Relative(signed_byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: true,
-            },
-            /* Bvs */
-            0x70 => Instruction {
-                opcode: Opcode::Bvs,
-                mode: AddressingMode::Relative(signed_byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: true,
-            },
-            /* Clc */
-            0x18 => Instruction {
-                opcode: Opcode::Clc,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Cld */
-            0xD8 => Instruction {
-                opcode: Opcode::Cld,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Cli */
-            0x58 => Instruction {
-                opcode: Opcode::Cli,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Clv */
-            0xB8 => Instruction {
-                opcode: Opcode::Clv,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Cmp */
-            0xC9 => Instruction {
-                opcode: Opcode::Cmp,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xC5 => Instruction {
-                opcode: Opcode::Cmp,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0xD5 => Instruction {
-                opcode: Opcode::Cmp,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xCD => Instruction {
-                opcode: Opcode::Cmp,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xDD => Instruction {
-                opcode: Opcode::Cmp,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0xD9 => Instruction {
-                opcode: Opcode::Cmp,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0xC1 => Instruction {
-                opcode: Opcode::Cmp,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xD1 => Instruction {
-                opcode: Opcode::Cmp,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: true,
-            },
-            /* Cpx */
-            0xE0 => Instruction {
-                opcode: Opcode::Cpx,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xE4 => Instruction {
-                opcode: Opcode::Cpx,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0xEC => Instruction {
-                opcode: Opcode::Cpx,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            /* Cpy */
-            0xC0 => Instruction {
-                opcode: Opcode::Cpy,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xC4 => Instruction {
-                opcode: Opcode::Cpy,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0xCC => Instruction {
-                opcode: Opcode::Cpy,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            /* Dcp */
-            0xC3 => Instruction {
-                opcode: Opcode::Dcp,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0xC7 => Instruction {
-                opcode: Opcode::Dcp,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0xCF => Instruction {
-                opcode: Opcode::Dcp,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xD3 => Instruction {
-                opcode: Opcode::Dcp,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0xD7 => Instruction {
-                opcode: Opcode::Dcp,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xDB => Instruction {
-                opcode: Opcode::Dcp,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            0xDF => Instruction {
-                opcode: Opcode::Dcp,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Dec */
-            0xC6 => Instruction {
-                opcode: Opcode::Dec,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0xD6 => Instruction {
-                opcode: Opcode::Dec,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xCE => Instruction {
-                opcode: Opcode::Dec,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xDE => Instruction {
-                opcode: Opcode::Dec,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Dex */
-            0xCA => Instruction {
-                opcode: Opcode::Dex,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Dey */
-            0x88 => Instruction {
-                opcode: Opcode::Dey,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Eor */
-            0x49 => Instruction {
-                opcode: Opcode::Eor,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x45 => Instruction {
-                opcode: Opcode::Eor,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x55 => Instruction {
-                opcode: Opcode::Eor,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x4D => Instruction {
-                opcode: Opcode::Eor,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x5D => Instruction {
-                opcode: Opcode::Eor,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x59 => Instruction {
-                opcode: Opcode::Eor,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x41 => Instruction {
-                opcode: Opcode::Eor,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x51 => Instruction {
-                opcode: Opcode::Eor,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: true,
-            },
-            /* Inc */
-            0xE6 => Instruction {
-                opcode: Opcode::Inc,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0xF6 => Instruction {
-                opcode: Opcode::Inc,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xEE => Instruction {
-                opcode: Opcode::Inc,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xFE => Instruction {
-                opcode: Opcode::Inc,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Inx */
-            0xE8 => Instruction {
-                opcode: Opcode::Inx,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Iny */
-            0xC8 => Instruction {
-                opcode: Opcode::Iny,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Isc */
-            0xE3 => Instruction {
-                opcode: Opcode::Isc,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0xE7 => Instruction {
-                opcode: Opcode::Isc,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0xEF => Instruction {
-                opcode: Opcode::Isc,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xF3 => Instruction {
-                opcode: Opcode::Isc,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0xF7 => Instruction {
-                opcode: Opcode::Isc,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xFB => Instruction {
-                opcode: Opcode::Isc,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            0xFF => Instruction {
-                opcode: Opcode::Isc,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Jmp */
-            0x4C => Instruction {
-                opcode: Opcode::Jmp,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x6C => Instruction {
-                opcode: Opcode::Jmp,
-                mode: AddressingMode::Indirect(word_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            /* Jsr */
-            0x20 => Instruction {
-                opcode: Opcode::Jsr,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            /* Lax */
-            0xA3 => Instruction {
-                opcode: Opcode::Lax,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xA7 => Instruction {
-                opcode: Opcode::Lax,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0xAF => Instruction {
-                opcode: Opcode::Lax,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xB3 => Instruction {
-                opcode: Opcode::Lax,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: true,
-            },
-            0xB7 => Instruction {
-                opcode: Opcode::Lax,
-                mode: AddressingMode::ZeroPageY(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xBF => Instruction {
-                opcode: Opcode::Lax,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            /* Lda */
-            0xA9 => Instruction {
-                opcode: Opcode::Lda,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xA5 => Instruction {
-                opcode: Opcode::Lda,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0xB5 => Instruction {
-                opcode: Opcode::Lda,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xAD => Instruction {
-                opcode: Opcode::Lda,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xBD => Instruction {
-                opcode: Opcode::Lda,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0xB9 => Instruction {
-                opcode: Opcode::Lda,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0xA1 => Instruction {
-                opcode: Opcode::Lda,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xB1 => Instruction {
-                opcode: Opcode::Lda,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: true,
-            },
-            /* Ldx */
-            0xA2 => Instruction {
-                opcode: Opcode::Ldx,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xA6 => Instruction {
-                opcode: Opcode::Ldx,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0xB6 => Instruction {
-                opcode: Opcode::Ldx,
-                mode: AddressingMode::ZeroPageY(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xAE => Instruction {
-                opcode: Opcode::Ldx,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xBE => Instruction {
-                opcode: Opcode::Ldx,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            /* Ldy */
-            0xA0 => Instruction {
-                opcode: Opcode::Ldy,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xA4 => Instruction {
-                opcode: Opcode::Ldy,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0xB4 => Instruction {
-                opcode: Opcode::Ldy,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xAC => Instruction {
-                opcode: Opcode::Ldy,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xBC => Instruction {
-                opcode: Opcode::Ldy,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            /* Lsr */
-            0x4A => Instruction {
-                opcode: Opcode::Lsr,
-                mode: AddressingMode::Accumulator,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x46 => Instruction {
-                opcode: Opcode::Lsr,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x56 => Instruction {
-                opcode: Opcode::Lsr,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x4E => Instruction {
-                opcode: Opcode::Lsr,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x5E => Instruction {
-                opcode: Opcode::Lsr,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Nop */
-            0x04 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x0C => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x14 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x1A => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x1C => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x3A => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x34 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x3C => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x44 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x54 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x5A => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x5C => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x64 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x74 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x7A => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x7C => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x80 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x82 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x89 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xC2 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xD4 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xDA => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xDC => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0xE2 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0xEA => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xF4 => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xFA => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xFC => Instruction {
-                opcode: Opcode::Nop,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            /* Ora */
-            0x09 => Instruction {
-                opcode: Opcode::Ora,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x05 => Instruction {
-                opcode: Opcode::Ora,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x15 => Instruction {
-                opcode: Opcode::Ora,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x0D => Instruction {
-                opcode: Opcode::Ora,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x1D => Instruction {
-                opcode: Opcode::Ora,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x19 => Instruction {
-                opcode: Opcode::Ora,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0x01 => Instruction {
-                opcode: Opcode::Ora,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x11 => Instruction {
-                opcode: Opcode::Ora,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            /* Pha */
-            0x48 => Instruction {
-                opcode: Opcode::Pha,
-                mode: AddressingMode::Implicit,
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            /* Php */
-            0x08 => Instruction {
-                opcode: Opcode::Php,
-                mode: AddressingMode::Implicit,
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            /* Pla */
-            0x68 => Instruction {
-                opcode: Opcode::Pla,
-                mode: AddressingMode::Implicit,
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            /* Plp */
-            0x28 => Instruction {
-                opcode: Opcode::Plp,
-                mode: AddressingMode::Implicit,
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            /* Rla */
-            0x23 => Instruction {
-                opcode: Opcode::Rla,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0x27 => Instruction {
-                opcode: Opcode::Rla,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x2F => Instruction {
-                opcode: Opcode::Rla,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x33 => Instruction {
-                opcode: Opcode::Rla,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0x37 => Instruction {
-                opcode: Opcode::Rla,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x3B => Instruction {
-                opcode: Opcode::Rla,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            0x3F => Instruction {
-                opcode: Opcode::Rla,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Rol */
-            0x2A => Instruction {
-                opcode: Opcode::Rol,
-                mode: AddressingMode::Accumulator,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x26 => Instruction {
-                opcode: Opcode::Rol,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x36 => Instruction {
-                opcode: Opcode::Rol,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x2E => Instruction {
-                opcode: Opcode::Rol,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x3E => Instruction {
-                opcode: Opcode::Rol,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Ror */
-            0x6A => Instruction {
-                opcode: Opcode::Ror,
-                mode: AddressingMode::Accumulator,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0x66 => Instruction {
-                opcode: Opcode::Ror,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x76 => Instruction {
-                opcode: Opcode::Ror,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x6E => Instruction {
-                opcode: Opcode::Ror,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x7E => Instruction {
-                opcode: Opcode::Ror,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Rra */
-            0x63 => Instruction {
-                opcode: Opcode::Rra,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0x67 => Instruction {
-                opcode: Opcode::Rra,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x6F => Instruction {
-                opcode: Opcode::Rra,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x73 => Instruction {
-                opcode: Opcode::Rra,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0x77 => Instruction {
-                opcode: Opcode::Rra,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x7B => Instruction {
-                opcode: Opcode::Rra,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            0x7F => Instruction {
-                opcode: Opcode::Rra,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Rti */
-            0x40 => Instruction {
-                opcode: Opcode::Rti,
-                mode: AddressingMode::Implicit,
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            /* Rts */
-            0x60 => Instruction {
-                opcode: Opcode::Rts,
-                mode: AddressingMode::Implicit,
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            /* Sax */
-            0x83 => Instruction {
-                opcode: Opcode::Sax,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x87 => Instruction {
-                opcode: Opcode::Sax,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x8F => Instruction {
-                opcode: Opcode::Sax,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x97 => Instruction {
-                opcode: Opcode::Sax,
-                mode: AddressingMode::ZeroPageY(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            /* Sbc */
-            0xE9 => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xEB => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::Immediate(byte_after_opcode),
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            0xE5 => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0xF5 => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xED => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0xFD => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0xF9 => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: true,
-            },
-            0xE1 => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0xF1 => Instruction {
-                opcode: Opcode::Sbc,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            /* Sec */
-            0x38 => Instruction {
-                opcode: Opcode::Sec,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Sed */
-            0xF8 => Instruction {
-                opcode: Opcode::Sed,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Sei */
-            0x78 => Instruction {
-                opcode: Opcode::Sei,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Slo */
-            0x03 => Instruction {
-                opcode: Opcode::Slo,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0x07 => Instruction {
-                opcode: Opcode::Slo,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x0F => Instruction {
-                opcode: Opcode::Slo,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x13 => Instruction {
-                opcode: Opcode::Slo,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0x17 => Instruction {
-                opcode: Opcode::Slo,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x1B => Instruction {
-                opcode: Opcode::Slo,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            0x1F => Instruction {
-                opcode: Opcode::Slo,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Sre */
-            0x43 => Instruction {
-                opcode: Opcode::Sre,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0x47 => Instruction {
-                opcode: Opcode::Sre,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x4F => Instruction {
-                opcode: Opcode::Sre,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x53 => Instruction {
-                opcode: Opcode::Sre,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 8,
-                page_cross_cost: false,
-            },
-            0x57 => Instruction {
-                opcode: Opcode::Sre,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x5B => Instruction {
-                opcode: Opcode::Sre,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            0x5F => Instruction {
-                opcode: Opcode::Sre,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 7,
-                page_cross_cost: false,
-            },
-            /* Sta */
-            0x85 => Instruction {
-                opcode: Opcode::Sta,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x95 => Instruction {
-                opcode: Opcode::Sta,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x8D => Instruction {
-                opcode: Opcode::Sta,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x9D => Instruction {
-                opcode: Opcode::Sta,
-                mode: AddressingMode::AbsoluteX(word_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x99 => Instruction {
-                opcode: Opcode::Sta,
-                mode: AddressingMode::AbsoluteY(word_after_opcode),
-                cycles: 5,
-                page_cross_cost: false,
-            },
-            0x81 => Instruction {
-                opcode: Opcode::Sta,
-                mode: AddressingMode::IndirectX(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            0x91 => Instruction {
-                opcode: Opcode::Sta,
-                mode: AddressingMode::IndirectY(byte_after_opcode),
-                cycles: 6,
-                page_cross_cost: false,
-            },
-            /* Stx */
-            0x86 => Instruction {
-                opcode: Opcode::Stx,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x96 => Instruction {
-                opcode: Opcode::Stx,
-                mode: AddressingMode::ZeroPageY(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x8E => Instruction {
-                opcode: Opcode::Stx,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            /* Sty */
-            0x84 => Instruction {
-                opcode: Opcode::Sty,
-                mode: AddressingMode::ZeroPage(byte_after_opcode),
-                cycles: 3,
-                page_cross_cost: false,
-            },
-            0x94 => Instruction {
-                opcode: Opcode::Sty,
-                mode: AddressingMode::ZeroPageX(byte_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            0x8C => Instruction {
-                opcode: Opcode::Sty,
-                mode: AddressingMode::Absolute(word_after_opcode),
-                cycles: 4,
-                page_cross_cost: false,
-            },
-            /* Tax */
-            0xAA => Instruction {
-                opcode: Opcode::Tax,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Tay */
-            0xA8 => Instruction {
-                opcode: Opcode::Tay,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Tsx */
-            0xBA => Instruction {
-                opcode: Opcode::Tsx,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Txa */
-            0x8A => Instruction {
-                opcode: Opcode::Txa,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Txs */
-            0x9A => Instruction {
-                opcode: Opcode::Txs,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            /* Tya */
-            0x98 => Instruction {
-                opcode: Opcode::Tya,
-                mode: AddressingMode::Implicit,
-                cycles: 2,
-                page_cross_cost: false,
-            },
-            opcode => panic!("0x{:02X} not implemented!", opcode),
-        };
-
-        let instruction_length = 1 + match result.mode {
-            AddressingMode::ZeroPage(_) => 1,
-            AddressingMode::ZeroPageX(_) => 1,
-            AddressingMode::ZeroPageY(_) => 1,
-            AddressingMode::IndirectX(_) => 1,
-            AddressingMode::IndirectY(_) => 1,
-            AddressingMode::Immediate(_) => 1,
-            AddressingMode::Relative(_) => 1,
-            AddressingMode::Absolute(_) => 2,
-            AddressingMode::AbsoluteX(_) => 2,
-            AddressingMode::AbsoluteY(_) => 2,
-            AddressingMode::Indirect(_) => 2,
-            AddressingMode::Implicit => 0,
-            AddressingMode::Accumulator => 0,
-        };
+        let result = Instruction::decode(opcode, byte_after_opcode, word_after_opcode)
+            .unwrap_or_else(|| panic!("0x{:02X} not implemented!", opcode));
 
-        self.pc += instruction_length;
+        self.pc += result.length();
         result
     }
 
@@ -1775,82 +417,88 @@ impl Cpu {
             Opcode::Tya => self.tya(),
         }
     }
-
-    fn read_with_addressing_mode(&mut self, mode: AddressingMode) -> u8 {
-        match mode {
-            AddressingMode::ZeroPage(val) => self.bus.get_byte_at(u16::from(val)),
-            AddressingMode::ZeroPageX(val) => {
-                self.bus.get_byte_at(u16::from(val.wrapping_add(self.x)))
-            }
-            AddressingMode::ZeroPageY(val) => {
-                self.bus.get_byte_at(u16::from(val.wrapping_add(self.y)))
-            }
-            AddressingMode::Absolute(addr) => self.bus.get_byte_at(addr),
-            AddressingMode::AbsoluteX(val) => self.bus.get_byte_at(val + u16::from(self.x)),
-            AddressingMode::AbsoluteY(val) => {
-                self.bus.get_byte_at(val.wrapping_add(u16::from(self.y)))
-            }
-            AddressingMode::IndirectX(val) => {
-                let low_byte = self.bus.get_byte_at((val.wrapping_add(self.x)) as u16);
-                let high_byte = self
-                    .bus
-                    .get_byte_at((val.wrapping_add(self.x).wrapping_add(1)) as u16);
-                let indirect_addr = ((high_byte as u16) << 8) | (low_byte as u16);
-                self.bus.get_byte_at(indirect_addr)
-            }
-            AddressingMode::IndirectY(val) => {
-                let low_byte = self.bus.get_byte_at(val as u16);
-                let high_byte = self.bus.get_byte_at((val.wrapping_add(1)) as u16);
-                let indirect_addr = ((high_byte as u16) << 8) | (low_byte as u16);
-                self.bus
-                    .get_byte_at(indirect_addr.wrapping_add(u16::from(self.y)))
-            }
-            AddressingMode::Immediate(val) => val,
-            AddressingMode::Accumulator => self.accumulator,
-            _ => unreachable!(
-                "Attempted to read from address value of {:?} illegally",
-                mode
-            ),
-        }
-    }
-
-    fn write_with_addressing_mode(&mut self, mode: AddressingMode, assigned_val: u8) {
-        match mode {
-            AddressingMode::ZeroPage(val) => self.bus.set_byte_at(u16::from(val), assigned_val),
-            AddressingMode::ZeroPageX(val) => self
-                .bus
-                .set_byte_at(u16::from(val.wrapping_add(self.x)), assigned_val),
-            AddressingMode::ZeroPageY(val) => self
-                .bus
-                .set_byte_at(u16::from(val.wrapping_add(self.y)), assigned_val),
-            AddressingMode::Absolute(addr) => self.bus.set_byte_at(addr, assigned_val),
-            AddressingMode::AbsoluteX(val) => {
-                self.bus.set_byte_at(val + u16::from(self.x), assigned_val)
-            }
-            AddressingMode::AbsoluteY(val) => {
-                self.bus.set_byte_at(val + u16::from(self.y), assigned_val)
-            }
+
+    // Resolves the memory address an addressing mode refers to. Indirect modes read their pointer
+    // from the bus, so this should only be called once per instruction.
+    fn get_effective_address(&mut self, mode: AddressingMode) -> u16 {
+        match mode {
+            AddressingMode::ZeroPage(val) => u16::from(val),
+            AddressingMode::ZeroPageX(val) => u16::from(val.wrapping_add(self.x)),
+            AddressingMode::ZeroPageY(val) => u16::from(val.wrapping_add(self.y)),
+            AddressingMode::Absolute(addr) => addr,
+            AddressingMode::AbsoluteX(val) => val.wrapping_add(u16::from(self.x)),
+            AddressingMode::AbsoluteY(val) => val.wrapping_add(u16::from(self.y)),
             AddressingMode::IndirectX(val) => {
                 let low_byte = self.bus.get_byte_at((val.wrapping_add(self.x)) as u16);
                 let high_byte = self
                     .bus
                     .get_byte_at((val.wrapping_add(self.x).wrapping_add(1)) as u16);
-                let indirect_addr = ((high_byte as u16) << 8) | (low_byte as u16);
-                self.bus.set_byte_at(indirect_addr, assigned_val);
+                ((high_byte as u16) << 8) | (low_byte as u16)
             }
             AddressingMode::IndirectY(val) => {
                 let low_byte = self.bus.get_byte_at(val as u16);
                 let high_byte = self.bus.get_byte_at((val.wrapping_add(1)) as u16);
-                let indirect_addr =
-                    (((high_byte as u16) << 8) | (low_byte as u16)).wrapping_add(self.y as u16);
-                self.bus.set_byte_at(indirect_addr, assigned_val);
+                (((high_byte as u16) << 8) | (low_byte as u16)).wrapping_add(u16::from(self.y))
+            }
+            _ => unreachable!("{:?} does not reference memory", mode),
+        }
+    }
+
+    fn read_with_addressing_mode(&mut self, mode: AddressingMode) -> u8 {
+        match mode {
+            AddressingMode::Immediate(val) => val,
+            AddressingMode::Accumulator => self.accumulator,
+            AddressingMode::Implicit
+            | AddressingMode::Relative(_)
+            | AddressingMode::Indirect(_) => unreachable!(
+                "Attempted to read from address value of {:?} illegally",
+                mode
+            ),
+            _ => {
+                let addr = self.get_effective_address(mode);
+                self.bus.get_byte_at(addr)
             }
+        }
+    }
+
+    fn write_with_addressing_mode(&mut self, mode: AddressingMode, assigned_val: u8) {
+        match mode {
             AddressingMode::Accumulator => self.accumulator = assigned_val,
-            _ => panic!(
+            AddressingMode::Implicit
+            | AddressingMode::Immediate(_)
+            | AddressingMode::Relative(_)
+            | AddressingMode::Indirect(_) => panic!(
                 "Attempted to write to address value of {:?} illegally",
                 mode
             ),
+            _ => {
+                let addr = self.get_effective_address(mode);
+                self.bus.set_byte_at(addr, assigned_val);
+            }
+        }
+    }
+
+    // Read-modify-write instructions read their target once, write the unmodified value back while
+    // the ALU works on it, and then write the modified value. Memory mapped registers see both
+    // writes, just like on hardware. Returns the modified value.
+    fn read_modify_write(
+        &mut self,
+        mode: AddressingMode,
+        operation: fn(&mut Self, u8) -> u8,
+    ) -> u8 {
+        if mode == AddressingMode::Accumulator {
+            let result = operation(self, self.accumulator);
+            self.accumulator = result;
+            return result;
         }
+
+        let addr = self.get_effective_address(mode);
+        let old_val = self.bus.get_byte_at(addr);
+        self.bus.set_byte_at(addr, old_val); // dummy write
+        let result = operation(self, old_val);
+        self.bus.set_byte_at(addr, result);
+
+        result
     }
 
     fn read_crosses_page_boundry(&mut self, mode: AddressingMode) -> bool {
@@ -1864,7 +512,7 @@ impl Cpu {
                 overflow
             }
             AddressingMode::IndirectY(val) => {
-                let (_, overflow) = self.bus.get_byte_at(val as u16).overflowing_add(self.y);
+                let (_, overflow) = self.bus.peek_byte_at(val as u16).overflowing_add(self.y);
                 overflow
             }
             _ => false,
@@ -1873,6 +521,15 @@ impl Cpu {
 
     fn adc(&mut self, mode: AddressingMode) {
         let to_be_added = self.read_with_addressing_mode(mode);
+        self.add_with_carry(to_be_added);
+    }
+
+    fn add_with_carry(&mut self, to_be_added: u8) {
+        if self.decimal && self.variant == Variant::Nmos6502 {
+            self.add_with_carry_decimal(to_be_added);
+            return;
+        }
+
         let old_accumulator = self.accumulator;
 
         let (first_add, first_carry) = old_accumulator.overflowing_add(to_be_added);
@@ -1885,6 +542,39 @@ impl Cpu {
         self.carry = first_carry | second_carry;
     }
 
+    // NMOS decimal mode addition. The zero flag comes from the binary sum, while the sign and
+    // overflow flags come from the intermediate result after the low nibble has been adjusted but
+    // before the high nibble has been adjusted. This matches real NMOS parts, including for invalid
+    // BCD inputs.
+    fn add_with_carry_decimal(&mut self, to_be_added: u8) {
+        let old_accumulator = self.accumulator;
+        let carry_in = u16::from(self.carry);
+
+        let mut low = u16::from(old_accumulator & 0x0F) + u16::from(to_be_added & 0x0F) + carry_in;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+
+        let mut result = u16::from(old_accumulator & 0xF0) + u16::from(to_be_added & 0xF0) + low;
+
+        self.zero = old_accumulator
+            .wrapping_add(to_be_added)
+            .wrapping_add(self.carry as u8)
+            == 0;
+        self.sign = (result & 0x80) != 0;
+        self.overflow = ((u16::from(old_accumulator) ^ result)
+            & !u16::from(old_accumulator ^ to_be_added)
+            & 0x80)
+            != 0;
+
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+
+        self.carry = result >= 0x100;
+        self.accumulator = (result & 0xFF) as u8;
+    }
+
     fn and(&mut self, mode: AddressingMode) {
         let to_be_anded = self.read_with_addressing_mode(mode);
         let result = to_be_anded & self.accumulator;
@@ -1895,13 +585,16 @@ impl Cpu {
     }
 
     fn asl(&mut self, mode: AddressingMode) {
-        let to_be_asled = self.read_with_addressing_mode(mode);
+        self.read_modify_write(mode, Self::shift_left);
+    }
+
+    fn shift_left(&mut self, to_be_asled: u8) -> u8 {
         let result = to_be_asled << 1;
 
-        self.write_with_addressing_mode(mode, result);
         self.sign = (result as i8) < 0;
         self.zero = result == 0;
         self.carry = (to_be_asled & (1 << 7)) != 0;
+        result
     }
 
     fn branch(&mut self, condition: bool, offset: i8) {
@@ -1999,364 +692,1877 @@ impl Cpu {
         self.interrupt = false;
     }
 
-    fn clv(&mut self) {
-        self.overflow = false;
+    fn clv(&mut self) {
+        self.overflow = false;
+    }
+
+    fn cmp(&mut self, mode: AddressingMode) {
+        let to_compare = self.read_with_addressing_mode(mode);
+        self.compare(self.accumulator, to_compare);
+    }
+
+    fn compare(&mut self, register: u8, to_compare: u8) {
+        self.sign = (register.wrapping_sub(to_compare) as i8) < 0;
+        self.zero = register == to_compare;
+        self.carry = register >= to_compare;
+    }
+
+    fn cpx(&mut self, mode: AddressingMode) {
+        let to_compare = self.read_with_addressing_mode(mode);
+        self.compare(self.x, to_compare);
+    }
+
+    fn cpy(&mut self, mode: AddressingMode) {
+        let to_compare = self.read_with_addressing_mode(mode);
+        self.compare(self.y, to_compare);
+    }
+
+    // Equivalent to dec then cmp, but with a single read-modify-write of memory
+    fn dcp(&mut self, mode: AddressingMode) {
+        let result = self.read_modify_write(mode, Self::decrement);
+        self.compare(self.accumulator, result);
+    }
+
+    fn dec(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, Self::decrement);
+    }
+
+    fn decrement(&mut self, old_val: u8) -> u8 {
+        let result = old_val.wrapping_sub(1);
+
+        self.sign = (result as i8) < 0;
+        self.zero = result == 0;
+        result
+    }
+
+    fn dex(&mut self) {
+        self.x = self.x.wrapping_sub(1);
+
+        self.sign = (self.x as i8) < 0;
+        self.zero = self.x == 0;
+    }
+
+    fn dey(&mut self) {
+        self.y = self.y.wrapping_sub(1);
+
+        self.sign = (self.y as i8) < 0;
+        self.zero = self.y == 0;
+    }
+
+    fn eor(&mut self, mode: AddressingMode) {
+        self.accumulator ^= self.read_with_addressing_mode(mode);
+
+        self.sign = (self.accumulator as i8) < 0;
+        self.zero = self.accumulator == 0;
+    }
+
+    fn inc(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, Self::increment);
+    }
+
+    fn increment(&mut self, old_val: u8) -> u8 {
+        let result = old_val.wrapping_add(1);
+
+        self.sign = (result as i8) < 0;
+        self.zero = result == 0;
+        result
+    }
+
+    fn inx(&mut self) {
+        self.x = self.x.wrapping_add(1);
+
+        self.sign = (self.x as i8) < 0;
+        self.zero = self.x == 0;
+    }
+
+    fn iny(&mut self) {
+        self.y = self.y.wrapping_add(1);
+
+        self.sign = (self.y as i8) < 0;
+        self.zero = self.y == 0;
+    }
+
+    // Equivalent to inc then sbc, but with a single read-modify-write of memory
+    fn isc(&mut self, mode: AddressingMode) {
+        let result = self.read_modify_write(mode, Self::increment);
+        self.subtract_with_borrow(result);
+    }
+
+    fn jmp(&mut self, mode: AddressingMode) {
+        match mode {
+            AddressingMode::Absolute(addr) => {
+                self.pc = addr;
+            }
+            // jmp (xxFF) will read from xxFF and xx00 instead of crossing page boundary.
+            AddressingMode::Indirect(addr) => {
+                if addr & 0x00FF == 0xFF {
+                    let low_byte = self.bus.get_byte_at(addr);
+                    let high_byte = self.bus.get_byte_at(addr & 0xFF00);
+                    self.pc = ((high_byte as u16) << 8) | (low_byte as u16);
+                } else {
+                    self.pc = self.bus.get_word_at(addr);
+                }
+            }
+            _ => panic!("Cannot jmp using {:?}", mode),
+        }
+    }
+
+    fn jsr(&mut self, mode: AddressingMode) {
+        match mode {
+            AddressingMode::Absolute(addr) => {
+                self.push_word(self.pc - 1);
+                self.pc = addr;
+            }
+            _ => panic!("Cannot jsr using {:?}", mode),
+        }
+    }
+
+    // Shortcut for lda then tax
+    fn lax(&mut self, mode: AddressingMode) {
+        // lda
+        self.accumulator = self.read_with_addressing_mode(mode);
+
+        self.sign = (self.accumulator as i8) < 0;
+        self.zero = self.accumulator == 0;
+
+        // tax
+        self.x = self.accumulator;
+    }
+
+    fn lda(&mut self, mode: AddressingMode) {
+        self.accumulator = self.read_with_addressing_mode(mode);
+
+        self.sign = (self.accumulator as i8) < 0;
+        self.zero = self.accumulator == 0;
+    }
+
+    fn ldx(&mut self, mode: AddressingMode) {
+        self.x = self.read_with_addressing_mode(mode);
+
+        self.sign = (self.x as i8) < 0;
+        self.zero = self.x == 0;
+    }
+
+    fn ldy(&mut self, mode: AddressingMode) {
+        self.y = self.read_with_addressing_mode(mode);
+
+        self.sign = (self.y as i8) < 0;
+        self.zero = self.y == 0;
+    }
+
+    fn lsr(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, Self::shift_right);
+    }
+
+    fn shift_right(&mut self, to_be_lsred: u8) -> u8 {
+        let result = to_be_lsred >> 1;
+
+        self.sign = false;
+        self.zero = result == 0;
+        self.carry = (to_be_lsred & (1 << 0)) != 0;
+        result
+    }
+
+    fn nop(&mut self, mode: AddressingMode) {
+        // If mode isn't implicit, actually do the read. Some nop instructions may use addressing
+        // modes with "oops" cycles, so we have to actually do the read no matter what to account
+        // for this.
+        if mode != AddressingMode::Implicit {
+            self.read_with_addressing_mode(mode);
+        }
+    }
+
+    fn ora(&mut self, mode: AddressingMode) {
+        self.accumulator |= self.read_with_addressing_mode(mode);
+
+        self.sign = (self.accumulator as i8) < 0;
+        self.zero = self.accumulator == 0;
+    }
+
+    fn pha(&mut self) {
+        self.push_byte(self.accumulator);
+    }
+
+    fn php(&mut self) {
+        self.push_byte(self.get_status(true));
+    }
+
+    fn pla(&mut self) {
+        self.accumulator = self.pop_byte();
+
+        self.zero = self.accumulator == 0;
+        self.sign = (self.accumulator as i8) < 0;
+    }
+
+    fn plp(&mut self) {
+        let processor_flags = self.pop_byte();
+        self.set_status(processor_flags);
+    }
+
+    // Equivalent to rol then and, but with a single read-modify-write of memory
+    fn rla(&mut self, mode: AddressingMode) {
+        let result = self.read_modify_write(mode, Self::rotate_left);
+        self.accumulator &= result;
+
+        self.sign = (self.accumulator as i8) < 0;
+        self.zero = self.accumulator == 0;
+    }
+
+    fn rol(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, Self::rotate_left);
+    }
+
+    fn rotate_left(&mut self, to_be_roled: u8) -> u8 {
+        let new_val = (to_be_roled << 1) | (self.carry as u8);
+
+        self.sign = (new_val as i8) < 0;
+        self.zero = new_val == 0;
+        self.carry = (to_be_roled & (1 << 7)) != 0;
+        new_val
+    }
+
+    fn ror(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, Self::rotate_right);
+    }
+
+    fn rotate_right(&mut self, to_be_rored: u8) -> u8 {
+        let new_val = (to_be_rored >> 1) | ((self.carry as u8) << 7);
+
+        self.sign = (new_val as i8) < 0;
+        self.zero = new_val == 0;
+        self.carry = (to_be_rored & (1 << 0)) != 0;
+        new_val
+    }
+
+    // Equivalent to ror then adc, but with a single read-modify-write of memory
+    fn rra(&mut self, mode: AddressingMode) {
+        let result = self.read_modify_write(mode, Self::rotate_right);
+        self.add_with_carry(result);
     }
 
-    fn cmp(&mut self, mode: AddressingMode) {
-        let to_compare = self.read_with_addressing_mode(mode);
+    fn rti(&mut self) {
+        // Pull processor flags from stack
+        self.plp();
 
-        self.sign = (self.accumulator.wrapping_sub(to_compare) as i8) < 0;
-        self.zero = self.accumulator == to_compare;
-        self.carry = self.accumulator >= to_compare;
+        self.pc = self.pop_word();
     }
 
-    fn cpx(&mut self, mode: AddressingMode) {
-        let to_compare = self.read_with_addressing_mode(mode);
-
-        self.sign = (self.x.wrapping_sub(to_compare) as i8) < 0;
-        self.zero = self.x == to_compare;
-        self.carry = self.x >= to_compare;
+    fn rts(&mut self) {
+        self.pc = self.pop_word() + 1;
     }
 
-    fn cpy(&mut self, mode: AddressingMode) {
-        let to_compare = self.read_with_addressing_mode(mode);
+    fn sax(&mut self, mode: AddressingMode) {
+        let result = self.accumulator & self.x;
 
-        self.sign = (self.y.wrapping_sub(to_compare) as i8) < 0;
-        self.zero = self.y == to_compare;
-        self.carry = self.y >= to_compare;
+        self.write_with_addressing_mode(mode, result);
     }
 
-    // Equivalent to dec then cmp
-    fn dcp(&mut self, mode: AddressingMode) {
-        self.dec(mode);
-        self.cmp(mode);
+    fn sbc(&mut self, mode: AddressingMode) {
+        let to_be_subtracted = self.read_with_addressing_mode(mode);
+        self.subtract_with_borrow(to_be_subtracted);
     }
 
-    fn dec(&mut self, mode: AddressingMode) {
-        let old_val = self.read_with_addressing_mode(mode);
-        let result = old_val.wrapping_sub(1);
-        self.write_with_addressing_mode(mode, result);
+    fn subtract_with_borrow(&mut self, to_be_subtracted: u8) {
+        let old_accumulator = self.accumulator;
+        let borrow = !self.carry;
+
+        // We can take advantage of:
+        // A - M - (1 - C)
+        // A + !M + 1 - (1 - C)
+        // A + !M + 1 + C - 1
+        // A + !M + C -> same as adc
+        //
+        // On NMOS parts, all flags in decimal mode are the same as they would be in binary mode,
+        // only the value stored in the accumulator differs.
+        let to_be_added = !to_be_subtracted;
+
+        let (first_add, first_carry) = old_accumulator.overflowing_add(to_be_added);
+        let (result, second_carry) = first_add.overflowing_add(u8::from(self.carry));
 
+        self.accumulator = result;
         self.sign = (result as i8) < 0;
         self.zero = result == 0;
-    }
+        self.overflow = ((to_be_added ^ result) & (old_accumulator ^ result) & 0x80) != 0;
+        self.carry = first_carry | second_carry;
 
-    fn dex(&mut self) {
-        self.x = self.x.wrapping_sub(1);
+        if self.decimal && self.variant == Variant::Nmos6502 {
+            let mut low = i16::from(old_accumulator & 0x0F)
+                - i16::from(to_be_subtracted & 0x0F)
+                - i16::from(borrow);
+            if low < 0 {
+                low = ((low - 0x06) & 0x0F) - 0x10;
+            }
 
-        self.sign = (self.x as i8) < 0;
-        self.zero = self.x == 0;
+            let mut result =
+                i16::from(old_accumulator & 0xF0) - i16::from(to_be_subtracted & 0xF0) + low;
+            if result < 0 {
+                result -= 0x60;
+            }
+
+            self.accumulator = (result & 0xFF) as u8;
+        }
     }
 
-    fn dey(&mut self) {
-        self.y = self.y.wrapping_sub(1);
+    fn sec(&mut self) {
+        self.carry = true;
+    }
 
-        self.sign = (self.y as i8) < 0;
-        self.zero = self.y == 0;
+    fn sed(&mut self) {
+        self.decimal = true;
     }
 
-    fn eor(&mut self, mode: AddressingMode) {
-        self.accumulator ^= self.read_with_addressing_mode(mode);
+    fn sei(&mut self) {
+        self.interrupt = true;
+    }
+
+    // Equivalent to asl then ora, but with a single read-modify-write of memory
+    fn slo(&mut self, mode: AddressingMode) {
+        let result = self.read_modify_write(mode, Self::shift_left);
+        self.accumulator |= result;
 
         self.sign = (self.accumulator as i8) < 0;
         self.zero = self.accumulator == 0;
     }
 
-    fn inc(&mut self, mode: AddressingMode) {
-        let old_val = self.read_with_addressing_mode(mode);
-        let result = old_val.wrapping_add(1);
-        self.write_with_addressing_mode(mode, result);
+    // Equivalent to lsr then eor, but with a single read-modify-write of memory
+    fn sre(&mut self, mode: AddressingMode) {
+        let result = self.read_modify_write(mode, Self::shift_right);
+        self.accumulator ^= result;
 
-        self.sign = (result as i8) < 0;
-        self.zero = result == 0;
+        self.sign = (self.accumulator as i8) < 0;
+        self.zero = self.accumulator == 0;
     }
 
-    fn inx(&mut self) {
-        self.x = self.x.wrapping_add(1);
-
-        self.sign = (self.x as i8) < 0;
-        self.zero = self.x == 0;
+    fn sta(&mut self, mode: AddressingMode) {
+        self.write_with_addressing_mode(mode, self.accumulator);
     }
 
-    fn iny(&mut self) {
-        self.y = self.y.wrapping_add(1);
-
-        self.sign = (self.y as i8) < 0;
-        self.zero = self.y == 0;
+    fn stx(&mut self, mode: AddressingMode) {
+        self.write_with_addressing_mode(mode, self.x);
     }
 
-    // Equivalent to inc then sbc
-    fn isc(&mut self, mode: AddressingMode) {
-        self.inc(mode);
-        self.sbc(mode);
+    fn sty(&mut self, mode: AddressingMode) {
+        self.write_with_addressing_mode(mode, self.y);
     }
 
-    fn jmp(&mut self, mode: AddressingMode) {
-        match mode {
-            AddressingMode::Absolute(addr) => {
-                self.pc = addr;
-            }
-            // jmp (xxFF) will read from xxFF and xx00 instead of crossing page boundary.
-            AddressingMode::Indirect(addr) => {
-                if addr & 0x00FF == 0xFF {
-                    let low_byte = self.bus.get_byte_at(addr);
-                    let high_byte = self.bus.get_byte_at(addr & 0xFF00);
-                    self.pc = ((high_byte as u16) << 8) | (low_byte as u16);
-                } else {
-                    self.pc = self.bus.get_word_at(addr);
-                }
-            }
-            _ => panic!("Cannot jmp using {:?}", mode),
-        }
-    }
+    fn tax(&mut self) {
+        self.x = self.accumulator;
 
-    fn jsr(&mut self, mode: AddressingMode) {
-        match mode {
-            AddressingMode::Absolute(addr) => {
-                self.push_word(self.pc - 1);
-                self.pc = addr;
-            }
-            _ => panic!("Cannot jsr using {:?}", mode),
-        }
+        self.zero = self.x == 0;
+        self.sign = (self.x as i8) < 0;
     }
 
-    // Shortcut for lda then tax
-    fn lax(&mut self, mode: AddressingMode) {
-        // lda
-        self.accumulator = self.read_with_addressing_mode(mode);
+    fn tay(&mut self) {
+        self.y = self.accumulator;
 
-        self.sign = (self.accumulator as i8) < 0;
-        self.zero = self.accumulator == 0;
+        self.zero = self.y == 0;
+        self.sign = (self.y as i8) < 0;
+    }
 
-        // tax
-        self.x = self.accumulator;
+    fn tsx(&mut self) {
+        self.x = self.sp;
+
+        self.zero = self.x == 0;
+        self.sign = (self.x as i8) < 0;
     }
 
-    fn lda(&mut self, mode: AddressingMode) {
-        self.accumulator = self.read_with_addressing_mode(mode);
+    fn txa(&mut self) {
+        self.accumulator = self.x;
 
-        self.sign = (self.accumulator as i8) < 0;
         self.zero = self.accumulator == 0;
+        self.sign = (self.accumulator as i8) < 0;
     }
 
-    fn ldx(&mut self, mode: AddressingMode) {
-        self.x = self.read_with_addressing_mode(mode);
-
-        self.sign = (self.x as i8) < 0;
-        self.zero = self.x == 0;
+    fn txs(&mut self) {
+        self.sp = self.x;
     }
 
-    fn ldy(&mut self, mode: AddressingMode) {
-        self.y = self.read_with_addressing_mode(mode);
+    fn tya(&mut self) {
+        self.accumulator = self.y;
 
-        self.sign = (self.y as i8) < 0;
-        self.zero = self.y == 0;
+        self.zero = self.accumulator == 0;
+        self.sign = (self.accumulator as i8) < 0;
     }
+}
 
-    fn lsr(&mut self, mode: AddressingMode) {
-        let to_be_lsred = self.read_with_addressing_mode(mode);
-        let result = to_be_lsred >> 1;
+impl Instruction {
+    // Decodes an instruction from its opcode and the (up to) two bytes following it, without
+    // touching any CPU or bus state. Returns None for opcodes that aren't implemented.
+    pub fn decode(opcode: u8, byte_after_opcode: u8, word_after_opcode: u16) -> Option<Self> {
+        let signed_byte_after_opcode: i8 = byte_after_opcode as i8;
+        let result = match opcode {
+            /* Add */
+            0x69 => Instruction {
+                opcode: Opcode::Add,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x65 => Instruction {
+                opcode: Opcode::Add,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x75 => Instruction {
+                opcode: Opcode::Add,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x6D => Instruction {
+                opcode: Opcode::Add,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x7D => Instruction {
+                opcode: Opcode::Add,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x79 => Instruction {
+                opcode: Opcode::Add,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x61 => Instruction {
+                opcode: Opcode::Add,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x71 => Instruction {
+                opcode: Opcode::Add,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: true,
+            },
+            /* And */
+            0x29 => Instruction {
+                opcode: Opcode::And,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x25 => Instruction {
+                opcode: Opcode::And,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x35 => Instruction {
+                opcode: Opcode::And,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x2D => Instruction {
+                opcode: Opcode::And,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x3D => Instruction {
+                opcode: Opcode::And,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x39 => Instruction {
+                opcode: Opcode::And,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x21 => Instruction {
+                opcode: Opcode::And,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x31 => Instruction {
+                opcode: Opcode::And,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            /* Asl */
+            0x0A => Instruction {
+                opcode: Opcode::Asl,
+                mode: AddressingMode::Accumulator,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x06 => Instruction {
+                opcode: Opcode::Asl,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x16 => Instruction {
+                opcode: Opcode::Asl,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x0E => Instruction {
+                opcode: Opcode::Asl,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x1E => Instruction {
+                opcode: Opcode::Asl,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Bcc */
+            0x90 => Instruction {
+                opcode: Opcode::Bcc,
+                mode: AddressingMode::Relative(signed_byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: true,
+            },
+            /* Bcs */
+            0xB0 => Instruction {
+                opcode: Opcode::Bcs,
+                mode: AddressingMode::Relative(signed_byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: true,
+            },
+            /* Beq */
+            0xF0 => Instruction {
+                opcode: Opcode::Beq,
+                mode: AddressingMode::Relative(signed_byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: true,
+            },
+            /* Bit */
+            0x24 => Instruction {
+                opcode: Opcode::Bit,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x2C => Instruction {
+                opcode: Opcode::Bit,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            /* Bmi */
+            0x30 => Instruction {
+                opcode: Opcode::Bmi,
+                mode: AddressingMode::Relative(signed_byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false, /* Is this right? */
+            },
+            /* Bne */
+            0xD0 => Instruction {
+                opcode: Opcode::Bne,
+                mode: AddressingMode::Relative(signed_byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: true,
+            },
+            /* Bpl */
+            0x10 => Instruction {
+                opcode: Opcode::Bpl,
+                mode: AddressingMode::Relative(signed_byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: true,
+            },
+            /* Brk */
+            0x00 => Instruction {
+                opcode: Opcode::Brk,
+                mode: AddressingMode::Implicit,
+                cycles: 0, // this generates an interrupt which adds the right number of cycles itself
+                page_cross_cost: false,
+            },
+            /* Bvc */
+            0x50 => Instruction {
+                opcode: Opcode::Bvc,
+                mode: AddressingMode::Relative(signed_byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: true,
+            },
+            /* Bvs */
+            0x70 => Instruction {
+                opcode: Opcode::Bvs,
+                mode: AddressingMode::Relative(signed_byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: true,
+            },
+            /* Clc */
+            0x18 => Instruction {
+                opcode: Opcode::Clc,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Cld */
+            0xD8 => Instruction {
+                opcode: Opcode::Cld,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Cli */
+            0x58 => Instruction {
+                opcode: Opcode::Cli,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Clv */
+            0xB8 => Instruction {
+                opcode: Opcode::Clv,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Cmp */
+            0xC9 => Instruction {
+                opcode: Opcode::Cmp,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xC5 => Instruction {
+                opcode: Opcode::Cmp,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0xD5 => Instruction {
+                opcode: Opcode::Cmp,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xCD => Instruction {
+                opcode: Opcode::Cmp,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xDD => Instruction {
+                opcode: Opcode::Cmp,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0xD9 => Instruction {
+                opcode: Opcode::Cmp,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0xC1 => Instruction {
+                opcode: Opcode::Cmp,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xD1 => Instruction {
+                opcode: Opcode::Cmp,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: true,
+            },
+            /* Cpx */
+            0xE0 => Instruction {
+                opcode: Opcode::Cpx,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xE4 => Instruction {
+                opcode: Opcode::Cpx,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0xEC => Instruction {
+                opcode: Opcode::Cpx,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            /* Cpy */
+            0xC0 => Instruction {
+                opcode: Opcode::Cpy,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xC4 => Instruction {
+                opcode: Opcode::Cpy,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0xCC => Instruction {
+                opcode: Opcode::Cpy,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            /* Dcp */
+            0xC3 => Instruction {
+                opcode: Opcode::Dcp,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0xC7 => Instruction {
+                opcode: Opcode::Dcp,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0xCF => Instruction {
+                opcode: Opcode::Dcp,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xD3 => Instruction {
+                opcode: Opcode::Dcp,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0xD7 => Instruction {
+                opcode: Opcode::Dcp,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xDB => Instruction {
+                opcode: Opcode::Dcp,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            0xDF => Instruction {
+                opcode: Opcode::Dcp,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Dec */
+            0xC6 => Instruction {
+                opcode: Opcode::Dec,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0xD6 => Instruction {
+                opcode: Opcode::Dec,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xCE => Instruction {
+                opcode: Opcode::Dec,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xDE => Instruction {
+                opcode: Opcode::Dec,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Dex */
+            0xCA => Instruction {
+                opcode: Opcode::Dex,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Dey */
+            0x88 => Instruction {
+                opcode: Opcode::Dey,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Eor */
+            0x49 => Instruction {
+                opcode: Opcode::Eor,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x45 => Instruction {
+                opcode: Opcode::Eor,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x55 => Instruction {
+                opcode: Opcode::Eor,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x4D => Instruction {
+                opcode: Opcode::Eor,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x5D => Instruction {
+                opcode: Opcode::Eor,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x59 => Instruction {
+                opcode: Opcode::Eor,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x41 => Instruction {
+                opcode: Opcode::Eor,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x51 => Instruction {
+                opcode: Opcode::Eor,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: true,
+            },
+            /* Inc */
+            0xE6 => Instruction {
+                opcode: Opcode::Inc,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0xF6 => Instruction {
+                opcode: Opcode::Inc,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xEE => Instruction {
+                opcode: Opcode::Inc,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xFE => Instruction {
+                opcode: Opcode::Inc,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Inx */
+            0xE8 => Instruction {
+                opcode: Opcode::Inx,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Iny */
+            0xC8 => Instruction {
+                opcode: Opcode::Iny,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Isc */
+            0xE3 => Instruction {
+                opcode: Opcode::Isc,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0xE7 => Instruction {
+                opcode: Opcode::Isc,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0xEF => Instruction {
+                opcode: Opcode::Isc,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xF3 => Instruction {
+                opcode: Opcode::Isc,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0xF7 => Instruction {
+                opcode: Opcode::Isc,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xFB => Instruction {
+                opcode: Opcode::Isc,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            0xFF => Instruction {
+                opcode: Opcode::Isc,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Jmp */
+            0x4C => Instruction {
+                opcode: Opcode::Jmp,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x6C => Instruction {
+                opcode: Opcode::Jmp,
+                mode: AddressingMode::Indirect(word_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            /* Jsr */
+            0x20 => Instruction {
+                opcode: Opcode::Jsr,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            /* Lax */
+            0xA3 => Instruction {
+                opcode: Opcode::Lax,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xA7 => Instruction {
+                opcode: Opcode::Lax,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0xAF => Instruction {
+                opcode: Opcode::Lax,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xB3 => Instruction {
+                opcode: Opcode::Lax,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: true,
+            },
+            0xB7 => Instruction {
+                opcode: Opcode::Lax,
+                mode: AddressingMode::ZeroPageY(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xBF => Instruction {
+                opcode: Opcode::Lax,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            /* Lda */
+            0xA9 => Instruction {
+                opcode: Opcode::Lda,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xA5 => Instruction {
+                opcode: Opcode::Lda,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0xB5 => Instruction {
+                opcode: Opcode::Lda,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xAD => Instruction {
+                opcode: Opcode::Lda,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xBD => Instruction {
+                opcode: Opcode::Lda,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0xB9 => Instruction {
+                opcode: Opcode::Lda,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0xA1 => Instruction {
+                opcode: Opcode::Lda,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xB1 => Instruction {
+                opcode: Opcode::Lda,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: true,
+            },
+            /* Ldx */
+            0xA2 => Instruction {
+                opcode: Opcode::Ldx,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xA6 => Instruction {
+                opcode: Opcode::Ldx,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0xB6 => Instruction {
+                opcode: Opcode::Ldx,
+                mode: AddressingMode::ZeroPageY(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xAE => Instruction {
+                opcode: Opcode::Ldx,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xBE => Instruction {
+                opcode: Opcode::Ldx,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            /* Ldy */
+            0xA0 => Instruction {
+                opcode: Opcode::Ldy,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xA4 => Instruction {
+                opcode: Opcode::Ldy,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0xB4 => Instruction {
+                opcode: Opcode::Ldy,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xAC => Instruction {
+                opcode: Opcode::Ldy,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xBC => Instruction {
+                opcode: Opcode::Ldy,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            /* Lsr */
+            0x4A => Instruction {
+                opcode: Opcode::Lsr,
+                mode: AddressingMode::Accumulator,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x46 => Instruction {
+                opcode: Opcode::Lsr,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x56 => Instruction {
+                opcode: Opcode::Lsr,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x4E => Instruction {
+                opcode: Opcode::Lsr,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x5E => Instruction {
+                opcode: Opcode::Lsr,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Nop */
+            0x04 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x0C => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x14 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x1A => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x1C => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x3A => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x34 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x3C => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x44 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x54 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x5A => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x5C => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x64 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x74 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x7A => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x7C => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x80 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x82 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x89 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xC2 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xD4 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xDA => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xDC => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0xE2 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xEA => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xF4 => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xFA => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xFC => Instruction {
+                opcode: Opcode::Nop,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            /* Ora */
+            0x09 => Instruction {
+                opcode: Opcode::Ora,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x05 => Instruction {
+                opcode: Opcode::Ora,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x15 => Instruction {
+                opcode: Opcode::Ora,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x0D => Instruction {
+                opcode: Opcode::Ora,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x1D => Instruction {
+                opcode: Opcode::Ora,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x19 => Instruction {
+                opcode: Opcode::Ora,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0x01 => Instruction {
+                opcode: Opcode::Ora,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x11 => Instruction {
+                opcode: Opcode::Ora,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            /* Pha */
+            0x48 => Instruction {
+                opcode: Opcode::Pha,
+                mode: AddressingMode::Implicit,
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            /* Php */
+            0x08 => Instruction {
+                opcode: Opcode::Php,
+                mode: AddressingMode::Implicit,
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            /* Pla */
+            0x68 => Instruction {
+                opcode: Opcode::Pla,
+                mode: AddressingMode::Implicit,
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            /* Plp */
+            0x28 => Instruction {
+                opcode: Opcode::Plp,
+                mode: AddressingMode::Implicit,
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            /* Rla */
+            0x23 => Instruction {
+                opcode: Opcode::Rla,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0x27 => Instruction {
+                opcode: Opcode::Rla,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x2F => Instruction {
+                opcode: Opcode::Rla,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x33 => Instruction {
+                opcode: Opcode::Rla,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0x37 => Instruction {
+                opcode: Opcode::Rla,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x3B => Instruction {
+                opcode: Opcode::Rla,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            0x3F => Instruction {
+                opcode: Opcode::Rla,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Rol */
+            0x2A => Instruction {
+                opcode: Opcode::Rol,
+                mode: AddressingMode::Accumulator,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x26 => Instruction {
+                opcode: Opcode::Rol,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x36 => Instruction {
+                opcode: Opcode::Rol,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x2E => Instruction {
+                opcode: Opcode::Rol,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x3E => Instruction {
+                opcode: Opcode::Rol,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Ror */
+            0x6A => Instruction {
+                opcode: Opcode::Ror,
+                mode: AddressingMode::Accumulator,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0x66 => Instruction {
+                opcode: Opcode::Ror,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x76 => Instruction {
+                opcode: Opcode::Ror,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x6E => Instruction {
+                opcode: Opcode::Ror,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x7E => Instruction {
+                opcode: Opcode::Ror,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Rra */
+            0x63 => Instruction {
+                opcode: Opcode::Rra,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0x67 => Instruction {
+                opcode: Opcode::Rra,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x6F => Instruction {
+                opcode: Opcode::Rra,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x73 => Instruction {
+                opcode: Opcode::Rra,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0x77 => Instruction {
+                opcode: Opcode::Rra,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x7B => Instruction {
+                opcode: Opcode::Rra,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            0x7F => Instruction {
+                opcode: Opcode::Rra,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Rti */
+            0x40 => Instruction {
+                opcode: Opcode::Rti,
+                mode: AddressingMode::Implicit,
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            /* Rts */
+            0x60 => Instruction {
+                opcode: Opcode::Rts,
+                mode: AddressingMode::Implicit,
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            /* Sax */
+            0x83 => Instruction {
+                opcode: Opcode::Sax,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x87 => Instruction {
+                opcode: Opcode::Sax,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x8F => Instruction {
+                opcode: Opcode::Sax,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x97 => Instruction {
+                opcode: Opcode::Sax,
+                mode: AddressingMode::ZeroPageY(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            /* Sbc */
+            0xE9 => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xEB => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::Immediate(byte_after_opcode),
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            0xE5 => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0xF5 => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xED => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0xFD => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0xF9 => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: true,
+            },
+            0xE1 => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0xF1 => Instruction {
+                opcode: Opcode::Sbc,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            /* Sec */
+            0x38 => Instruction {
+                opcode: Opcode::Sec,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Sed */
+            0xF8 => Instruction {
+                opcode: Opcode::Sed,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Sei */
+            0x78 => Instruction {
+                opcode: Opcode::Sei,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Slo */
+            0x03 => Instruction {
+                opcode: Opcode::Slo,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0x07 => Instruction {
+                opcode: Opcode::Slo,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x0F => Instruction {
+                opcode: Opcode::Slo,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x13 => Instruction {
+                opcode: Opcode::Slo,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0x17 => Instruction {
+                opcode: Opcode::Slo,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x1B => Instruction {
+                opcode: Opcode::Slo,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            0x1F => Instruction {
+                opcode: Opcode::Slo,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Sre */
+            0x43 => Instruction {
+                opcode: Opcode::Sre,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0x47 => Instruction {
+                opcode: Opcode::Sre,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x4F => Instruction {
+                opcode: Opcode::Sre,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x53 => Instruction {
+                opcode: Opcode::Sre,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 8,
+                page_cross_cost: false,
+            },
+            0x57 => Instruction {
+                opcode: Opcode::Sre,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x5B => Instruction {
+                opcode: Opcode::Sre,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            0x5F => Instruction {
+                opcode: Opcode::Sre,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 7,
+                page_cross_cost: false,
+            },
+            /* Sta */
+            0x85 => Instruction {
+                opcode: Opcode::Sta,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x95 => Instruction {
+                opcode: Opcode::Sta,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x8D => Instruction {
+                opcode: Opcode::Sta,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x9D => Instruction {
+                opcode: Opcode::Sta,
+                mode: AddressingMode::AbsoluteX(word_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x99 => Instruction {
+                opcode: Opcode::Sta,
+                mode: AddressingMode::AbsoluteY(word_after_opcode),
+                cycles: 5,
+                page_cross_cost: false,
+            },
+            0x81 => Instruction {
+                opcode: Opcode::Sta,
+                mode: AddressingMode::IndirectX(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            0x91 => Instruction {
+                opcode: Opcode::Sta,
+                mode: AddressingMode::IndirectY(byte_after_opcode),
+                cycles: 6,
+                page_cross_cost: false,
+            },
+            /* Stx */
+            0x86 => Instruction {
+                opcode: Opcode::Stx,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x96 => Instruction {
+                opcode: Opcode::Stx,
+                mode: AddressingMode::ZeroPageY(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x8E => Instruction {
+                opcode: Opcode::Stx,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            /* Sty */
+            0x84 => Instruction {
+                opcode: Opcode::Sty,
+                mode: AddressingMode::ZeroPage(byte_after_opcode),
+                cycles: 3,
+                page_cross_cost: false,
+            },
+            0x94 => Instruction {
+                opcode: Opcode::Sty,
+                mode: AddressingMode::ZeroPageX(byte_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            0x8C => Instruction {
+                opcode: Opcode::Sty,
+                mode: AddressingMode::Absolute(word_after_opcode),
+                cycles: 4,
+                page_cross_cost: false,
+            },
+            /* Tax */
+            0xAA => Instruction {
+                opcode: Opcode::Tax,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Tay */
+            0xA8 => Instruction {
+                opcode: Opcode::Tay,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Tsx */
+            0xBA => Instruction {
+                opcode: Opcode::Tsx,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Txa */
+            0x8A => Instruction {
+                opcode: Opcode::Txa,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Txs */
+            0x9A => Instruction {
+                opcode: Opcode::Txs,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            /* Tya */
+            0x98 => Instruction {
+                opcode: Opcode::Tya,
+                mode: AddressingMode::Implicit,
+                cycles: 2,
+                page_cross_cost: false,
+            },
+            _ => return None,
+        };
 
-        self.write_with_addressing_mode(mode, result);
-        self.sign = false;
-        self.zero = result == 0;
-        self.carry = (to_be_lsred & (1 << 0)) != 0;
+        Some(result)
     }
 
-    fn nop(&mut self, mode: AddressingMode) {
-        // If mode isn't implicit, actually do the read. Some nop instructions may use addressing
-        // modes with "oops" cycles, so we have to actually do the read no matter what to account
-        // for this.
-        if mode != AddressingMode::Implicit {
-            self.read_with_addressing_mode(mode);
+    // Total length of the instruction in bytes, including the opcode.
+    pub fn length(&self) -> u16 {
+        1 + match self.mode {
+            AddressingMode::ZeroPage(_) => 1,
+            AddressingMode::ZeroPageX(_) => 1,
+            AddressingMode::ZeroPageY(_) => 1,
+            AddressingMode::IndirectX(_) => 1,
+            AddressingMode::IndirectY(_) => 1,
+            AddressingMode::Immediate(_) => 1,
+            AddressingMode::Relative(_) => 1,
+            AddressingMode::Absolute(_) => 2,
+            AddressingMode::AbsoluteX(_) => 2,
+            AddressingMode::AbsoluteY(_) => 2,
+            AddressingMode::Indirect(_) => 2,
+            AddressingMode::Implicit => 0,
+            AddressingMode::Accumulator => 0,
         }
     }
-
-    fn ora(&mut self, mode: AddressingMode) {
-        self.accumulator |= self.read_with_addressing_mode(mode);
-
-        self.sign = (self.accumulator as i8) < 0;
-        self.zero = self.accumulator == 0;
-    }
-
-    fn pha(&mut self) {
-        self.push_byte(self.accumulator);
-    }
-
-    fn php(&mut self) {
-        let processor_status: u8 = ((self.sign as u8) << 7)
-            | ((self.overflow as u8) << 6)
-            | ((1 as u8) << 5)
-            | ((1 as u8) << 4)
-            | ((self.decimal as u8) << 3)
-            | ((self.interrupt as u8) << 2)
-            | ((self.zero as u8) << 1)
-            | (self.carry as u8);
-        self.push_byte(processor_status);
-    }
-
-    fn pla(&mut self) {
-        self.accumulator = self.pop_byte();
-
-        self.zero = self.accumulator == 0;
-        self.sign = (self.accumulator as i8) < 0;
-    }
-
-    fn plp(&mut self) {
-        let processor_flags = self.pop_byte();
-
-        self.sign = (processor_flags & (1 << 7)) != 0;
-        self.overflow = (processor_flags & (1 << 6)) != 0;
-        let _ = (processor_flags & (1 << 5)) != 0;
-        let _ = (processor_flags & (1 << 4)) != 0;
-        self.decimal = (processor_flags & (1 << 3)) != 0;
-        self.interrupt = (processor_flags & (1 << 2)) != 0;
-        self.zero = (processor_flags & (1 << 1)) != 0;
-        self.carry = (processor_flags & (1 << 0)) != 0;
-    }
-
-    // Equivalent to rol then and
-    fn rla(&mut self, mode: AddressingMode) {
-        self.rol(mode);
-        self.and(mode);
-    }
-
-    fn rol(&mut self, mode: AddressingMode) {
-        let to_be_roled = self.read_with_addressing_mode(mode);
-        let new_val = (to_be_roled << 1) | (self.carry as u8);
-
-        self.write_with_addressing_mode(mode, new_val);
-        self.sign = (new_val as i8) < 0;
-        self.zero = new_val == 0;
-        self.carry = (to_be_roled & (1 << 7)) != 0;
-    }
-
-    fn ror(&mut self, mode: AddressingMode) {
-        let to_be_rored = self.read_with_addressing_mode(mode);
-        let new_val = (to_be_rored >> 1) | ((self.carry as u8) << 7);
-
-        self.write_with_addressing_mode(mode, new_val);
-        self.sign = (new_val as i8) < 0;
-        self.zero = new_val == 0;
-        self.carry = (to_be_rored & (1 << 0)) != 0;
-    }
-
-    // Equivalent to ror then adc
-    fn rra(&mut self, mode: AddressingMode) {
-        self.ror(mode);
-        self.adc(mode);
-    }
-
-    fn rti(&mut self) {
-        // Pull processor flags from stack
-        self.plp();
-
-        self.pc = self.pop_word();
-    }
-
-    fn rts(&mut self) {
-        self.pc = self.pop_word() + 1;
-    }
-
-    fn sax(&mut self, mode: AddressingMode) {
-        let result = self.accumulator & self.x;
-
-        self.write_with_addressing_mode(mode, result);
-    }
-
-    fn sbc(&mut self, mode: AddressingMode) {
-        // We can take advantage of:
-        // A - M - (1 - C)
-        // A + !M + 1 - (1 - C)
-        // A + !M + 1 + C - 1
-        // A + !M + C -> same as adc
-
-        let to_be_added = !self.read_with_addressing_mode(mode);
-        let old_accumulator = self.accumulator;
-
-        let (first_add, first_carry) = old_accumulator.overflowing_add(to_be_added);
-        let (result, second_carry) = first_add.overflowing_add(u8::from(self.carry));
-
-        self.accumulator = result;
-        self.sign = (result as i8) < 0;
-        self.zero = result == 0;
-        self.overflow = ((to_be_added ^ result) & (old_accumulator ^ result) & 0x80) != 0;
-        self.carry = first_carry | second_carry;
-    }
-
-    fn sec(&mut self) {
-        self.carry = true;
-    }
-
-    fn sed(&mut self) {
-        self.decimal = true;
-    }
-
-    fn sei(&mut self) {
-        self.interrupt = true;
-    }
-
-    // Equivalent to asl then ora
-    fn slo(&mut self, mode: AddressingMode) {
-        self.asl(mode);
-        self.ora(mode);
-    }
-
-    // Equivalent to lsr then eor
-    fn sre(&mut self, mode: AddressingMode) {
-        self.lsr(mode);
-        self.eor(mode);
-    }
-
-    fn sta(&mut self, mode: AddressingMode) {
-        self.write_with_addressing_mode(mode, self.accumulator);
-    }
-
-    fn stx(&mut self, mode: AddressingMode) {
-        self.write_with_addressing_mode(mode, self.x);
-    }
-
-    fn sty(&mut self, mode: AddressingMode) {
-        self.write_with_addressing_mode(mode, self.y);
-    }
-
-    fn tax(&mut self) {
-        self.x = self.accumulator;
-
-        self.zero = self.x == 0;
-        self.sign = (self.x as i8) < 0;
-    }
-
-    fn tay(&mut self) {
-        self.y = self.accumulator;
-
-        self.zero = self.y == 0;
-        self.sign = (self.y as i8) < 0;
-    }
-
-    fn tsx(&mut self) {
-        self.x = self.sp;
-
-        self.zero = self.x == 0;
-        self.sign = (self.x as i8) < 0;
-    }
-
-    fn txa(&mut self) {
-        self.accumulator = self.x;
-
-        self.zero = self.accumulator == 0;
-        self.sign = (self.accumulator as i8) < 0;
-    }
-
-    fn txs(&mut self) {
-        self.sp = self.x;
-    }
-
-    fn tya(&mut self) {
-        self.accumulator = self.y;
-
-        self.zero = self.accumulator == 0;
-        self.sign = (self.accumulator as i8) < 0;
-    }
 }
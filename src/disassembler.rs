@@ -0,0 +1,657 @@
+use crate::cpu::{AddressingMode, Cpu, CpuBus, Instruction, Opcode};
+use crate::rom::Rom;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+use std::io::{Error, ErrorKind};
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mnemonic = match self {
+            Opcode::Add => "ADC",
+            Opcode::And => "AND",
+            Opcode::Asl => "ASL",
+            Opcode::Bcc => "BCC",
+            Opcode::Bcs => "BCS",
+            Opcode::Beq => "BEQ",
+            Opcode::Bit => "BIT",
+            Opcode::Bmi => "BMI",
+            Opcode::Bne => "BNE",
+            Opcode::Bpl => "BPL",
+            Opcode::Brk => "BRK",
+            Opcode::Bvc => "BVC",
+            Opcode::Bvs => "BVS",
+            Opcode::Clc => "CLC",
+            Opcode::Cld => "CLD",
+            Opcode::Cli => "CLI",
+            Opcode::Clv => "CLV",
+            Opcode::Cmp => "CMP",
+            Opcode::Cpx => "CPX",
+            Opcode::Cpy => "CPY",
+            Opcode::Dcp => "DCP",
+            Opcode::Dec => "DEC",
+            Opcode::Dex => "DEX",
+            Opcode::Dey => "DEY",
+            Opcode::Eor => "EOR",
+            Opcode::Inc => "INC",
+            Opcode::Inx => "INX",
+            Opcode::Iny => "INY",
+            Opcode::Isc => "ISB", // nestest.log spells it ISB
+            Opcode::Jmp => "JMP",
+            Opcode::Jsr => "JSR",
+            Opcode::Lax => "LAX",
+            Opcode::Lda => "LDA",
+            Opcode::Ldx => "LDX",
+            Opcode::Ldy => "LDY",
+            Opcode::Lsr => "LSR",
+            Opcode::Nop => "NOP",
+            Opcode::Ora => "ORA",
+            Opcode::Pha => "PHA",
+            Opcode::Php => "PHP",
+            Opcode::Pla => "PLA",
+            Opcode::Plp => "PLP",
+            Opcode::Rla => "RLA",
+            Opcode::Rol => "ROL",
+            Opcode::Ror => "ROR",
+            Opcode::Rra => "RRA",
+            Opcode::Rti => "RTI",
+            Opcode::Rts => "RTS",
+            Opcode::Sax => "SAX",
+            Opcode::Sbc => "SBC",
+            Opcode::Sec => "SEC",
+            Opcode::Sed => "SED",
+            Opcode::Sei => "SEI",
+            Opcode::Slo => "SLO",
+            Opcode::Sre => "SRE",
+            Opcode::Sta => "STA",
+            Opcode::Stx => "STX",
+            Opcode::Sty => "STY",
+            Opcode::Tax => "TAX",
+            Opcode::Tay => "TAY",
+            Opcode::Tsx => "TSX",
+            Opcode::Txa => "TXA",
+            Opcode::Txs => "TXS",
+            Opcode::Tya => "TYA",
+        };
+
+        f.write_str(mnemonic)
+    }
+}
+
+// Whether an opcode byte is one of the undocumented ones. These are prefixed with a '*' in
+// listings, the same way nestest.log does it.
+pub fn is_unofficial(code: u8, opcode: Opcode) -> bool {
+    match opcode {
+        Opcode::Dcp
+        | Opcode::Isc
+        | Opcode::Lax
+        | Opcode::Rla
+        | Opcode::Rra
+        | Opcode::Sax
+        | Opcode::Slo
+        | Opcode::Sre => true,
+        Opcode::Nop => code != 0xEA,
+        Opcode::Sbc => code == 0xEB,
+        _ => false,
+    }
+}
+
+// Where a relative branch at `pc` ends up.
+pub fn branch_target(pc: u16, offset: i8) -> u16 {
+    pc.wrapping_add(2).wrapping_add(offset as u16)
+}
+
+// Formats the operand of an instruction located at `pc` in standard assembler syntax, e.g.
+// `($20),Y` or `$C0F3` for branches.
+pub fn format_operand(mode: AddressingMode, pc: u16) -> String {
+    match mode {
+        AddressingMode::ZeroPage(val) => format!("${:02X}", val),
+        AddressingMode::ZeroPageX(val) => format!("${:02X},X", val),
+        AddressingMode::ZeroPageY(val) => format!("${:02X},Y", val),
+        AddressingMode::Absolute(addr) => format!("${:04X}", addr),
+        AddressingMode::AbsoluteX(addr) => format!("${:04X},X", addr),
+        AddressingMode::AbsoluteY(addr) => format!("${:04X},Y", addr),
+        AddressingMode::Indirect(addr) => format!("(${:04X})", addr),
+        AddressingMode::IndirectX(val) => format!("(${:02X},X)", val),
+        AddressingMode::IndirectY(val) => format!("(${:02X}),Y", val),
+        AddressingMode::Implicit => String::new(),
+        AddressingMode::Immediate(val) => format!("#${:02X}", val),
+        AddressingMode::Relative(offset) => format!("${:04X}", branch_target(pc, offset)),
+        AddressingMode::Accumulator => String::from("A"),
+    }
+}
+
+// Formats an instruction located at `pc`, e.g. `LDA ($20),Y`.
+pub fn format_instruction(instruction: &Instruction, pc: u16) -> String {
+    let operand = format_operand(instruction.mode, pc);
+    if operand.is_empty() {
+        instruction.opcode.to_string()
+    } else {
+        format!("{} {}", instruction.opcode, operand)
+    }
+}
+
+// Formats an instruction about to be executed at `pc`, annotated with the addresses and values it
+// will touch given the CPU's current state (the way nestest.log does it), e.g.
+// `LDA ($89),Y = 0300 @ 0300 = 89`. Memory is only peeked, so this has no side effects.
+pub fn format_instruction_with_state<B: CpuBus>(
+    cpu: &Cpu<B>,
+    instruction: &Instruction,
+    pc: u16,
+) -> String {
+    let text = format_instruction(instruction, pc);
+    let bus = &cpu.bus;
+    let peek_word = |low_addr: u16, high_addr: u16| {
+        (u16::from(bus.peek_byte_at(high_addr)) << 8) | u16::from(bus.peek_byte_at(low_addr))
+    };
+
+    // Jumps don't read their target, so there's nothing interesting to annotate.
+    if matches!(instruction.opcode, Opcode::Jmp | Opcode::Jsr) {
+        if let AddressingMode::Indirect(addr) = instruction.mode {
+            // jmp (xxFF) reads its high byte from xx00
+            let target = peek_word(addr, (addr & 0xFF00) | (addr.wrapping_add(1) & 0x00FF));
+            return format!("{} = {:04X}", text, target);
+        }
+        return text;
+    }
+
+    match instruction.mode {
+        AddressingMode::ZeroPage(val) => {
+            format!("{} = {:02X}", text, bus.peek_byte_at(u16::from(val)))
+        }
+        AddressingMode::ZeroPageX(val) | AddressingMode::ZeroPageY(val) => {
+            let index = match instruction.mode {
+                AddressingMode::ZeroPageX(_) => cpu.x,
+                _ => cpu.y,
+            };
+            let addr = val.wrapping_add(index);
+            format!(
+                "{} @ {:02X} = {:02X}",
+                text,
+                addr,
+                bus.peek_byte_at(u16::from(addr))
+            )
+        }
+        AddressingMode::Absolute(addr) => format!("{} = {:02X}", text, bus.peek_byte_at(addr)),
+        AddressingMode::AbsoluteX(base) | AddressingMode::AbsoluteY(base) => {
+            let index = match instruction.mode {
+                AddressingMode::AbsoluteX(_) => cpu.x,
+                _ => cpu.y,
+            };
+            let addr = base.wrapping_add(u16::from(index));
+            format!("{} @ {:04X} = {:02X}", text, addr, bus.peek_byte_at(addr))
+        }
+        AddressingMode::IndirectX(val) => {
+            let pointer = val.wrapping_add(cpu.x);
+            let addr = peek_word(u16::from(pointer), u16::from(pointer.wrapping_add(1)));
+            format!(
+                "{} @ {:02X} = {:04X} = {:02X}",
+                text,
+                pointer,
+                addr,
+                bus.peek_byte_at(addr)
+            )
+        }
+        AddressingMode::IndirectY(val) => {
+            let base = peek_word(u16::from(val), u16::from(val.wrapping_add(1)));
+            let addr = base.wrapping_add(u16::from(cpu.y));
+            format!(
+                "{} = {:04X} @ {:04X} = {:02X}",
+                text,
+                base,
+                addr,
+                bus.peek_byte_at(addr)
+            )
+        }
+        _ => text,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DisassembledLine {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub instruction: Option<Instruction>, // None for bytes that don't decode to an instruction
+}
+
+impl fmt::Display for DisassembledLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.instruction {
+            Some(instruction) => {
+                let bytes = self
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                let prefix = if is_unofficial(self.bytes[0], instruction.opcode) {
+                    '*'
+                } else {
+                    ' '
+                };
+                write!(
+                    f,
+                    "{:04X}  {:<8} {}{}",
+                    self.addr,
+                    bytes,
+                    prefix,
+                    format_instruction(&instruction, self.addr)
+                )
+            }
+            None => {
+                let values = self
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("${:02X}", byte))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                write!(f, "{:04X}  {:<8}  .db {}", self.addr, "", values)
+            }
+        }
+    }
+}
+
+// Decodes the single instruction at `offset` in `memory`. Returns None if the opcode isn't known
+// or the instruction runs off the end of `memory`.
+pub fn decode_at(memory: &[u8], offset: usize) -> Option<Instruction> {
+    let opcode = *memory.get(offset)?;
+    let byte_after_opcode = memory.get(offset + 1).copied().unwrap_or(0);
+    let word_after_opcode = u16::from(byte_after_opcode)
+        | (u16::from(memory.get(offset + 2).copied().unwrap_or(0)) << 8);
+
+    let instruction = Instruction::decode(opcode, byte_after_opcode, word_after_opcode)?;
+    if offset + usize::from(instruction.length()) > memory.len() {
+        return None;
+    }
+
+    Some(instruction)
+}
+
+// Linearly disassembles all of `memory` (mapped starting at `base`) without executing anything.
+// Bytes that don't decode are emitted as single-byte data lines.
+pub fn disassemble(memory: &[u8], base: u16) -> Vec<DisassembledLine> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < memory.len() {
+        let line = disassemble_line(memory, base, offset);
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn disassemble_line(memory: &[u8], base: u16, offset: usize) -> DisassembledLine {
+    let addr = base.wrapping_add(offset as u16);
+    match decode_at(memory, offset) {
+        Some(instruction) => DisassembledLine {
+            addr,
+            bytes: memory[offset..offset + usize::from(instruction.length())].to_vec(),
+            instruction: Some(instruction),
+        },
+        None => DisassembledLine {
+            addr,
+            bytes: vec![memory[offset]],
+            instruction: None,
+        },
+    }
+}
+
+// Follows control flow from each entry point, returning the addresses of every instruction
+// reachable from them. Indirect jumps and computed branches can't be followed statically, so code
+// only reachable that way will be missed.
+pub fn trace_code(memory: &[u8], base: u16, entry_points: &[u16]) -> BTreeSet<u16> {
+    let mut visited = BTreeSet::new();
+    let mut to_visit = entry_points.to_vec();
+    let end = usize::from(base) + memory.len();
+
+    while let Some(addr) = to_visit.pop() {
+        let mut addr = addr;
+        loop {
+            if usize::from(addr) < usize::from(base) || usize::from(addr) >= end {
+                break;
+            }
+            if !visited.insert(addr) {
+                break;
+            }
+
+            let instruction = match decode_at(memory, usize::from(addr - base)) {
+                Some(instruction) => instruction,
+                None => {
+                    visited.remove(&addr);
+                    break;
+                }
+            };
+
+            if let Some(target) = jump_target(addr, instruction) {
+                to_visit.push(target);
+            }
+            if matches!(
+                instruction.opcode,
+                Opcode::Jmp | Opcode::Rts | Opcode::Rti | Opcode::Brk
+            ) {
+                break;
+            }
+
+            addr = addr.wrapping_add(instruction.length());
+        }
+    }
+
+    visited
+}
+
+// The address a jump, call or branch at `addr` can transfer control to, if it is known statically.
+fn jump_target(addr: u16, instruction: Instruction) -> Option<u16> {
+    match (instruction.opcode, instruction.mode) {
+        (Opcode::Jmp, AddressingMode::Absolute(target))
+        | (Opcode::Jsr, AddressingMode::Absolute(target)) => Some(target),
+        (_, AddressingMode::Relative(offset)) => Some(branch_target(addr, offset)),
+        _ => None,
+    }
+}
+
+// Where each PRG bank of a ROM shows up in the CPU address space. With a single bank it is
+// mirrored, so we list it at 0xC000 where the vectors live. With more banks than fit, only the last
+// one is fixed at 0xC000 and the rest are listed at 0x8000.
+fn prg_bank_bases(rom: &Rom) -> Vec<u16> {
+    let bank_count = rom.prg_rom.len() / 0x4000;
+    match bank_count {
+        1 => vec![0xC000],
+        2 => vec![0x8000, 0xC000],
+        _ => (0..bank_count)
+            .map(|bank| {
+                if bank == bank_count - 1 {
+                    0xC000
+                } else {
+                    0x8000
+                }
+            })
+            .collect(),
+    }
+}
+
+// Produces an annotated listing of every PRG bank in the ROM. Code reachable from the reset, NMI
+// and IRQ vectors is disassembled and labelled, everything else is emitted as data.
+pub fn listing(rom: &Rom) -> Result<String, Error> {
+    if rom.prg_rom.is_empty() || rom.prg_rom.len() % 0x4000 != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "PRG ROM is {} bytes, not a whole number of 16KB banks",
+                rom.prg_rom.len()
+            ),
+        ));
+    }
+
+    let mut output = String::new();
+    let bases = prg_bank_bases(rom);
+    let last_bank = &rom.prg_rom[rom.prg_rom.len() - 0x4000..];
+    let read_vector = |addr: usize| {
+        u16::from(last_bank[addr - 0xC000]) | (u16::from(last_bank[addr - 0xBFFF]) << 8)
+    };
+
+    // Vectors often share a handler (NMI and IRQ especially), which then gets a label like
+    // `nmi/irq`.
+    let mut labels = BTreeMap::<u16, String>::new();
+    for &(vector, name) in &[(0xFFFA, "nmi"), (0xFFFC, "reset"), (0xFFFE, "irq")] {
+        labels
+            .entry(read_vector(vector))
+            .and_modify(|label| *label = format!("{}/{}", label, name))
+            .or_insert_with(|| String::from(name));
+    }
+    let entry_points = labels.keys().copied().collect::<Vec<_>>();
+
+    let _ = writeln!(output, "; NMI vector:   ${:04X}", read_vector(0xFFFA));
+    let _ = writeln!(output, "; RESET vector: ${:04X}", read_vector(0xFFFC));
+    let _ = writeln!(output, "; IRQ vector:   ${:04X}", read_vector(0xFFFE));
+
+    // Trace through what is mapped at power on first, so code in one bank that is only reached
+    // from another bank is still found.
+    let mut image = Vec::with_capacity(0x8000);
+    image.extend_from_slice(&rom.prg_rom[..0x4000]);
+    image.extend_from_slice(last_bank);
+    let mut bank_entry_points = entry_points.clone();
+    for addr in trace_code(&image, 0x8000, &entry_points) {
+        let instruction = decode_at(&image, usize::from(addr - 0x8000)).unwrap();
+        if let Some(target) = jump_target(addr, instruction) {
+            bank_entry_points.push(target);
+        }
+    }
+
+    for (bank, base) in bases.iter().enumerate() {
+        let memory = &rom.prg_rom[bank * 0x4000..(bank + 1) * 0x4000];
+        let code = trace_code(memory, *base, &bank_entry_points);
+
+        // Label everything that is jumped or branched to from code in this bank.
+        let mut bank_labels = labels.clone();
+        for addr in &code {
+            let instruction = decode_at(memory, usize::from(addr - base)).unwrap();
+            let target = match jump_target(*addr, instruction) {
+                Some(target) => target,
+                None => continue,
+            };
+            bank_labels
+                .entry(target)
+                .or_insert_with(|| format!("L{:04X}", target));
+        }
+
+        let _ = writeln!(output);
+        let _ = writeln!(output, "; PRG bank {} at ${:04X}", bank, base);
+
+        let mut offset = 0;
+        while offset < memory.len() {
+            let addr = base.wrapping_add(offset as u16);
+            let line = if code.contains(&addr) {
+                disassemble_line(memory, *base, offset)
+            } else {
+                // Group data into lines of up to 8 bytes, stopping early at code or labels.
+                let mut data_end = offset + 1;
+                while data_end < memory.len() && data_end - offset < 8 {
+                    let data_addr = base.wrapping_add(data_end as u16);
+                    if code.contains(&data_addr) || bank_labels.contains_key(&data_addr) {
+                        break;
+                    }
+                    data_end += 1;
+                }
+
+                DisassembledLine {
+                    addr,
+                    bytes: memory[offset..data_end].to_vec(),
+                    instruction: None,
+                }
+            };
+
+            if let Some(label) = bank_labels.get(&addr) {
+                let _ = writeln!(output, "{}:", label);
+            }
+            let _ = writeln!(output, "{}", line);
+
+            offset += line.bytes.len();
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::MirroringType;
+
+    fn rom_with_prg(prg_rom: Vec<u8>) -> Rom {
+        Rom {
+            prg_rom,
+            chr_rom: Vec::new(),
+            mapper_number: 0,
+            mirroring: MirroringType::Horizontal,
+            battery_backed_ram: false,
+            trainer: false,
+            hash: 0,
+        }
+    }
+
+    fn decoded(memory: &[u8]) -> (Opcode, AddressingMode) {
+        let instruction = decode_at(memory, 0).unwrap();
+        (instruction.opcode, instruction.mode)
+    }
+
+    #[test]
+    fn operands_in_every_addressing_mode() {
+        let format = |mode| format_operand(mode, 0xC000);
+        assert_eq!(format(AddressingMode::ZeroPage(0x20)), "$20");
+        assert_eq!(format(AddressingMode::ZeroPageX(0x20)), "$20,X");
+        assert_eq!(format(AddressingMode::ZeroPageY(0x20)), "$20,Y");
+        assert_eq!(format(AddressingMode::Absolute(0x0633)), "$0633");
+        assert_eq!(format(AddressingMode::AbsoluteX(0x0633)), "$0633,X");
+        assert_eq!(format(AddressingMode::AbsoluteY(0x0633)), "$0633,Y");
+        assert_eq!(format(AddressingMode::Indirect(0x02FF)), "($02FF)");
+        assert_eq!(format(AddressingMode::IndirectX(0x20)), "($20,X)");
+        assert_eq!(format(AddressingMode::IndirectY(0x20)), "($20),Y");
+        assert_eq!(format(AddressingMode::Implicit), "");
+        assert_eq!(format(AddressingMode::Immediate(0x7F)), "#$7F");
+        assert_eq!(format(AddressingMode::Accumulator), "A");
+
+        // Branches show where they go, relative to the instruction after them
+        assert_eq!(format(AddressingMode::Relative(0x10)), "$C012");
+        assert_eq!(format(AddressingMode::Relative(-2)), "$C000");
+        assert_eq!(
+            format_operand(AddressingMode::Relative(-3), 0x0000),
+            "$FFFF"
+        );
+    }
+
+    #[test]
+    fn decoding_single_instructions() {
+        assert_eq!(
+            decoded(&[0xB1, 0x20]),
+            (Opcode::Lda, AddressingMode::IndirectY(0x20))
+        );
+        assert_eq!(
+            decoded(&[0x6C, 0xFF, 0x02]),
+            (Opcode::Jmp, AddressingMode::Indirect(0x02FF))
+        );
+        assert_eq!(decoded(&[0x0A]), (Opcode::Asl, AddressingMode::Accumulator));
+        // $E2 is an undocumented 2 byte NOP that reads an immediate operand
+        assert_eq!(
+            decoded(&[0xE2, 0x44]),
+            (Opcode::Nop, AddressingMode::Immediate(0x44))
+        );
+
+        // Unknown opcodes and instructions cut short by the end of memory don't decode
+        assert!(decode_at(&[0x02], 0).is_none());
+        assert!(decode_at(&[0xAD, 0x00], 0).is_none());
+        assert!(decode_at(&[0xEA], 1).is_none());
+    }
+
+    #[test]
+    fn linear_disassembly() {
+        let memory = [0xA9, 0x01, 0x02, 0x8D, 0x00, 0x20, 0x04, 0x44, 0x4C];
+        let lines = disassemble(&memory, 0x8000)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "8000  A9 01     LDA #$01",
+                "8002            .db $02",
+                "8003  8D 00 20  STA $2000",
+                "8006  04 44    *NOP $44",
+                "8008            .db $4C",
+            ]
+        );
+    }
+
+    #[test]
+    fn tracing_follows_jumps_and_branches() {
+        let memory = [
+            0xD0, 0x03, // $8000: BNE $8005
+            0x4C, 0x08, 0x80, // $8002: JMP $8008
+            0x20, 0x09, 0x80, // $8005: JSR $8009
+            0x60, // $8008: RTS
+            0x40, // $8009: RTI
+            0xEA, // $800A: never reached
+        ];
+        let code = trace_code(&memory, 0x8000, &[0x8000]);
+        assert_eq!(
+            code.into_iter().collect::<Vec<_>>(),
+            [0x8000, 0x8002, 0x8005, 0x8008, 0x8009]
+        );
+
+        // Targets outside of memory and bytes that don't decode stop the trace
+        let memory = [0x4C, 0x00, 0x90, 0x02];
+        assert_eq!(
+            trace_code(&memory, 0x8000, &[0x8000, 0x8003])
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [0x8000]
+        );
+    }
+
+    #[test]
+    fn where_prg_banks_are_listed() {
+        let bases = |banks: usize| prg_bank_bases(&rom_with_prg(vec![0; banks * 0x4000]));
+        assert_eq!(bases(1), [0xC000]);
+        assert_eq!(bases(2), [0x8000, 0xC000]);
+        assert_eq!(bases(4), [0x8000, 0x8000, 0x8000, 0xC000]);
+    }
+
+    #[test]
+    fn listing_a_small_prg() {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..9].copy_from_slice(&[
+            0xA2, 0x00, // $C000: LDX #$00
+            0xE8, // $C002: INX
+            0xD0, 0xFD, // $C003: BNE $C002
+            0x4C, 0x00, 0xC0, // $C005: JMP $C000
+            0x40, // $C008: RTI
+        ]);
+        prg_rom[0x3FFA..].copy_from_slice(&[0x08, 0xC0, 0x00, 0xC0, 0x08, 0xC0]);
+
+        let listing = listing(&rom_with_prg(prg_rom)).unwrap();
+        let lines = listing.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[..14],
+            [
+                "; NMI vector:   $C008",
+                "; RESET vector: $C000",
+                "; IRQ vector:   $C008",
+                "",
+                "; PRG bank 0 at $C000",
+                "reset:",
+                "C000  A2 00     LDX #$00",
+                "LC002:",
+                "C002  E8        INX",
+                "C003  D0 FD     BNE $C002",
+                "C005  4C 00 C0  JMP $C000",
+                "nmi/irq:",
+                "C008  40        RTI",
+                "C009            .db $00,$00,$00,$00,$00,$00,$00,$00",
+            ]
+        );
+        assert_eq!(
+            lines.last(),
+            Some(&"FFF9            .db $00,$08,$C0,$00,$C0,$08,$C0")
+        );
+    }
+
+    #[test]
+    fn listing_needs_whole_prg_banks() {
+        assert!(listing(&rom_with_prg(Vec::new())).is_err());
+        assert!(listing(&rom_with_prg(vec![0; 0x100])).is_err());
+        assert!(listing(&rom_with_prg(vec![0; 0x6000])).is_err());
+    }
+
+    #[test]
+    fn vectors_sharing_a_handler_keep_every_name() {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0] = 0x40; // $C000: RTI
+        prg_rom[0x3FFA..].copy_from_slice(&[0x00, 0xC0, 0x00, 0xC0, 0x00, 0xC0]);
+
+        let listing = listing(&rom_with_prg(prg_rom)).unwrap();
+        assert!(listing.contains("nmi/reset/irq:\nC000  40        RTI\n"));
+    }
+}
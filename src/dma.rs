@@ -0,0 +1,130 @@
+// The 2A03's DMA unit. While active it halts the CPU and steals every CPU cycle, alternating
+// between "get" cycles (reading from the CPU bus) and "put" cycles (writing to OAM). Which kind of
+// cycle a given CPU cycle is depends only on its parity, so a transfer started on the wrong parity
+// has to burn an extra alignment cycle before it can do its first read.
+//
+// OAM DMA (a write to $4014) copies 256 bytes to $2004, taking 513 or 514 cycles. DMC DMA fetches a
+// single sample byte for the APU. If a DMC fetch is requested while OAM DMA is running, it takes
+// over the next get cycle and the OAM transfer has to realign afterwards, usually costing 2 cycles.
+
+use crate::savestate::{StateReader, StateWriter};
+
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaCycle {
+    Halt,         // the cycle the CPU spends stopping
+    Idle,         // dummy or alignment cycle, no useful bus access
+    OamRead(u16), // read a byte to be copied into OAM
+    OamWrite(u8), // write the previously read byte to $2004
+    DmcRead(u16), // read a sample byte for the DMC
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Dma {
+    pub oam_page: Option<u8>,
+    pub oam_offset: u16,
+    pub oam_latch: Option<u8>,
+    pub dmc_addr: Option<u16>,
+    pub dmc_dummy_done: bool,
+    pub dmc_sample: Option<u8>,
+    pub halted: bool,
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start_oam(&mut self, page: u8) {
+        self.oam_page = Some(page);
+        self.oam_offset = 0;
+        self.oam_latch = None;
+    }
+
+    pub fn request_dmc(&mut self, addr: u16) {
+        self.dmc_addr = Some(addr);
+        self.dmc_dummy_done = false;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.oam_page.is_some() || self.dmc_addr.is_some()
+    }
+
+    // Decides what the DMA unit does on the current CPU cycle, or returns None if there is no DMA
+    // in progress and the CPU may run.
+    pub fn next_cycle(&mut self, get_cycle: bool) -> Option<DmaCycle> {
+        if !self.is_active() {
+            self.halted = false;
+            return None;
+        }
+
+        if !self.halted {
+            self.halted = true;
+            return Some(DmaCycle::Halt);
+        }
+
+        if get_cycle {
+            if let Some(addr) = self.dmc_addr {
+                // DMC DMA needs a dummy cycle after halting before it can read. During OAM DMA
+                // that's just one of the OAM cycles, so the OAM read still goes ahead.
+                if !self.dmc_dummy_done {
+                    self.dmc_dummy_done = true;
+                    if self.oam_page.is_none() {
+                        return Some(DmaCycle::Idle);
+                    }
+                } else {
+                    self.dmc_addr = None;
+                    return Some(DmaCycle::DmcRead(addr));
+                }
+            }
+
+            if let Some(page) = self.oam_page {
+                if self.oam_latch.is_none() {
+                    let addr = (u16::from(page) << 8) | self.oam_offset;
+                    self.oam_offset += 1;
+                    return Some(DmaCycle::OamRead(addr));
+                }
+            }
+        } else {
+            if self.dmc_addr.is_some() {
+                self.dmc_dummy_done = true;
+            }
+
+            if let Some(val) = self.oam_latch.take() {
+                if self.oam_offset >= 0x100 {
+                    self.oam_page = None;
+                }
+                return Some(DmaCycle::OamWrite(val));
+            }
+        }
+
+        Some(DmaCycle::Idle)
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_option_u8(self.oam_page);
+        writer.write_u16(self.oam_offset);
+        writer.write_option_u8(self.oam_latch);
+        writer.write_option_u16(self.dmc_addr);
+        writer.write_bool(self.dmc_dummy_done);
+        writer.write_option_u8(self.dmc_sample);
+        writer.write_bool(self.halted);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.oam_page = reader.read_option_u8()?;
+        self.oam_offset = reader.read_u16()?;
+        self.oam_latch = reader.read_option_u8()?;
+        self.dmc_addr = reader.read_option_u16()?;
+        self.dmc_dummy_done = reader.read_bool()?;
+        self.dmc_sample = reader.read_option_u8()?;
+        self.halted = reader.read_bool()?;
+        Ok(())
+    }
+
+    // Called by the bus with the result of an OamRead cycle.
+    pub fn latch_oam_byte(&mut self, val: u8) {
+        self.oam_latch = Some(val);
+    }
+}
@@ -0,0 +1,215 @@
+// The Famicom's expansion port. Unlike the controller ports, a device here sees all three output
+// lines written to $4016 (OUT0-OUT2), and it can drive D1-D4 of both $4016 and $4017, alongside
+// whatever the controllers put on D0.
+
+use crate::ppu::Ppu;
+use crate::savestate::{StateReader, StateWriter};
+
+use serde::{Deserialize, Serialize};
+
+use std::convert::TryFrom;
+use std::io;
+
+pub const KEYBOARD_ROWS: usize = 9;
+
+// Keys held down on a Family BASIC keyboard, one byte per row of its matrix with bit n set while
+// the row's key n (see FAMILY_BASIC_KEYS) is down.
+pub type KeyMatrix = [u8; KEYBOARD_ROWS];
+
+pub trait ExpansionPort {
+    // Called on writes to $4016 with OUT0-OUT2 (bits 0-2).
+    fn write(&mut self, out: u8);
+
+    // Called on reads of $4016 (register 0) and $4017 (register 1). Returns D1-D4 in bits 1-4,
+    // the other bits are ignored.
+    fn read(&mut self, register: usize, ppu: &Ppu) -> u8;
+
+    // Keys held down for the coming frame. Devices without keys ignore them.
+    fn set_keys(&mut self, _keys: KeyMatrix) {}
+
+    // None for devices without keys.
+    fn keys(&self) -> Option<KeyMatrix> {
+        None
+    }
+
+    fn device(&self) -> Expansion;
+
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()>;
+}
+
+// The devices that can be plugged into the expansion port, by the name used to pick them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Expansion {
+    None,
+    FamilyBasicKeyboard,
+}
+
+impl Expansion {
+    pub const NAMES: &'static [(&'static str, Expansion)] = &[
+        ("none", Expansion::None),
+        ("family_basic_keyboard", Expansion::FamilyBasicKeyboard),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Expansion> {
+        Self::NAMES
+            .iter()
+            .find(|&&(device_name, _)| device_name == name)
+            .map(|&(_, device)| device)
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|&&(_, device)| device == self)
+            .unwrap()
+            .0
+    }
+
+    pub fn create(self) -> Box<dyn ExpansionPort> {
+        match self {
+            Expansion::None => Box::new(Unplugged),
+            Expansion::FamilyBasicKeyboard => Box::new(FamilyBasicKeyboard::new()),
+        }
+    }
+}
+
+impl TryFrom<String> for Expansion {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, String> {
+        Self::from_name(&name).ok_or_else(|| format!("unknown expansion device \"{}\"", name))
+    }
+}
+
+impl From<Expansion> for String {
+    fn from(device: Expansion) -> String {
+        device.name().to_string()
+    }
+}
+
+pub struct Unplugged;
+
+impl ExpansionPort for Unplugged {
+    fn write(&mut self, _out: u8) {}
+
+    fn read(&mut self, _register: usize, _ppu: &Ppu) -> u8 {
+        0
+    }
+
+    fn device(&self) -> Expansion {
+        Expansion::None
+    }
+
+    fn save_state(&self, _writer: &mut StateWriter) {}
+
+    fn load_state(&mut self, _reader: &mut StateReader) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The Family BASIC keyboard's matrix, by the host key standing in for each key (as SDL names
+// them). Every row is two columns of four keys, each column read as D4 down to D1:
+//
+//   row 0  ]     [      RETURN  F8    STOP  ¥     RSHIFT  KANA
+//   row 1  ;     :      @       F7    ^     -     /       _
+//   row 2  K     L      O       F6    0     P     ,       .
+//   row 3  J     U      I       F5    8     9     N       M
+//   row 4  H     G      Y       F4    6     7     V       B
+//   row 5  D     R      T       F3    4     5     C       F
+//   row 6  A     S      W       F2    3     E     Z       X
+//   row 7  CTR   Q      ESC     F1    2     1     GRPH    LSHIFT
+//   row 8  LEFT  RIGHT  UP      CLR   INS   DEL   SPACE   DOWN
+#[rustfmt::skip]
+pub const FAMILY_BASIC_KEYS: [[&str; 8]; KEYBOARD_ROWS] = [
+    ["]",         "[",     "Return", "F8",   "End",    "\\",     "Right Shift", "Right Alt"],
+    [";",         "'",     "`",      "F7",   "=",      "-",      "/",           "Right Ctrl"],
+    ["K",         "L",     "O",      "F6",   "0",      "P",      ",",           "."],
+    ["J",         "U",     "I",      "F5",   "8",      "9",      "N",           "M"],
+    ["H",         "G",     "Y",      "F4",   "6",      "7",      "V",           "B"],
+    ["D",         "R",     "T",      "F3",   "4",      "5",      "C",           "F"],
+    ["A",         "S",     "W",      "F2",   "3",      "E",      "Z",           "X"],
+    ["Left Ctrl", "Q",     "Escape", "F1",   "2",      "1",      "Left Alt",    "Left Shift"],
+    ["Left",      "Right", "Up",     "Home", "Insert", "Delete", "Space",       "Down"],
+];
+
+// The Family BASIC keyboard. Writes to $4016 drive the matrix scan:
+//
+//   OUT0  1 goes back to row 0
+//   OUT1  the column, going from 1 to 0 moves on to the next row
+//   OUT2  1 enables the keyboard
+//
+// and $4017 D1-D4 read the selected column of the selected row, 0 for a key held down. Past the
+// last row nothing is held; with the keyboard disabled it drives nothing at all.
+#[derive(Default)]
+pub struct FamilyBasicKeyboard {
+    keys: KeyMatrix,
+    row: u8,
+    column: u8,
+    enabled: bool,
+}
+
+impl FamilyBasicKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ExpansionPort for FamilyBasicKeyboard {
+    fn write(&mut self, out: u8) {
+        let column = (out >> 1) & 1;
+        self.enabled = out & (1 << 2) != 0;
+        if self.enabled {
+            if self.column == 1 && column == 0 {
+                self.row = (self.row + 1).min(KEYBOARD_ROWS as u8);
+            }
+            if out & 1 != 0 {
+                self.row = 0;
+            }
+        }
+        self.column = column;
+    }
+
+    fn read(&mut self, register: usize, _ppu: &Ppu) -> u8 {
+        if register != 1 || !self.enabled {
+            return 0;
+        }
+
+        let held = match self.keys.get(usize::from(self.row)) {
+            Some(&row) => (row >> (self.column * 4)) & 0xF,
+            None => 0,
+        };
+        // Key 0 of the column is on D4, key 3 on D1
+        let held = (0..4)
+            .filter(|&key| held & (1 << key) != 0)
+            .fold(0, |bits, key| bits | (1 << (4 - key)));
+        !held & 0x1E
+    }
+
+    fn set_keys(&mut self, keys: KeyMatrix) {
+        self.keys = keys;
+    }
+
+    fn keys(&self) -> Option<KeyMatrix> {
+        Some(self.keys)
+    }
+
+    fn device(&self) -> Expansion {
+        Expansion::FamilyBasicKeyboard
+    }
+
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.row);
+        writer.write_u8(self.column);
+        writer.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.row = reader.read_u8()?;
+        self.column = reader.read_u8()?;
+        self.enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
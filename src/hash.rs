@@ -0,0 +1,15 @@
+// 64-bit FNV-1a. Not cryptographic, just a short and stable way to tell two blobs of bytes apart.
+
+const OFFSET_BASIS: u64 = 0xCBF29CE484222325;
+const PRIME: u64 = 0x100000001B3;
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    fnv1a_continue(OFFSET_BASIS, bytes)
+}
+
+// Hashes more bytes on top of an existing hash, so several buffers can be hashed as one.
+pub fn fnv1a_continue(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
@@ -0,0 +1,191 @@
+// What sits between the keys a player holds down and the buttons the console sees: turbo,
+// autofire and macros. Everything here runs once per frame before the buttons are handed to the
+// console, so movies and rewind record the result like any other input.
+
+use crate::controller::Buttons;
+use crate::movie;
+
+use serde::{Deserialize, Serialize};
+
+use std::io::{self, Error, ErrorKind};
+
+// How fast turbo buttons flip, in frames pressed then frames released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TurboRate {
+    pub on: u32,
+    pub off: u32,
+}
+
+impl Default for TurboRate {
+    fn default() -> Self {
+        Self { on: 2, off: 2 }
+    }
+}
+
+// The turbo rate of every button, so a shooter can fire A quickly while B flips slowly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TurboRates {
+    pub a: TurboRate,
+    pub b: TurboRate,
+    pub select: TurboRate,
+    pub start: TurboRate,
+    pub up: TurboRate,
+    pub down: TurboRate,
+    pub left: TurboRate,
+    pub right: TurboRate,
+}
+
+impl TurboRates {
+    // Every button at the same rate.
+    pub fn all(rate: TurboRate) -> Self {
+        Self {
+            a: rate,
+            b: rate,
+            select: rate,
+            start: rate,
+            up: rate,
+            down: rate,
+            left: rate,
+            right: rate,
+        }
+    }
+
+    // (button name, button, rate) for every button, in the order the controller reports them.
+    pub fn buttons(&self) -> [(&'static str, Buttons, TurboRate); 8] {
+        [
+            ("a", Buttons::A, self.a),
+            ("b", Buttons::B, self.b),
+            ("select", Buttons::SELECT, self.select),
+            ("start", Buttons::START, self.start),
+            ("up", Buttons::UP, self.up),
+            ("down", Buttons::DOWN, self.down),
+            ("left", Buttons::LEFT, self.left),
+            ("right", Buttons::RIGHT, self.right),
+        ]
+    }
+}
+
+// A recorded stretch of input, played back one frame at a time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Macro {
+    pub frames: Vec<Buttons>,
+}
+
+impl Macro {
+    // One line per frame, with the buttons written the way FM2 movies write them.
+    pub fn to_text(&self) -> String {
+        self.frames
+            .iter()
+            .map(|&buttons| movie::buttons_to_fm2(buttons) + "\n")
+            .collect()
+    }
+
+    pub fn from_text(text: &str) -> io::Result<Self> {
+        let frames = text
+            .lines()
+            .enumerate()
+            .map(|(line, buttons)| {
+                movie::buttons_from_fm2(buttons.trim_end()).ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("line {}: expected buttons like RLDUTSBA", line + 1),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self { frames })
+    }
+}
+
+// One player's input layer.
+#[derive(Default)]
+pub struct PlayerInput {
+    pub rates: TurboRates,
+    // With autofire on, tapping a turbo key keeps that button firing until it's tapped again,
+    // instead of only while it's held.
+    autofire: bool,
+    firing: Buttons,        // turbo buttons currently firing
+    turbo_held: Buttons,    // turbo keys held last frame, to see taps
+    turbo_frames: [u32; 8], // for each button, frames since it started firing
+    playing: Option<(Macro, usize)>,
+    recording: Option<Vec<Buttons>>,
+}
+
+impl PlayerInput {
+    pub fn new(rates: TurboRates) -> Self {
+        Self {
+            rates,
+            ..Self::default()
+        }
+    }
+
+    // Works out the buttons for the coming frame from the keys held down: `held` for the normal
+    // buttons and `turbo_held` for the turbo ones.
+    pub fn frame(&mut self, held: Buttons, turbo_held: Buttons) -> Buttons {
+        if self.autofire {
+            let tapped = Buttons(turbo_held.0 & !self.turbo_held.0);
+            self.firing = Buttons(self.firing.0 ^ tapped.0);
+        } else {
+            self.firing = turbo_held;
+        }
+        self.turbo_held = turbo_held;
+
+        let mut buttons = held;
+        for (frames, (_, button, rate)) in self.turbo_frames.iter_mut().zip(self.rates.buttons()) {
+            if !self.firing.contains(button) {
+                *frames = 0;
+                continue;
+            }
+            // Turbo starts with its pressed frames, so even a quick tap registers.
+            if *frames % (rate.on + rate.off) < rate.on {
+                buttons |= button;
+            }
+            *frames += 1;
+        }
+
+        if let Some((played, frame)) = self.playing.as_mut() {
+            buttons |= played.frames[*frame];
+            *frame += 1;
+            if *frame == played.frames.len() {
+                self.playing = None;
+            }
+        }
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(buttons);
+        }
+
+        buttons
+    }
+
+    pub fn autofire(&self) -> bool {
+        self.autofire
+    }
+
+    pub fn set_autofire(&mut self, autofire: bool) {
+        self.autofire = autofire;
+        self.firing = Buttons::NONE;
+    }
+
+    // Plays a macro on top of whatever is held down, starting next frame.
+    pub fn play(&mut self, played: &Macro) {
+        if !played.frames.is_empty() {
+            self.playing = Some((played.clone(), 0));
+        }
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Vec::new());
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Stops recording and returns everything since start_recording.
+    pub fn finish_recording(&mut self) -> Option<Macro> {
+        self.recording.take().map(|frames| Macro { frames })
+    }
+}
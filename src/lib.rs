@@ -0,0 +1,18 @@
+pub mod bus;
+pub mod config;
+pub mod controller;
+pub mod cpu;
+pub mod disassembler;
+pub mod dma;
+pub mod expansion;
+pub mod hash;
+pub mod input;
+pub mod movie;
+pub mod nes;
+pub mod ppu;
+pub mod rewind;
+pub mod rom;
+pub mod savestate;
+pub mod screenshot;
+pub mod test_rom;
+pub mod trace;
@@ -1,74 +1,717 @@
-mod bus;
-mod controller;
-mod cpu;
-mod ppu;
-mod rom;
-
-use crate::bus::Bus;
-use crate::controller::Controller;
-use crate::cpu::{Cpu, Interrupt};
-use crate::ppu::Ppu;
-use crate::rom::Rom;
-
-use std::cell::RefCell;
+use emulator::config::{Config, GameConfig, KeyChord};
+use emulator::controller::{Buttons, Device, Pointer, PLAYERS};
+use emulator::disassembler;
+use emulator::expansion::{Expansion, KeyMatrix, FAMILY_BASIC_KEYS, KEYBOARD_ROWS};
+use emulator::input::{Macro, PlayerInput};
+use emulator::movie::{Movie, MovieSession, MovieStart, COMMAND_RESET};
+use emulator::nes::Nes;
+use emulator::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use emulator::rewind::{Rewind, RewindConfig};
+use emulator::rom::Rom;
+use emulator::screenshot;
+use emulator::trace::{TraceCondition, TraceFormat, Tracer};
+
+use sdl2::event::Event;
+use sdl2::keyboard::{KeyboardState, Keycode, Mod, Scancode};
+use sdl2::mouse::MouseState;
+use sdl2::pixels::PixelFormatEnum;
+
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::rc::Rc;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
+const DEFAULT_ROM: &str = "roms/galaga.nes";
+const SCALE: u32 = 3;
+// Read if it exists and no other config file is given.
+const DEFAULT_CONFIG: &str = "emulator.toml";
+// Recorded macros are kept here, as <name>.txt.
+const MACRO_DIR: &str = "macros";
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let rom = Rom::new("roms/galaga.nes")?;
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    match args.first().map(String::as_str) {
+        // emulator disasm <rom>: dump an annotated listing of the ROM's PRG banks
+        Some("disasm") => {
+            let rom_path = args.get(1).ok_or("usage: emulator disasm <rom>")?;
+            let rom = Rom::new(rom_path)?;
+            print!("{}", disassembler::listing(&rom)?);
+            Ok(())
+        }
+        // emulator [rom] [options]: play the given ROM
+        _ => run(Options::parse(&args)?),
+    }
+}
+
+struct Options {
+    rom_path: String,
+    trace_path: Option<String>,
+    trace_ring: Option<usize>,
+    trace_format: TraceFormat,
+    trace_pc: Option<RangeInclusive<u16>>,
+    trace_frames: Option<RangeInclusive<u64>>,
+    rewind: RewindConfig,
+    record_path: Option<String>,
+    play_path: Option<String>,
+    movie_state_path: Option<String>,
+    devices: [Option<Device>; 2],
+    expansion: Option<Expansion>,
+    config_path: Option<String>,
+    bindings: Vec<String>,
+}
+
+impl Options {
+    const USAGE: &'static str = "usage: emulator [rom] [--trace <file>] [--trace-ring <lines>] \
+        [--trace-format nestest|registers] [--trace-pc <start>-<end>] [--trace-frames <start>-<end>] \
+        [--rewind-interval <frames>] [--rewind-memory <megabytes>] \
+        [--record <movie.fm2> [--movie-state <state>]] [--play <movie.fm2>] \
+        [--port1 <device>] [--port2 <device>] [--expansion <device>] \
+        [--config <file.toml>] [--bind <table>.<name>=<key>]...";
+
+    fn parse(args: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut options = Options {
+            rom_path: String::from(DEFAULT_ROM),
+            trace_path: None,
+            trace_ring: None,
+            trace_format: TraceFormat::Nestest,
+            trace_pc: None,
+            trace_frames: None,
+            rewind: RewindConfig::default(),
+            record_path: None,
+            play_path: None,
+            movie_state_path: None,
+            devices: [None; 2],
+            expansion: None,
+            config_path: None,
+            bindings: Vec::new(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(Self::USAGE);
+            match arg.as_str() {
+                "--trace" => options.trace_path = Some(value()?.clone()),
+                "--trace-ring" => {
+                    let lines = value()?.parse()?;
+                    if lines == 0 {
+                        return Err("--trace-ring needs at least 1 line".into());
+                    }
+                    options.trace_ring = Some(lines);
+                }
+                "--trace-format" => {
+                    options.trace_format = match value()?.as_str() {
+                        "nestest" => TraceFormat::Nestest,
+                        "registers" => TraceFormat::Registers,
+                        _ => return Err(Self::USAGE.into()),
+                    }
+                }
+                "--trace-pc" => {
+                    let (start, end) = parse_range(value()?)?;
+                    options.trace_pc =
+                        Some(u16::from_str_radix(start, 16)?..=u16::from_str_radix(end, 16)?);
+                }
+                "--trace-frames" => {
+                    let (start, end) = parse_range(value()?)?;
+                    options.trace_frames = Some(start.parse()?..=end.parse()?);
+                }
+                "--rewind-interval" => options.rewind.interval = value()?.parse()?,
+                "--rewind-memory" => {
+                    options.rewind.max_bytes = value()?.parse::<usize>()? * 1024 * 1024
+                }
+                "--record" => options.record_path = Some(value()?.clone()),
+                "--play" => options.play_path = Some(value()?.clone()),
+                "--movie-state" => options.movie_state_path = Some(value()?.clone()),
+                "--port1" | "--port2" => {
+                    let port = if arg == "--port1" { 0 } else { 1 };
+                    options.devices[port] = Some(Device::from_name(value()?).ok_or(Self::USAGE)?);
+                }
+                "--expansion" => {
+                    options.expansion = Some(Expansion::from_name(value()?).ok_or(Self::USAGE)?);
+                }
+                "--config" => options.config_path = Some(value()?.clone()),
+                "--bind" => options.bindings.push(value()?.clone()),
+                flag if flag.starts_with("--") => return Err(Self::USAGE.into()),
+                rom_path => options.rom_path = rom_path.to_string(),
+            }
+        }
+
+        if options.record_path.is_some() && options.play_path.is_some() {
+            return Err(Self::USAGE.into());
+        }
+
+        Ok(options)
+    }
+
+    // Starts recording or playing back a movie, if asked to.
+    fn movie(&self, nes: &mut Nes) -> Result<Option<MovieSession>, Box<dyn Error>> {
+        if let Some(path) = &self.play_path {
+            let movie = Movie::from_fm2(&std::fs::read_to_string(path)?)?;
+            return Ok(Some(MovieSession::play(nes, movie)?));
+        }
+
+        if self.record_path.is_some() {
+            let start = match &self.movie_state_path {
+                Some(path) => MovieStart::SaveState(std::fs::read(path)?),
+                None => MovieStart::PowerOn,
+            };
+            let movie = Movie::new(&self.rom_name(), nes.cpu.bus.rom.hash, start);
+            return Ok(Some(MovieSession::record(nes, movie)?));
+        }
+
+        Ok(None)
+    }
+
+    // The ROM's file name without the extension, which is also what per game settings go by.
+    fn rom_name(&self) -> String {
+        Path::new(&self.rom_path)
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+    }
+
+    // What's plugged into each port: the command line wins over the game's settings.
+    fn devices(&self, config: &Config) -> Result<[Device; 2], Box<dyn Error>> {
+        let game = self.game(config);
+        let devices = [
+            self.devices[0].or(game.port1).unwrap_or(Device::Standard),
+            self.devices[1].or(game.port2).unwrap_or(Device::Standard),
+        ];
+        Device::check_ports(devices)?;
+        Ok(devices)
+    }
+
+    fn expansion(&self, config: &Config) -> Expansion {
+        self.expansion
+            .or(self.game(config).expansion)
+            .unwrap_or(Expansion::None)
+    }
+
+    fn game(&self, config: &Config) -> GameConfig {
+        config
+            .games
+            .get(&self.rom_name())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn config(&self) -> Result<Config, Box<dyn Error>> {
+        let path = match &self.config_path {
+            Some(path) => Some(Path::new(path)),
+            None => Some(Path::new(DEFAULT_CONFIG)).filter(|path| path.exists()),
+        };
+        Config::load(path, &self.bindings).map_err(|err| {
+            let path = path.map_or(String::from("--bind"), |path| path.display().to_string());
+            format!("{}: {}", path, err).into()
+        })
+    }
+
+    fn tracer(&self) -> Result<Option<Tracer>, Box<dyn Error>> {
+        let tracer = match (&self.trace_path, self.trace_ring) {
+            (Some(path), _) => Tracer::to_file(path, self.trace_format)?,
+            (None, Some(lines)) => Tracer::ring_buffer(lines, self.trace_format),
+            (None, None) => return Ok(None),
+        };
+
+        // Only trace while inside both the PC and frame ranges (if given).
+        let pc_range = self.trace_pc.clone();
+        let frame_range = self.trace_frames.clone();
+        if pc_range.is_none() && frame_range.is_none() {
+            return Ok(Some(tracer));
+        }
+        let in_range = move |state: &emulator::trace::TraceState| {
+            pc_range
+                .as_ref()
+                .is_none_or(|range| range.contains(&state.pc))
+                && frame_range
+                    .as_ref()
+                    .is_none_or(|range| range.contains(&state.frame))
+        };
+        let tracer = tracer
+            .start_when(TraceCondition::Expression(Box::new(in_range.clone())))
+            .stop_when(TraceCondition::Expression(Box::new(move |state| {
+                !in_range(state)
+            })));
+        Ok(Some(tracer))
+    }
+}
+
+// Splits "<start>-<end>" into its two halves.
+fn parse_range(range: &str) -> Result<(&str, &str), Box<dyn Error>> {
+    range
+        .split_once('-')
+        .ok_or_else(|| format!("expected <start>-<end>, got {}", range).into())
+}
+
+// A KeyChord from the config, resolved to an SDL key.
+#[derive(Clone, Copy)]
+struct Chord {
+    key: Keycode,
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+}
+
+impl Chord {
+    fn resolve(name: &str, chord: &KeyChord) -> Result<Self, String> {
+        let key = Keycode::from_name(&chord.key)
+            .ok_or_else(|| format!("{}: unknown key \"{}\"", name, chord.key))?;
+        Ok(Self {
+            key,
+            shift: chord.shift,
+            ctrl: chord.ctrl,
+            alt: chord.alt,
+        })
+    }
+
+    // The modifiers have to match exactly, so F1 doesn't also fire for Shift+F1.
+    fn matches(&self, key: Keycode, keymod: Mod) -> bool {
+        key == self.key
+            && self.shift == keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD)
+            && self.ctrl == keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)
+            && self.alt == keymod.intersects(Mod::LALTMOD | Mod::RALTMOD)
+    }
+}
+
+struct Bindings {
+    buttons: Vec<Vec<(Scancode, Buttons)>>, // per player
+    turbo: Vec<Vec<(Scancode, Buttons)>>,
+    mat: Vec<Scancode>,                   // per mat button
+    keyboard: Vec<(Scancode, usize, u8)>, // (key, row, bit) for the Family BASIC keyboard
+    microphone: Scancode,
+    pause: Chord,
+    reset: Chord,
+    fast_forward: Chord,
+    rewind: Chord,
+    screenshot: Chord,
+    autofire: Chord,
+    record_macro: Chord,
+    save_slot: Vec<Chord>,
+    load_slot: Vec<Chord>,
+    macros: Vec<(String, Chord)>,
+}
+
+impl Bindings {
+    fn new(config: &Config) -> Result<Self, String> {
+        let scancode = |name: &str, key: &KeyChord| {
+            let key = Chord::resolve(name, key)?.key;
+            Scancode::from_keycode(key)
+                .ok_or_else(|| format!("{}: {} isn't a physical key", name, key))
+        };
+        let player_buttons = |player: &str, keys: &[(&str, &KeyChord, Buttons)]| {
+            keys.iter()
+                .map(|&(button, key, buttons)| {
+                    Ok((scancode(&format!("{}.{}", player, button), key)?, buttons))
+                })
+                .collect::<Result<Vec<_>, String>>()
+        };
+        let slots = |name: &str, keys: &[KeyChord]| {
+            keys.iter()
+                .enumerate()
+                .map(|(slot, key)| Chord::resolve(&format!("hotkeys.{}[{}]", name, slot), key))
+                .collect::<Result<Vec<_>, String>>()
+        };
+
+        let hotkeys = &config.hotkeys;
+        Ok(Self {
+            buttons: config
+                .players()
+                .iter()
+                .map(|(player, keys)| player_buttons(player, &keys.buttons()))
+                .collect::<Result<_, _>>()?,
+            turbo: config
+                .players()
+                .iter()
+                .map(|(player, keys)| {
+                    player_buttons(&format!("{}.turbo", player), &keys.turbo.buttons())
+                })
+                .collect::<Result<_, _>>()?,
+            mat: config
+                .power_pad
+                .buttons
+                .iter()
+                .enumerate()
+                .map(|(button, key)| scancode(&format!("power_pad.buttons[{}]", button), key))
+                .collect::<Result<_, _>>()?,
+            keyboard: FAMILY_BASIC_KEYS
+                .iter()
+                .enumerate()
+                .flat_map(|(row, keys)| {
+                    keys.iter().enumerate().map(move |(bit, key)| {
+                        let name = format!("Family BASIC key {},{}", row, bit);
+                        Ok((scancode(&name, &KeyChord::key(key))?, row, 1 << bit))
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            microphone: scancode("hotkeys.microphone", &hotkeys.microphone)?,
+            pause: Chord::resolve("hotkeys.pause", &hotkeys.pause)?,
+            reset: Chord::resolve("hotkeys.reset", &hotkeys.reset)?,
+            fast_forward: Chord::resolve("hotkeys.fast_forward", &hotkeys.fast_forward)?,
+            rewind: Chord::resolve("hotkeys.rewind", &hotkeys.rewind)?,
+            screenshot: Chord::resolve("hotkeys.screenshot", &hotkeys.screenshot)?,
+            autofire: Chord::resolve("hotkeys.autofire", &hotkeys.autofire)?,
+            record_macro: Chord::resolve("hotkeys.record_macro", &hotkeys.record_macro)?,
+            save_slot: slots("save_slot", &hotkeys.save_slot)?,
+            load_slot: slots("load_slot", &hotkeys.load_slot)?,
+            macros: config
+                .macros
+                .iter()
+                .map(|(name, key)| {
+                    let chord = Chord::resolve(&format!("macros.{}", name), key)?;
+                    Ok((name.clone(), chord))
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+        })
+    }
+
+    // The buttons and the turbo buttons a player is holding down.
+    fn player_buttons(&self, keyboard: &KeyboardState, player: usize) -> (Buttons, Buttons) {
+        let held = |keys: &[(Scancode, Buttons)]| {
+            keys.iter()
+                .filter(|&&(key, _)| keyboard.is_scancode_pressed(key))
+                .fold(Buttons::NONE, |buttons, &(_, button)| buttons | button)
+        };
+        (held(&self.buttons[player]), held(&self.turbo[player]))
+    }
+
+    // The Family BASIC keys held down.
+    fn keys(&self, keyboard: &KeyboardState) -> KeyMatrix {
+        let mut keys = [0; KEYBOARD_ROWS];
+        for &(key, row, bit) in &self.keyboard {
+            if keyboard.is_scancode_pressed(key) {
+                keys[row] |= bit;
+            }
+        }
+        keys
+    }
+
+    // The mat buttons being stepped on, see MAT_BUTTONS.
+    fn mat(&self, keyboard: &KeyboardState) -> u16 {
+        self.mat
+            .iter()
+            .enumerate()
+            .filter(|&(_, &key)| keyboard.is_scancode_pressed(key))
+            .fold(0, |mat, (button, _)| mat | (1 << button))
+    }
+}
+
+// The mouse as a pointer on the NES screen. The right button pulls the trigger with the gun aimed
+// away from the screen, which is how light gun games are told to miss on purpose.
+fn pointer(mouse: &MouseState) -> Pointer {
+    if mouse.right() {
+        return Pointer {
+            x: 0,
+            y: 0xFF,
+            button: true,
+        };
+    }
+
+    let pixel = |pos: i32, size: usize| (pos / SCALE as i32).clamp(0, size as i32 - 1) as u8;
+    Pointer {
+        x: pixel(mouse.x(), SCREEN_WIDTH),
+        y: pixel(mouse.y(), SCREEN_HEIGHT),
+        button: mouse.left(),
+    }
+}
+
+// Save states live next to the ROM: roms/mario.nes slot 0 is roms/mario.ss0.
+fn slot_path(rom_path: &str, slot: usize) -> PathBuf {
+    Path::new(rom_path).with_extension(format!("ss{}", slot))
+}
+
+// Screenshots go next to the ROM too, numbered: roms/mario-1.png, roms/mario-2.png, ...
+fn screenshot_path(rom_path: &str) -> PathBuf {
+    let rom_path = Path::new(rom_path);
+    let stem = rom_path
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    (1..)
+        .map(|n| rom_path.with_file_name(format!("{}-{}.png", stem, n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+fn save_screenshot(nes: &Nes, path: &Path) {
+    match screenshot::save_png(path, &nes.ppu().framebuffer_rgb()) {
+        Ok(()) => println!("Saved screenshot to {}", path.display()),
+        Err(err) => eprintln!("Couldn't save screenshot to {}: {}", path.display(), err),
+    }
+}
+
+fn macro_path(name: &str) -> PathBuf {
+    Path::new(MACRO_DIR).join(format!("{}.txt", name))
+}
+
+// Macros that haven't been recorded yet just do nothing.
+fn load_macros(config: &Config) -> Result<BTreeMap<String, Macro>, Box<dyn Error>> {
+    let mut macros = BTreeMap::new();
+    for name in config.macros.keys() {
+        let path = macro_path(name);
+        if path.exists() {
+            let loaded = Macro::from_text(&std::fs::read_to_string(&path)?)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            macros.insert(name.clone(), loaded);
+        }
+    }
+    Ok(macros)
+}
+
+fn save_macro(name: &str, recorded: &Macro) {
+    let path = macro_path(name);
+    let result =
+        std::fs::create_dir_all(MACRO_DIR).and_then(|()| std::fs::write(&path, recorded.to_text()));
+    match result {
+        Ok(()) => println!("Saved macro {} to {}", name, path.display()),
+        Err(err) => eprintln!("Couldn't save macro to {}: {}", path.display(), err),
+    }
+}
+
+// Failing to save or load a state shouldn't end the game, so errors are only reported.
+fn save_state(nes: &Nes, path: &Path) {
+    match std::fs::write(path, nes.save_state()) {
+        Ok(()) => println!("Saved state to {}", path.display()),
+        Err(err) => eprintln!("Couldn't save state to {}: {}", path.display(), err),
+    }
+}
+
+fn load_state(nes: &mut Nes, path: &Path) {
+    match std::fs::read(path).and_then(|state| nes.load_state(&state)) {
+        Ok(()) => println!("Loaded state from {}", path.display()),
+        Err(err) => eprintln!("Couldn't load state from {}: {}", path.display(), err),
+    }
+}
+
+// After jumping around in time, the movie has to find its place again.
+fn sync_movie(movie: &mut Option<MovieSession>, nes: &Nes) {
+    if let Some(movie) = movie {
+        if !movie.sync_frame(nes) {
+            eprintln!("The movie doesn't cover this point in the game");
+        }
+    }
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let rom = Rom::new(&options.rom_path)?;
+    let config = options.config()?;
+    let bindings = Bindings::new(&config)?;
+    let mut macros = load_macros(&config)?;
+    let devices = options.devices(&config)?;
+    let expansion = options.expansion(&config);
+    // With a keyboard plugged in, every key types, so only hotkeys held with Ctrl or Alt still
+    // reach the emulator.
+    let typing = expansion == Expansion::FamilyBasicKeyboard;
+    let mut inputs = (0..PLAYERS)
+        .map(|_| PlayerInput::new(config.turbo))
+        .collect::<Vec<_>>();
 
     let sdl_context = sdl2::init()?;
 
     let sdl_video_subsystem = sdl_context.video()?;
 
-    let sdl_events = Rc::new(RefCell::new(sdl_context.event_pump()?));
-
-    let ppu = Ppu::new(&sdl_video_subsystem);
+    let mut sdl_events = sdl_context.event_pump()?;
 
-    let controller = Controller::new(Rc::clone(&sdl_events));
+    let window = sdl_video_subsystem
+        .window(
+            "NES Terminal Window",
+            SCREEN_WIDTH as u32 * SCALE,
+            SCREEN_HEIGHT as u32 * SCALE,
+        )
+        .position_centered()
+        .build()?;
 
-    let bus = Bus::new(rom, ppu, controller);
-    let mut cpu = Cpu::new(bus);
+    let mut canvas = window.into_canvas().build()?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_streaming(
+        PixelFormatEnum::RGB24,
+        SCREEN_WIDTH as u32,
+        SCREEN_HEIGHT as u32,
+    )?;
 
-    cpu.interrupt(Interrupt::Reset);
+    let mut nes = Nes::new(rom);
+    for (port, device) in devices.iter().enumerate() {
+        nes.plug(port, device.create(port));
+    }
+    nes.plug_expansion(expansion.create());
 
-    let mut master_clock_ticks: u64 = 0;
-    let mut last_frame_start = 0;
+    nes.cpu.tracer = options.tracer()?;
+    nes.reset();
 
     let time_per_cpu_cycle = Duration::new(1, 0) / (236_250_000 / 11 / 12);
+    let time_per_frame = Duration::new(1, 0) / 60;
     let mut cpu_cycle_start_time = Instant::now();
-    let _start_time = Instant::now();
 
-    // Master clocks run at 21.477272 MHz
-    loop {
-        // CPU runs every 12 master ticks ;)
-        if master_clock_ticks % 12 == 0 {
-            cpu.step();
+    let mut movie = options.movie(&mut nes)?;
 
-            while cpu_cycle_start_time.elapsed() < time_per_cpu_cycle {} // spinlock :/
+    let mut rewind = Rewind::new(options.rewind);
+    let mut rewinding = false;
+    let mut paused = false;
+    let mut fast_forward = false;
+    let mut commands = 0; // resets asked for since the last frame
 
-            cpu_cycle_start_time = Instant::now();
-        }
+    // If we crash, dump the last few traced instructions so there's some idea of how we got there.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        loop {
+            if rewinding {
+                rewind.rewind_frame(&mut nes);
+                std::thread::sleep(time_per_frame);
+            } else if paused {
+                std::thread::sleep(time_per_frame);
+            } else {
+                for (player, input) in inputs.iter_mut().enumerate() {
+                    let (held, turbo_held) =
+                        bindings.player_buttons(&sdl_events.keyboard_state(), player);
+                    nes.set_buttons(player, input.frame(held, turbo_held));
+                }
+                let mouse = pointer(&sdl_events.mouse_state());
+                let keyboard = sdl_events.keyboard_state();
+                let mat = bindings.mat(&keyboard);
+                for port in 0..2 {
+                    nes.set_pointer(port, mouse);
+                    nes.set_mat(port, mat);
+                }
+                nes.set_keys(bindings.keys(&keyboard));
+                nes.set_microphone(keyboard.is_scancode_pressed(bindings.microphone));
+                // A movie has to see the reset to record it
+                match movie.as_mut() {
+                    Some(movie) => {
+                        if let Err(desync) = movie.begin_frame(&mut nes, commands) {
+                            eprintln!("{}", desync);
+                        }
+                    }
+                    None if commands & COMMAND_RESET != 0 => nes.reset(),
+                    None => {}
+                }
+                commands = 0;
+                rewind.record_frame(&nes);
+
+                // The CPU runs every 12 master ticks (21.477272 MHz), the PPU every 4
+                loop {
+                    let new_frame = nes.step();
+
+                    if !fast_forward {
+                        while cpu_cycle_start_time.elapsed() < time_per_cpu_cycle {}
+                        // spinlock :/
+                    }
 
-        // PPU runs every 4 master ticks
-        if master_clock_ticks % 4 == 0 {
-            let new_frame = cpu.bus.ppu.step();
-            if new_frame {
-                let current_steps = master_clock_ticks / 4;
-                let _last_frame_steps = current_steps - last_frame_start;
-                last_frame_start = current_steps;
+                    cpu_cycle_start_time = Instant::now();
+
+                    if new_frame {
+                        break;
+                    }
+                }
             }
-        }
 
-        if master_clock_ticks % 10000 == 0 {
-            for event in sdl_events.borrow_mut().poll_iter() {
-                if let sdl2::event::Event::Quit { .. } = event {
-                    panic!("Exiting!");
+            texture
+                .update(None, &nes.ppu().framebuffer_rgb(), SCREEN_WIDTH * 3)
+                .unwrap();
+            canvas.copy(&texture, None, None).unwrap();
+            canvas.present();
+
+            // Input only changes between frames, so rewinding can replay it exactly.
+            for event in sdl_events.poll_iter() {
+                match event {
+                    Event::Quit { .. } => return,
+                    Event::KeyDown {
+                        keycode: Some(key),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } if !typing
+                        || keymod.intersects(
+                            Mod::LCTRLMOD | Mod::RCTRLMOD | Mod::LALTMOD | Mod::RALTMOD,
+                        ) =>
+                    {
+                        if bindings.rewind.matches(key, keymod) {
+                            rewinding = true;
+                        } else if bindings.fast_forward.matches(key, keymod) {
+                            fast_forward = true;
+                        } else if bindings.pause.matches(key, keymod) {
+                            paused = !paused;
+                        } else if bindings.reset.matches(key, keymod) {
+                            commands |= COMMAND_RESET;
+                        } else if bindings.screenshot.matches(key, keymod) {
+                            save_screenshot(&nes, &screenshot_path(&options.rom_path));
+                        } else if bindings.autofire.matches(key, keymod) {
+                            let autofire = !inputs[0].autofire();
+                            for input in &mut inputs {
+                                input.set_autofire(autofire);
+                            }
+                            println!("Autofire {}", if autofire { "on" } else { "off" });
+                        } else if bindings.record_macro.matches(key, keymod) {
+                            // Pressing it again while recording throws the recording away
+                            if inputs[0].finish_recording().is_none() {
+                                inputs[0].start_recording();
+                                println!("Recording a macro, press a macro's key to save it");
+                            }
+                        } else if let Some((name, _)) = bindings
+                            .macros
+                            .iter()
+                            .find(|(_, chord)| chord.matches(key, keymod))
+                        {
+                            match inputs[0].finish_recording() {
+                                Some(recorded) => {
+                                    save_macro(name, &recorded);
+                                    macros.insert(name.clone(), recorded);
+                                }
+                                None => {
+                                    if let Some(played) = macros.get(name) {
+                                        inputs[0].play(played);
+                                    }
+                                }
+                            }
+                        } else if let Some(slot) = bindings
+                            .save_slot
+                            .iter()
+                            .position(|chord| chord.matches(key, keymod))
+                        {
+                            save_state(&nes, &slot_path(&options.rom_path, slot));
+                        } else if let Some(slot) = bindings
+                            .load_slot
+                            .iter()
+                            .position(|chord| chord.matches(key, keymod))
+                        {
+                            load_state(&mut nes, &slot_path(&options.rom_path, slot));
+                            rewind.clear();
+                            sync_movie(&mut movie, &nes);
+                        }
+                    }
+                    // Held hotkeys stop when their key comes up, whatever the modifiers are by then
+                    Event::KeyUp {
+                        keycode: Some(key), ..
+                    } => {
+                        if rewinding && key == bindings.rewind.key {
+                            rewinding = false;
+                            sync_movie(&mut movie, &nes);
+                        }
+                        if key == bindings.fast_forward.key {
+                            fast_forward = false;
+                        }
+                    }
+                    _ => {}
                 }
             }
         }
+    }));
 
-        master_clock_ticks += 1;
+    if let Some(tracer) = nes.cpu.tracer.as_mut() {
+        if result.is_err() {
+            eprintln!("Last traced instructions:");
+            tracer.dump(&mut std::io::stderr())?;
+        }
+        tracer.flush()?;
+    }
+
+    if let Some(path) = &options.record_path {
+        if let Some(movie) = &movie {
+            std::fs::write(path, movie.movie.to_fm2())?;
+            println!("Saved movie to {}", path);
+        }
     }
+
+    if let Err(panic) = result {
+        panic::resume_unwind(panic);
+    }
+
+    Ok(())
 }
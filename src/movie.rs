@@ -0,0 +1,508 @@
+// Input movies: the controller input of every frame, recorded so a session can be played back
+// exactly. Movies are read and written in FCEUX's FM2 text format: a header of "key value" lines
+// followed by one "|commands|port0|port1|port2|" line per frame, or with a four player adapter
+// "|commands|player1|player2|player3|player4|port2|". A port with a Zapper has "x y trigger 0 0"
+// instead of buttons.
+//
+// On top of the standard keys, movies record the ROM hash (romHash), our own save state when the
+// movie doesn't start at power-on (savestate), and a hash of RAM every RAM_HASH_INTERVAL frames
+// (ramHash <frame> <hash>) so playback can tell when it has desynced. FCEUX ignores keys it doesn't
+// know, but it can't load our save states, so only power-on movies are portable.
+
+use crate::controller::{Buttons, Device, Pointer, PLAYERS};
+use crate::expansion::Expansion;
+use crate::hash;
+use crate::nes::Nes;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Bits of the FM2 command field.
+pub const COMMAND_RESET: u8 = 1;
+pub const COMMAND_POWER: u8 = 2;
+
+pub const RAM_HASH_INTERVAL: usize = 60;
+
+// How FM2 writes a standard controller, most significant bit first: a pressed button is its
+// letter, a released one is '.'.
+const FM2_BUTTONS: &[u8; 8] = b"RLDUTSBA";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub commands: u8,
+    pub players: [Buttons; PLAYERS],
+    pub pointers: [Pointer; 2], // only used for ports with a Zapper
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MovieStart {
+    PowerOn,
+    SaveState(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Movie {
+    pub rom_filename: String,
+    pub rom_hash: Option<u64>,
+    pub guid: String,
+    pub rerecord_count: u32,
+    pub comments: Vec<String>,
+    pub start: MovieStart,
+    pub four_score: bool,  // players 3 and 4 are in the movie
+    pub zapper: [bool; 2], // which ports have a Zapper instead of a controller
+    pub frames: Vec<MovieFrame>,
+    pub ram_hashes: BTreeMap<usize, u64>,
+}
+
+impl Movie {
+    pub fn new(rom_filename: &str, rom_hash: u64, start: MovieStart) -> Self {
+        Self {
+            rom_filename: rom_filename.to_string(),
+            rom_hash: Some(rom_hash),
+            guid: new_guid(rom_hash),
+            rerecord_count: 0,
+            comments: Vec::new(),
+            start,
+            four_score: false,
+            zapper: [false; 2],
+            frames: Vec::new(),
+            ram_hashes: BTreeMap::new(),
+        }
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut fm2 = String::new();
+        let mut line = |key: &str, value: &str| fm2 += &format!("{} {}\n", key, value);
+
+        line("version", "3");
+        line("emuVersion", "0");
+        line("rerecordCount", &self.rerecord_count.to_string());
+        line("palFlag", "0");
+        line("romFilename", &self.rom_filename);
+        line("guid", &self.guid);
+        // Port types: 0 for nothing (or a four player adapter), 1 for a controller, 2 for a Zapper
+        let port = |zapper: bool| match (self.four_score, zapper) {
+            (true, _) => "0",
+            (false, false) => "1",
+            (false, true) => "2",
+        };
+        line("fourscore", if self.four_score { "1" } else { "0" });
+        line("microphone", "0");
+        line("port0", port(self.zapper[0]));
+        line("port1", port(self.zapper[1]));
+        line("port2", "0");
+        line("FDS", "0");
+        line("NewPPU", "0");
+        for comment in &self.comments {
+            line("comment", comment);
+        }
+        if let Some(rom_hash) = self.rom_hash {
+            line("romHash", &format!("0x{:016X}", rom_hash));
+        }
+        if let MovieStart::SaveState(state) = &self.start {
+            line("savestate", &format!("0x{}", to_hex(state)));
+        }
+        for (frame, ram_hash) in &self.ram_hashes {
+            line("ramHash", &format!("{} 0x{:016X}", frame, ram_hash));
+        }
+
+        let players = if self.four_score { PLAYERS } else { 2 };
+        for frame in &self.frames {
+            fm2 += &format!("|{}|", frame.commands);
+            for (player, &buttons) in frame.players[..players].iter().enumerate() {
+                if players == 2 && self.zapper[player] {
+                    let pointer = frame.pointers[player];
+                    fm2 += &format!(
+                        "{} {} {} 0 0",
+                        pointer.x,
+                        pointer.y,
+                        u8::from(pointer.button)
+                    );
+                } else {
+                    fm2 += &buttons_to_fm2(buttons);
+                }
+                fm2.push('|');
+            }
+            fm2 += "|\n";
+        }
+
+        fm2
+    }
+
+    pub fn from_fm2(fm2: &str) -> Result<Self, Error> {
+        let mut movie = Movie {
+            rom_filename: String::new(),
+            rom_hash: None,
+            guid: String::new(),
+            rerecord_count: 0,
+            comments: Vec::new(),
+            start: MovieStart::PowerOn,
+            four_score: false,
+            zapper: [false; 2],
+            frames: Vec::new(),
+            ram_hashes: BTreeMap::new(),
+        };
+
+        for (number, line) in fm2.lines().enumerate() {
+            let invalid = |reason: &str| invalid_data(format!("line {}: {}", number + 1, reason));
+
+            if let Some(input) = line.strip_prefix('|') {
+                let players = if movie.four_score { PLAYERS } else { 2 };
+                let fields = input.split('|').collect::<Vec<_>>();
+                if fields.len() < 1 + players {
+                    return Err(invalid(if movie.four_score {
+                        "expected |commands|player1|player2|player3|player4|"
+                    } else {
+                        "expected |commands|port0|port1|"
+                    }));
+                }
+
+                let mut frame = MovieFrame {
+                    commands: fields[0].parse().map_err(|_| invalid("bad command"))?,
+                    ..MovieFrame::default()
+                };
+                for (player, field) in fields[1..=players].iter().enumerate() {
+                    if players == 2 && movie.zapper[player] {
+                        frame.pointers[player] =
+                            zapper_from_fm2(field).ok_or_else(|| invalid("bad Zapper input"))?;
+                    } else {
+                        frame.players[player] =
+                            buttons_from_fm2(field).ok_or_else(|| invalid("bad buttons"))?;
+                    }
+                }
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "version" if value != "3" => {
+                    return Err(invalid("only FM2 version 3 is supported"))
+                }
+                "binary" if value != "0" => return Err(invalid("binary FM2 isn't supported")),
+                "rerecordCount" => {
+                    movie.rerecord_count = value.parse().map_err(|_| invalid("bad count"))?
+                }
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "fourscore" => movie.four_score = value == "1",
+                "port0" => movie.zapper[0] = value == "2",
+                "port1" => movie.zapper[1] = value == "2",
+                "guid" => movie.guid = value.to_string(),
+                "comment" => movie.comments.push(value.to_string()),
+                "romHash" => {
+                    movie.rom_hash = Some(parse_hex_u64(value).ok_or_else(|| invalid("bad hash"))?)
+                }
+                "savestate" => {
+                    let state = value
+                        .strip_prefix("0x")
+                        .and_then(from_hex)
+                        .ok_or_else(|| invalid("only hex save states from this emulator load"))?;
+                    movie.start = MovieStart::SaveState(state);
+                }
+                "ramHash" => {
+                    let (frame, ram_hash) = value
+                        .split_once(' ')
+                        .and_then(|(frame, ram_hash)| {
+                            Some((frame.parse().ok()?, parse_hex_u64(ram_hash)?))
+                        })
+                        .ok_or_else(|| invalid("expected ramHash <frame> <hash>"))?;
+                    movie.ram_hashes.insert(frame, ram_hash);
+                }
+                // Everything else describes hardware we always emulate the same way.
+                _ => {}
+            }
+        }
+
+        Ok(movie)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+    Finished,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl fmt::Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "movie desynced at frame {}: RAM hash is {:016X}, recording had {:016X}",
+            self.frame, self.actual, self.expected
+        )
+    }
+}
+
+// A movie being recorded or played back on a machine.
+pub struct MovieSession {
+    pub movie: Movie,
+    pub mode: MovieMode,
+    pub frame: usize, // the movie frame about to be emulated
+    // Movie frame = PPU frame + frame_offset, used to find our place again after a state is loaded.
+    frame_offset: i64,
+}
+
+impl MovieSession {
+    // Starts recording a new movie, putting the machine in the movie's starting state. The Famicom
+    // microphone isn't part of FM2's input, so it doesn't make it into the movie.
+    pub fn record(nes: &mut Nes, mut movie: Movie) -> Result<Self, Error> {
+        // FM2 only knows controllers and the Zapper
+        for port in 0..2 {
+            match nes.device(port) {
+                Device::None
+                | Device::Standard
+                | Device::FourScore
+                | Device::FamicomFourPlayer
+                | Device::Zapper => {}
+                device => {
+                    return Err(invalid_data(format!(
+                        "FM2 movies can't record {}",
+                        device.name()
+                    )))
+                }
+            }
+        }
+
+        if nes.expansion() != Expansion::None {
+            return Err(invalid_data(format!(
+                "FM2 movies can't record {}",
+                nes.expansion().name()
+            )));
+        }
+
+        movie.four_score = nes.players() > 2;
+        movie.zapper = [0, 1].map(|port| nes.device(port) == Device::Zapper);
+        if movie.four_score && movie.zapper.contains(&true) {
+            return Err(invalid_data(String::from(
+                "FM2 can't have a Zapper alongside four players",
+            )));
+        }
+        Self::start(nes, movie, MovieMode::Recording)
+    }
+
+    pub fn play(nes: &mut Nes, movie: Movie) -> Result<Self, Error> {
+        if movie
+            .rom_hash
+            .is_some_and(|rom_hash| rom_hash != nes.cpu.bus.rom.hash)
+        {
+            return Err(invalid_data(String::from(
+                "movie was recorded with a different ROM",
+            )));
+        }
+
+        // Input for the wrong devices would just be dropped and the movie would desync
+        for port in 0..2 {
+            let device = nes.device(port);
+            let (matches, expected) = if movie.zapper[port] {
+                (device == Device::Zapper, "zapper")
+            } else if movie.four_score {
+                (
+                    matches!(device, Device::FourScore | Device::FamicomFourPlayer),
+                    "four_score or famicom_four_player",
+                )
+            } else {
+                (
+                    matches!(device, Device::None | Device::Standard),
+                    "standard",
+                )
+            };
+            if !matches {
+                return Err(invalid_data(format!(
+                    "movie needs {} in port {}, but {} is plugged in",
+                    expected,
+                    port + 1,
+                    device.name()
+                )));
+            }
+        }
+
+        Self::start(nes, movie, MovieMode::Playing)
+    }
+
+    fn start(nes: &mut Nes, movie: Movie, mode: MovieMode) -> Result<Self, Error> {
+        match &movie.start {
+            MovieStart::PowerOn => nes.power_cycle(),
+            MovieStart::SaveState(state) => nes.load_state(state)?,
+        }
+
+        Ok(Self {
+            movie,
+            mode,
+            frame: 0,
+            frame_offset: -(nes.ppu().frame as i64),
+        })
+    }
+
+    // Called at the start of every frame. While recording, `commands` are the resets the player
+    // asked for this frame; while playing, they come from the movie instead.
+    pub fn begin_frame(&mut self, nes: &mut Nes, commands: u8) -> Result<(), Desync> {
+        let frame = match self.mode {
+            MovieMode::Recording => MovieFrame {
+                commands,
+                players: nes.all_buttons(),
+                pointers: nes.all_pointers().map(Option::unwrap_or_default),
+            },
+            MovieMode::Playing => match self.movie.frames.get(self.frame) {
+                Some(&frame) => frame,
+                None => {
+                    self.mode = MovieMode::Finished;
+                    return Ok(());
+                }
+            },
+            MovieMode::Finished => return Ok(()),
+        };
+
+        if frame.commands & COMMAND_POWER != 0 {
+            nes.power_cycle();
+            self.frame_offset = self.frame as i64;
+        } else if frame.commands & COMMAND_RESET != 0 {
+            nes.reset();
+        }
+
+        let ram_hash = hash::fnv1a(&nes.cpu.bus.ram[..0x800]);
+        let checkpoint = self.frame % RAM_HASH_INTERVAL == 0;
+
+        let result = match self.mode {
+            MovieMode::Recording => {
+                if checkpoint {
+                    self.movie.ram_hashes.insert(self.frame, ram_hash);
+                }
+                self.movie.frames.push(frame);
+                Ok(())
+            }
+            _ => {
+                nes.set_all_buttons(frame.players);
+                for port in 0..2 {
+                    if self.movie.zapper[port] {
+                        nes.set_pointer(port, frame.pointers[port]);
+                    }
+                }
+                match self.movie.ram_hashes.get(&self.frame) {
+                    Some(&expected) if expected != ram_hash => Err(Desync {
+                        frame: self.frame,
+                        expected,
+                        actual: ram_hash,
+                    }),
+                    _ => Ok(()),
+                }
+            }
+        };
+
+        self.frame += 1;
+        result
+    }
+
+    // Finds our place in the movie again after a save state was loaded or the game was rewound.
+    // While recording, everything after that point is thrown away and counts as a rerecord.
+    // Returns false if the machine is at a point the movie doesn't cover.
+    pub fn sync_frame(&mut self, nes: &Nes) -> bool {
+        let frame = nes.ppu().frame as i64 + self.frame_offset;
+        if frame < 0 || frame as usize > self.movie.frames.len() {
+            return false;
+        }
+        let frame = frame as usize;
+
+        if self.mode == MovieMode::Recording {
+            self.movie.frames.truncate(frame);
+            self.movie.ram_hashes.retain(|&hashed, _| hashed < frame);
+            self.movie.rerecord_count += 1;
+        } else {
+            self.mode = MovieMode::Playing;
+        }
+        self.frame = frame;
+        true
+    }
+}
+
+pub(crate) fn buttons_to_fm2(buttons: Buttons) -> String {
+    FM2_BUTTONS
+        .iter()
+        .enumerate()
+        .map(|(i, &letter)| {
+            if buttons.0 & (0x80 >> i) != 0 {
+                char::from(letter)
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+// Anything other than '.' or ' ' counts as pressed. An empty field means no controller.
+pub(crate) fn buttons_from_fm2(field: &str) -> Option<Buttons> {
+    if field.is_empty() {
+        return Some(Buttons::NONE);
+    }
+    if field.len() != FM2_BUTTONS.len() {
+        return None;
+    }
+
+    Some(Buttons(
+        field
+            .bytes()
+            .enumerate()
+            .filter(|&(_, letter)| letter != b'.' && letter != b' ')
+            .fold(0, |buttons, (i, _)| buttons | (0x80 >> i)),
+    ))
+}
+
+// "x y trigger q z", of which only the first three mean anything to us.
+fn zapper_from_fm2(field: &str) -> Option<Pointer> {
+    let mut numbers = field.split_whitespace();
+    let mut number = || numbers.next()?.parse::<u8>().ok();
+    Some(Pointer {
+        x: number()?,
+        y: number()?,
+        button: number()? & 1 != 0,
+    })
+}
+
+fn parse_hex_u64(text: &str) -> Option<u64> {
+    u64::from_str_radix(text.strip_prefix("0x")?, 16).ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// FM2 wants a GUID to tell movies apart; it only has to be unique, not random.
+fn new_guid(rom_hash: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64);
+    let high = hash::fnv1a(&now.to_le_bytes());
+    let low = hash::fnv1a_continue(high, &rom_hash.to_le_bytes());
+
+    format!(
+        "{:08X}-{:04X}-{:04X}-{:04X}-{:012X}",
+        high >> 32,
+        (high >> 16) & 0xFFFF,
+        high & 0xFFFF,
+        low >> 48,
+        low & 0xFFFF_FFFF_FFFF
+    )
+}
+
+fn invalid_data(reason: String) -> Error {
+    Error::new(ErrorKind::InvalidData, reason)
+}
@@ -0,0 +1,296 @@
+use crate::bus::Bus;
+use crate::controller::{Buttons, ControllerPort, Device, Pointer, PLAYERS};
+use crate::cpu::{Cpu, Interrupt};
+use crate::expansion::{Expansion, ExpansionPort, KeyMatrix};
+use crate::ppu::Ppu;
+use crate::rom::Rom;
+use crate::savestate::{SaveState, StateWriter, Tag};
+
+use std::io;
+
+// The save state chunks of the devices in port 1 and port 2.
+const CONTROLLER_TAGS: [Tag; 2] = [*b"CTRL", *b"CTL2"];
+
+// The whole console, without any window or audio attached. The frontend and the test harnesses
+// both drive it the same way: hand it the input for a frame, then run it one CPU cycle at a time.
+pub struct Nes {
+    pub cpu: Cpu<Bus>,
+}
+
+impl Nes {
+    // A console with standard controllers plugged into both ports.
+    pub fn new(rom: Rom) -> Self {
+        let controllers = [Device::Standard.create(0), Device::Standard.create(1)];
+        let bus = Bus::new(rom, Ppu::new(), controllers);
+        Self { cpu: Cpu::new(bus) }
+    }
+
+    // Swaps the device in a port (0 or 1) for another one.
+    pub fn plug(&mut self, port: usize, device: Box<dyn ControllerPort>) {
+        self.cpu.bus.controllers[port] = device;
+    }
+
+    // Swaps the device in the Famicom's expansion port for another one.
+    pub fn plug_expansion(&mut self, device: Box<dyn ExpansionPort>) {
+        self.cpu.bus.expansion = device;
+    }
+
+    pub fn expansion(&self) -> Expansion {
+        self.cpu.bus.expansion.device()
+    }
+
+    // Keys held down on the expansion port's keyboard for the coming frame.
+    pub fn set_keys(&mut self, keys: KeyMatrix) {
+        self.cpu.bus.expansion.set_keys(keys);
+    }
+
+    // None if there's no keyboard plugged in.
+    pub fn keys(&self) -> Option<KeyMatrix> {
+        self.cpu.bus.expansion.keys()
+    }
+
+    // Whether the player is shouting into the Famicom's second controller for the coming frame.
+    pub fn set_microphone(&mut self, microphone: bool) {
+        self.cpu.bus.microphone = microphone;
+    }
+
+    pub fn microphone(&self) -> bool {
+        self.cpu.bus.microphone
+    }
+
+    // What's plugged into a port.
+    pub fn device(&self, port: usize) -> Device {
+        self.cpu.bus.controllers[port].device()
+    }
+
+    // Input for the coming frame, for player 0-3. Players 0 and 2 are on port 1, 1 and 3 on
+    // port 2; players 2 and 3 need a four player adapter to be there at all.
+    pub fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        self.cpu.bus.controllers[player % 2].set_buttons(player / 2, buttons);
+    }
+
+    pub fn buttons(&self, player: usize) -> Buttons {
+        self.cpu.bus.controllers[player % 2].buttons(player / 2)
+    }
+
+    // Every player's buttons at once.
+    pub fn all_buttons(&self) -> [Buttons; PLAYERS] {
+        let mut buttons = [Buttons::NONE; PLAYERS];
+        for (player, buttons) in buttons.iter_mut().enumerate() {
+            *buttons = self.buttons(player);
+        }
+        buttons
+    }
+
+    pub fn set_all_buttons(&mut self, buttons: [Buttons; PLAYERS]) {
+        for (player, &buttons) in buttons.iter().enumerate() {
+            self.set_buttons(player, buttons);
+        }
+    }
+
+    // Where the mouse points for the device in a port (0 or 1), for the coming frame.
+    pub fn set_pointer(&mut self, port: usize, pointer: Pointer) {
+        self.cpu.bus.controllers[port].set_pointer(pointer);
+    }
+
+    // None if the device in the port isn't aimed with the mouse.
+    pub fn pointer(&self, port: usize) -> Option<Pointer> {
+        self.cpu.bus.controllers[port].pointer()
+    }
+
+    pub fn all_pointers(&self) -> [Option<Pointer>; 2] {
+        [self.pointer(0), self.pointer(1)]
+    }
+
+    pub fn set_all_pointers(&mut self, pointers: [Option<Pointer>; 2]) {
+        for (port, pointer) in pointers.iter().enumerate() {
+            if let Some(pointer) = *pointer {
+                self.set_pointer(port, pointer);
+            }
+        }
+    }
+
+    // The mat buttons stepped on for the device in a port, for the coming frame.
+    pub fn set_mat(&mut self, port: usize, mat: u16) {
+        self.cpu.bus.controllers[port].set_mat(mat);
+    }
+
+    // None if the device in the port has no mat.
+    pub fn mat(&self, port: usize) -> Option<u16> {
+        self.cpu.bus.controllers[port].mat()
+    }
+
+    pub fn all_mats(&self) -> [Option<u16>; 2] {
+        [self.mat(0), self.mat(1)]
+    }
+
+    pub fn set_all_mats(&mut self, mats: [Option<u16>; 2]) {
+        for (port, mat) in mats.iter().enumerate() {
+            if let Some(mat) = *mat {
+                self.set_mat(port, mat);
+            }
+        }
+    }
+
+    // How many players the plugged in devices take, counting up to the last one: a Famicom four
+    // player adapter on port 1 only still makes it 3.
+    pub fn players(&self) -> usize {
+        let controllers = &self.cpu.bus.controllers;
+        (0..PLAYERS)
+            .rev()
+            .find(|&player| controllers[player % 2].players() > player / 2)
+            .map_or(0, |player| player + 1)
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.interrupt(Interrupt::Reset);
+    }
+
+    // Switches the console off and on again.
+    pub fn power_cycle(&mut self) {
+        self.cpu.bus.power_cycle();
+
+        let cpu = &mut self.cpu;
+        cpu.pc = 0;
+        cpu.sp = 0;
+        cpu.accumulator = 0;
+        cpu.x = 0;
+        cpu.y = 0;
+        cpu.set_status(0);
+        cpu.cycles_left = 0;
+        cpu.cycles_completed = 0;
+
+        self.reset();
+    }
+
+    // Runs one CPU cycle, and the three PPU cycles that happen alongside it. Returns true if the
+    // PPU finished a frame.
+    pub fn step(&mut self) -> bool {
+        self.cpu.step();
+
+        let mut new_frame = false;
+        for _ in 0..3 {
+            new_frame |= self.cpu.bus.ppu.step();
+        }
+        new_frame
+    }
+
+    pub fn run_frame(&mut self) {
+        while !self.step() {}
+    }
+
+    // Serializes the whole machine, see savestate.rs for the format. There is no APU yet, so there
+    // is no APU chunk either.
+    pub fn save_state(&self) -> Vec<u8> {
+        let bus = &self.cpu.bus;
+        let mut writer = StateWriter::new(bus.rom.hash);
+
+        writer.chunk(b"CPU ", |writer| self.cpu.save_state(writer));
+        writer.chunk(b"RAM ", |writer| writer.write_bytes(&bus.ram[..0x800]));
+        // NROM has no mapper registers, just the (optional) cartridge RAM.
+        writer.chunk(b"CART", |writer| {
+            writer.write_u8(bus.rom.mapper_number);
+            writer.write_bytes(&bus.prg_ram);
+        });
+        writer.chunk(b"PPU ", |writer| bus.ppu.save_state(writer));
+        writer.chunk(b"DMA ", |writer| bus.dma.save_state(writer));
+        writer.chunk(b"BUS ", |writer| {
+            writer.write_u8(bus.open_bus);
+            writer.write_option_u16(bus.last_read);
+        });
+        for (port, tag) in CONTROLLER_TAGS.iter().enumerate() {
+            writer.chunk(tag, |writer| {
+                writer.write_str(bus.controllers[port].device().name());
+                bus.controllers[port].save_state(writer);
+            });
+        }
+        writer.chunk(b"EXP ", |writer| {
+            writer.write_str(bus.expansion.device().name());
+            bus.expansion.save_state(writer);
+        });
+
+        writer.finish()
+    }
+
+    // Loads a state over the whole machine, or leaves it untouched if the state can't be loaded.
+    pub fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        let state = SaveState::parse(data, self.cpu.bus.rom.hash)?;
+        self.check_devices(&state)?;
+
+        // A chunk can still turn out to be short after the ones before it went in, so keep the
+        // machine as it was to put back.
+        let backup = self.save_state();
+        if let Err(err) = self.load_chunks(state) {
+            let backup = SaveState::parse(&backup, self.cpu.bus.rom.hash)
+                .expect("the state just saved can be parsed");
+            self.load_chunks(backup)
+                .expect("the state just saved can be loaded");
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    // States only load with the same devices plugged in as when they were saved, since every
+    // device saves something different.
+    fn check_devices(&self, state: &SaveState) -> io::Result<()> {
+        for (tag, reader) in &state.chunks {
+            let (what, plugged) = match CONTROLLER_TAGS.iter().position(|port_tag| port_tag == tag)
+            {
+                Some(port) => (
+                    format!("port {}", port + 1),
+                    self.cpu.bus.controllers[port].device().name(),
+                ),
+                None if tag == b"EXP " => (
+                    "the expansion port".to_string(),
+                    self.cpu.bus.expansion.device().name(),
+                ),
+                None => continue,
+            };
+            let saved = reader.clone().read_string()?;
+            if saved != plugged {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "save state has {} in {}, but {} is plugged in",
+                        saved, what, plugged
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn load_chunks(&mut self, state: SaveState) -> io::Result<()> {
+        for (tag, mut reader) in state.chunks {
+            let bus = &mut self.cpu.bus;
+            if CONTROLLER_TAGS.contains(&tag) || &tag == b"EXP " {
+                // The device name, already checked
+                reader.read_string()?;
+            }
+            match &tag {
+                b"CPU " => self.cpu.load_state(&mut reader)?,
+                b"RAM " => reader.read_bytes(&mut bus.ram[..0x800])?,
+                b"CART" => {
+                    let _mapper_number = reader.read_u8()?;
+                    reader.read_bytes(&mut bus.prg_ram)?;
+                }
+                b"PPU " => bus.ppu.load_state(&mut reader)?,
+                b"DMA " => bus.dma.load_state(&mut reader)?,
+                b"BUS " => {
+                    bus.open_bus = reader.read_u8()?;
+                    bus.last_read = reader.read_option_u16()?;
+                }
+                b"CTRL" => bus.controllers[0].load_state(&mut reader)?,
+                b"CTL2" => bus.controllers[1].load_state(&mut reader)?,
+                b"EXP " => bus.expansion.load_state(&mut reader)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.cpu.bus.ppu
+    }
+}
@@ -1,13 +1,27 @@
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-
 use crate::rom;
+use crate::savestate::{StateReader, StateWriter};
 
 use std::convert::TryFrom;
+use std::io;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
 
-const SCALE: u32 = 3;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    // How bright the color looks, 0-255 (Rec. 601 luma).
+    pub fn brightness(self) -> u8 {
+        let luma = 299 * u32::from(self.r) + 587 * u32::from(self.g) + 114 * u32::from(self.b);
+        (luma / 1000) as u8
+    }
+}
 
 pub const PALETTE: [Color; 0x40] = [
     Color {
@@ -396,6 +410,39 @@ pub const PALETTE: [Color; 0x40] = [
     }, //0X3F
 ];
 
+// How much each color emphasis bit dims the other two color channels, in thousandths.
+const EMPHASIS_ATTENUATION: u32 = 816;
+
+// The colors the PPU puts out for every 9-bit pixel: PALETTE with the color emphasis bits of
+// PPUMASK (red, green and blue in bits 6-8 of the pixel) applied. Each emphasis bit dims the color
+// channels other than its own, so with all three set everything is dimmed.
+pub const OUTPUT_PALETTE: [Color; 0x200] = output_palette();
+
+const fn output_palette() -> [Color; 0x200] {
+    let mut palette = [PALETTE[0]; 0x200];
+    let mut pixel = 0;
+    while pixel < 0x200 {
+        let color = PALETTE[pixel & 0x3F];
+        let emphasis = pixel >> 6;
+        palette[pixel] = Color {
+            r: emphasize(color.r, emphasis & !0b001 != 0),
+            g: emphasize(color.g, emphasis & !0b010 != 0),
+            b: emphasize(color.b, emphasis & !0b100 != 0),
+            a: color.a,
+        };
+        pixel += 1;
+    }
+    palette
+}
+
+const fn emphasize(channel: u8, dimmed: bool) -> u8 {
+    if dimmed {
+        (channel as u32 * EMPHASIS_ATTENUATION / 1000) as u8
+    } else {
+        channel
+    }
+}
+
 pub struct Ppu {
     pub ppuctrl: u8,
     pub ppumask: u8,
@@ -409,11 +456,12 @@ pub struct Ppu {
     pub two_write_partial: bool,
     pub vram: [u8; 0x4000],
     pub oam: [u8; 0x100],
-    pub canvas: Canvas<Window>,
+    pub framebuffer: Vec<u16>, // 9-bit pixel (see OUTPUT_PALETTE) of every pixel on screen
     pub scanline: u16,
     pub cycle: u16,
     pub nmi_waiting: bool,
     pub even_frame: bool,
+    pub frame: u64,
     pub pattern_table_shift_low: u16, // the low byte of this is where the parallel input is "shifted" in (latched)
     pub pattern_table_shift_high: u16,
     pub attribute_table_palette_shift_low: u8,
@@ -426,18 +474,40 @@ pub struct Ppu {
     pub decoded_pattern_table_low: u8,
     pub decoded_pattern_table_high: u8,
     pub mirror_type: rom::MirroringType,
+    pub secondary_oam: [u8; 0x20], // up to 8 sprites found for the next scanline
+    // The sprites on this scanline, fetched from secondary_oam at the end of the previous one
+    pub sprite_pattern_shift_low: [u8; 8],
+    pub sprite_pattern_shift_high: [u8; 8],
+    pub sprite_attributes: [u8; 8],
+    pub sprite_x_counters: [u8; 8], // dots left until the sprite starts shifting out its pixels
+    pub sprite_count: u8,
+    pub sprite_zero: bool, // slot 0 is the first sprite evaluated (usually OAM sprite 0)
+    evaluation: SpriteEvaluation,
 }
 
-impl Ppu {
-    pub fn new(video_subsystem: &sdl2::VideoSubsystem) -> Self {
-        let window = video_subsystem
-            .window("NES Terminal Window", 256 * SCALE, 240 * SCALE)
-            .position_centered()
-            .build()
-            .unwrap();
+// Where sprite evaluation is on the current scanline. During dots 65-256 the PPU reads primary OAM
+// on odd dots and writes secondary OAM on even ones, copying the (up to 8) sprites in range of the
+// scanline. It goes through OAM by sprite (n) and byte within the sprite (m), starting wherever
+// OAMADDR points, so a misaligned OAMADDR makes it take the wrong bytes for Y coordinates.
+#[derive(Clone, Copy, Default)]
+struct SpriteEvaluation {
+    n: u8,
+    m: u8,
+    latch: u8,          // the byte read on the last odd dot
+    secondary_addr: u8, // next byte of secondary OAM to write
+    copying: bool,      // the sprite at n is in range and its bytes are being copied
+    sprite_zero: bool,  // the first sprite evaluated is in secondary OAM
+    done: bool,         // all of OAM has been looked at, or the overflow flag has been set
+}
 
-        let canvas = window.into_canvas().build().unwrap();
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl Ppu {
+    pub fn new() -> Self {
         Self {
             ppuctrl: 0x0,
             ppumask: 0x0,
@@ -451,11 +521,12 @@ impl Ppu {
             two_write_partial: false,
             vram: [0; 0x4000],
             oam: [0; 0x100],
-            canvas,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
             scanline: 0x0,
             cycle: 0x0,
             nmi_waiting: false,
             even_frame: false,
+            frame: 0,
             pattern_table_shift_low: 0,
             pattern_table_shift_high: 0,
             attribute_table_palette_shift_low: 0,
@@ -468,23 +539,144 @@ impl Ppu {
             decoded_pattern_table_low: 0,
             decoded_pattern_table_high: 0,
             mirror_type: rom::MirroringType::FourScreen,
+            secondary_oam: [0xFF; 0x20],
+            sprite_pattern_shift_low: [0; 8],
+            sprite_pattern_shift_high: [0; 8],
+            sprite_attributes: [0; 8],
+            sprite_x_counters: [0; 8],
+            sprite_count: 0,
+            sprite_zero: false,
+            evaluation: SpriteEvaluation::default(),
+        }
+    }
+
+    // Everything except the mirroring, which comes from the cartridge.
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.ppuctrl);
+        writer.write_u8(self.ppumask);
+        writer.write_u8(self.ppustatus);
+        writer.write_u8(self.oamaddr);
+        writer.write_u8(self.oamdata);
+        writer.write_u8(self.ppudata_buffer);
+        writer.write_u16(self.ppuscroll);
+        writer.write_u16(self.ppuaddr);
+        writer.write_u8(self.fine_x);
+        writer.write_bool(self.two_write_partial);
+        writer.write_bytes(&self.vram);
+        writer.write_bytes(&self.oam);
+        // The low 8 bits of every pixel, then the 9th bits packed 8 pixels to a byte
+        let low: Vec<u8> = self.framebuffer.iter().map(|&pixel| pixel as u8).collect();
+        writer.write_bytes(&low);
+        for pixels in self.framebuffer.chunks(8) {
+            let high = pixels.iter().enumerate().fold(0, |high, (i, &pixel)| {
+                high | (((pixel >> 8) as u8 & 1) << i)
+            });
+            writer.write_u8(high);
+        }
+        writer.write_u16(self.scanline);
+        writer.write_u16(self.cycle);
+        writer.write_bool(self.nmi_waiting);
+        writer.write_bool(self.even_frame);
+        writer.write_u64(self.frame);
+        writer.write_u16(self.pattern_table_shift_low);
+        writer.write_u16(self.pattern_table_shift_high);
+        writer.write_u8(self.attribute_table_palette_shift_low);
+        writer.write_bool(self.attribute_table_palette_latch_low);
+        writer.write_u8(self.attribute_table_palette_shift_high);
+        writer.write_bool(self.attribute_table_palette_latch_high);
+        writer.write_u8(self.decoded_nametable_byte);
+        writer.write_bool(self.decoded_attribute_table_bit_high);
+        writer.write_bool(self.decoded_attribute_table_bit_low);
+        writer.write_u8(self.decoded_pattern_table_low);
+        writer.write_u8(self.decoded_pattern_table_high);
+        writer.write_bytes(&self.secondary_oam);
+        writer.write_bytes(&self.sprite_pattern_shift_low);
+        writer.write_bytes(&self.sprite_pattern_shift_high);
+        writer.write_bytes(&self.sprite_attributes);
+        writer.write_bytes(&self.sprite_x_counters);
+        writer.write_u8(self.sprite_count);
+        writer.write_bool(self.sprite_zero);
+        let evaluation = &self.evaluation;
+        writer.write_u8(evaluation.n);
+        writer.write_u8(evaluation.m);
+        writer.write_u8(evaluation.latch);
+        writer.write_u8(evaluation.secondary_addr);
+        writer.write_bool(evaluation.copying);
+        writer.write_bool(evaluation.sprite_zero);
+        writer.write_bool(evaluation.done);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.ppuctrl = reader.read_u8()?;
+        self.ppumask = reader.read_u8()?;
+        self.ppustatus = reader.read_u8()?;
+        self.oamaddr = reader.read_u8()?;
+        self.oamdata = reader.read_u8()?;
+        self.ppudata_buffer = reader.read_u8()?;
+        self.ppuscroll = reader.read_u16()?;
+        self.ppuaddr = reader.read_u16()?;
+        self.fine_x = reader.read_u8()?;
+        self.two_write_partial = reader.read_bool()?;
+        reader.read_bytes(&mut self.vram)?;
+        reader.read_bytes(&mut self.oam)?;
+        let mut low = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        reader.read_bytes(&mut low)?;
+        self.framebuffer = low.into_iter().map(u16::from).collect();
+        for pixels in self.framebuffer.chunks_mut(8) {
+            let high = reader.read_u8()?;
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                *pixel |= u16::from((high >> i) & 1) << 8;
+            }
         }
+        self.scanline = reader.read_u16()?;
+        self.cycle = reader.read_u16()?;
+        self.nmi_waiting = reader.read_bool()?;
+        self.even_frame = reader.read_bool()?;
+        self.frame = reader.read_u64()?;
+        self.pattern_table_shift_low = reader.read_u16()?;
+        self.pattern_table_shift_high = reader.read_u16()?;
+        self.attribute_table_palette_shift_low = reader.read_u8()?;
+        self.attribute_table_palette_latch_low = reader.read_bool()?;
+        self.attribute_table_palette_shift_high = reader.read_u8()?;
+        self.attribute_table_palette_latch_high = reader.read_bool()?;
+        self.decoded_nametable_byte = reader.read_u8()?;
+        self.decoded_attribute_table_bit_high = reader.read_bool()?;
+        self.decoded_attribute_table_bit_low = reader.read_bool()?;
+        self.decoded_pattern_table_low = reader.read_u8()?;
+        self.decoded_pattern_table_high = reader.read_u8()?;
+        reader.read_bytes(&mut self.secondary_oam)?;
+        reader.read_bytes(&mut self.sprite_pattern_shift_low)?;
+        reader.read_bytes(&mut self.sprite_pattern_shift_high)?;
+        reader.read_bytes(&mut self.sprite_attributes)?;
+        reader.read_bytes(&mut self.sprite_x_counters)?;
+        self.sprite_count = reader.read_u8()?;
+        self.sprite_zero = reader.read_bool()?;
+        self.evaluation = SpriteEvaluation {
+            n: reader.read_u8()?,
+            m: reader.read_u8()?,
+            latch: reader.read_u8()?,
+            secondary_addr: reader.read_u8()?,
+            copying: reader.read_bool()?,
+            sprite_zero: reader.read_bool()?,
+            done: reader.read_bool()?,
+        };
+        Ok(())
     }
 
     pub fn get_vram_byte_at(&self, addr: u16) -> u8 {
         let mut actual_addr = addr % 0x4000;
 
         // palettes are mirrored from 0x3F00 to 0x4000 every 0x20 bytes
-        if actual_addr >= 0x3F00 && actual_addr < 0x4000 {
+        if (0x3F00..0x4000).contains(&actual_addr) {
             actual_addr = ((actual_addr - 0x3F00) % 0x20) + 0x3F00;
         }
 
         // Data at addresses 0x3000-0x3EFF mirrors 0x2000-0x2EFF
-        if actual_addr >= 0x3000 && actual_addr < 0x3F00 {
+        if (0x3000..0x3F00).contains(&actual_addr) {
             actual_addr -= 0x1000;
         }
 
-        if actual_addr >= 0x2000 && actual_addr < 0x3000 {
+        if (0x2000..0x3000).contains(&actual_addr) {
             match self.mirror_type {
                 rom::MirroringType::FourScreen => actual_addr &= !0x0C00,
                 rom::MirroringType::Horizontal => actual_addr &= !0x0400,
@@ -493,7 +685,7 @@ impl Ppu {
         }
 
         // Mirror 0x3F0{0,4,8,C} at 0x3F1{0,4,8,C}
-        if actual_addr >= 0x3F00 && actual_addr < 0x4000 && actual_addr % 0x4 == 0 {
+        if (0x3F00..0x4000).contains(&actual_addr) && actual_addr % 0x4 == 0 {
             actual_addr = ((actual_addr - 0x3F00) % 0x10) + 0x3F00;
         }
 
@@ -509,11 +701,11 @@ impl Ppu {
         }
 
         // Data at addresses 0x3000-0x3EFF mirrors 0x2000-0x2EFF
-        if actual_addr >= 0x3000 && actual_addr < 0x3F00 {
+        if (0x3000..0x3F00).contains(&actual_addr) {
             actual_addr -= 0x1000;
         }
 
-        if actual_addr >= 0x2000 && actual_addr < 0x3000 {
+        if (0x2000..0x3000).contains(&actual_addr) {
             match self.mirror_type {
                 rom::MirroringType::FourScreen => actual_addr &= !0x0C00,
                 rom::MirroringType::Horizontal => actual_addr &= !0x0400,
@@ -522,7 +714,7 @@ impl Ppu {
         }
 
         // Mirror 0x3F0{0,4,8,C} at 0x3F1{0,4,8,C}
-        if actual_addr >= 0x3F00 && actual_addr < 0x4000 && actual_addr % 0x4 == 0 {
+        if (0x3F00..0x4000).contains(&actual_addr) && actual_addr % 0x4 == 0 {
             actual_addr = ((actual_addr - 0x3F00) % 0x10) + 0x3F00;
         }
 
@@ -537,7 +729,7 @@ impl Ppu {
         self.oam[usize::from(addr)] = val;
     }
 
-    fn get_current_pixel(&mut self, scanline: u16, dot: u16) -> Color {
+    fn get_current_pixel(&mut self, dot: u16) -> u8 {
         let palette_x_offset = 7 - self.fine_x; // fine_x of 0 means we want the highest bit of 8-bit attribute_table_palette_shift_{high,low}
         let pattern_x_offset = 15 - self.fine_x; // fine_x of 0 means we actually want the highest bit of 16-bit pattern_table_shift_{high,low}
 
@@ -548,215 +740,149 @@ impl Ppu {
             u8::try_from((self.pattern_table_shift_high >> pattern_x_offset) & 0x1).unwrap();
         let bit_4 = u8::try_from((self.pattern_table_shift_low >> pattern_x_offset) & 0x1).unwrap();
 
+        // PPUMASK bits 1 and 2 hide the background and sprites in the leftmost 8 pixels
+        let show_background =
+            self.ppumask & (1 << 3) != 0 && (dot >= 8 || self.ppumask & (1 << 1) != 0);
+        let show_sprites =
+            self.ppumask & (1 << 4) != 0 && (dot >= 8 || self.ppumask & (1 << 2) != 0);
+
         // If background rendering enabled, get the background pattern offset here. Otherwise,
         // the offset will always be 0 (for the default background).
-        let background_pattern_final =
-            if self.ppumask & (1 << 3) != 0 && !(bit_3 == 0 && bit_4 == 0) {
-                (bit_1 << 3) | (bit_2 << 2) | (bit_3 << 1) | bit_4
-            } else {
-                0
-            };
-
-        if scanline % 8 == 0 || dot % 8 == 0 {
-            //return PALETTE[0];
-        }
+        let background_pattern_final = if show_background && !(bit_3 == 0 && bit_4 == 0) {
+            (bit_1 << 3) | (bit_2 << 2) | (bit_3 << 1) | bit_4
+        } else {
+            0
+        };
 
         let background_palette_idx =
             self.get_vram_byte_at(0x3F00 + u16::from(background_pattern_final));
 
-        let square_sprites = (self.ppuctrl & 0x20) >> 5 == 0;
+        // Every sprite's X counter counts down to the sprite, then its shift registers shift out one
+        // pixel per dot. The first opaque pixel, from the lowest slot, is the one that shows.
+        let mut sprite_pixel = None;
+        for slot in 0..usize::from(self.sprite_count) {
+            if self.sprite_x_counters[slot] > 0 {
+                self.sprite_x_counters[slot] -= 1;
+                continue;
+            }
+
+            let pattern_low = (self.sprite_pattern_shift_low[slot] >> 7)
+                | ((self.sprite_pattern_shift_high[slot] >> 7) << 1);
+            self.sprite_pattern_shift_low[slot] <<= 1;
+            self.sprite_pattern_shift_high[slot] <<= 1;
 
-        for sprite_idx in 0..64usize {
-            let sprite_y = self.oam[sprite_idx * 4] as u16 + 1; // "Sprite data is delayed by one scanline; you must subtract 1 from the sprite's Y coordinate before writing it here."
-            let pattern_idx = self.oam[sprite_idx * 4 + 1]; // As such, we must add 1 to the sprite's stored Y address to compensate for this.
-            let attributes = self.oam[sprite_idx * 4 + 2];
-            let sprite_x = self.oam[sprite_idx * 4 + 3] as u16;
+            if pattern_low != 0 && sprite_pixel.is_none() {
+                sprite_pixel = Some((slot, pattern_low));
+            }
+        }
 
-            let sprite_flipped_horizontally = attributes & (1 << 6) != 0;
-            let sprite_flipped_vertically = attributes & (1 << 7) != 0;
+        if let Some((slot, pattern_low)) = sprite_pixel.filter(|_| show_sprites) {
+            let attributes = self.sprite_attributes[slot];
             let pattern_high = attributes & 0b00000011;
             let sprite_has_priority = (attributes & (1 << 5)) == 0;
 
-            if square_sprites
-                && dot >= sprite_x
-                && dot < (sprite_x + 8)
-                && scanline >= sprite_y
-                && scanline < (sprite_y + 8)
-            {
-                let sprite_height_offset = if !sprite_flipped_vertically {
-                    scanline - sprite_y
-                } else {
-                    7 - (scanline - sprite_y)
-                };
-
-                let sprite_pattern_start =
-                    ((u16::from(self.ppuctrl) << 9) & 0x1000) | (u16::from(pattern_idx) << 4);
-
-                let pattern_0 = self.get_vram_byte_at(sprite_pattern_start + sprite_height_offset);
-                let pattern_1 =
-                    self.get_vram_byte_at(sprite_pattern_start + sprite_height_offset + 8);
-
-                // We use this to calculate the offset into this "strip" of sprite data (the sprite
-                // pos along the x-axis). By default, this is 7 - (actual x - sprite start x).
-                // In the case where the sprite is horizontally flipped (bit 6 of attributes is
-                // active), we can simply use actual x - sprite start x.
-                let sprite_strip_offset = if !sprite_flipped_horizontally {
-                    7 - (dot - sprite_x)
-                } else {
-                    dot - sprite_x
-                };
-
-                // bit offset into pattern_0 and pattern_1 are overlaid
-                let pattern_low = ((pattern_0 >> sprite_strip_offset) & 0x1)
-                    | (((pattern_1 >> sprite_strip_offset) & 0x1) << 1);
-
-                let pattern_final = pattern_low | (pattern_high << 2);
-
-                // If the low bits 2 bits of the sprite idx (just the bits derived from the
-                // pattern), the sprite at this point is transparent.
-                if pattern_low == 0 {
-                    continue;
-                }
-
-                // If we hit a sprite, set sprite 0 hit
-                if sprite_idx == 0 && pattern_low != 0 && background_pattern_final != 0 {
-                    self.ppustatus |= 1 << 6;
-                }
-
-                let palette_idx = self.get_vram_byte_at(0x3F10 + u16::from(pattern_final));
-
-                // If we found a sprite, but it doesn't have priority AND we found a
-                // non-transparent background pixel, the background pixel will be rendered instead
-                // of this sprite pixel.
-                if !sprite_has_priority && background_pattern_final != 0 {
-                    break;
-                }
-
-                return PALETTE[usize::from(palette_idx)];
-            } else if !square_sprites
-                && dot >= sprite_x
-                && dot < (sprite_x + 8)
-                && scanline >= sprite_y
-                && scanline < (sprite_y + 16)
-            {
-                let sprite_height_offset = if !sprite_flipped_vertically {
-                    scanline - sprite_y
-                } else {
-                    15 - (scanline - sprite_y)
-                };
-
-                let sprite_pattern_start = ((u16::from(pattern_idx) << 12) & 0x1000)
-                    | ((u16::from(pattern_idx) << 4) & 0x0FE0)
-                    | ((sprite_height_offset << 1) & 0x10);
-
-                let pattern_0 =
-                    self.get_vram_byte_at(sprite_pattern_start + (sprite_height_offset & 0x7));
-                let pattern_1 =
-                    self.get_vram_byte_at(sprite_pattern_start + (sprite_height_offset & 0x7) + 8);
-
-                // We use this to calculate the offset into this "strip" of sprite data (the sprite
-                // pos along the x-axis). By default, this is 7 - (actual x - sprite start x).
-                // In the case where the sprite is horizontally flipped (bit 6 of attributes is
-                // active), we can simply use actual x - sprite start x.
-                let sprite_strip_offset = if !sprite_flipped_horizontally {
-                    7 - (dot - sprite_x)
-                } else {
-                    dot - sprite_x
-                };
-
-                // bit offset into pattern_0 and pattern_1 are overlaid
-                let pattern_low = ((pattern_0 >> sprite_strip_offset) & 0x1)
-                    | (((pattern_1 >> sprite_strip_offset) & 0x1) << 1);
+            // If we hit a sprite, set sprite 0 hit. It never happens on the last pixel of the
+            // scanline.
+            if slot == 0 && self.sprite_zero && background_pattern_final != 0 && dot != 255 {
+                self.ppustatus |= 1 << 6;
+            }
 
+            // If we found a sprite, but it doesn't have priority AND we found a non-transparent
+            // background pixel, the background pixel will be rendered instead of this sprite
+            // pixel.
+            if sprite_has_priority || background_pattern_final == 0 {
                 let pattern_final = pattern_low | (pattern_high << 2);
-
-                // If the low bits 2 bits of the sprite idx (just the bits derived from the
-                // pattern), the sprite at this point is transparent.
-                if pattern_low == 0 {
-                    continue;
-                }
-
-                // If we hit a sprite, set sprite 0 hit
-                if sprite_idx == 0 && pattern_low != 0 && background_pattern_final != 0 {
-                    self.ppustatus |= 1 << 6;
-                }
-
-                let palette_idx = self.get_vram_byte_at(0x3F10 + u16::from(pattern_final));
-
-                // If we found a sprite, but it doesn't have priority AND we found a
-                // non-transparent background pixel, the background pixel will be rendered instead
-                // of this sprite pixel.
-                if !sprite_has_priority && background_pattern_final != 0 {
-                    break;
-                }
-
-                return PALETTE[usize::from(palette_idx)];
+                return self.get_vram_byte_at(0x3F10 + u16::from(pattern_final)) & 0x3F;
             }
         }
 
-        PALETTE[usize::from(background_palette_idx)]
+        background_palette_idx & 0x3F
+    }
+
+    // The RGB color of one pixel of the framebuffer.
+    pub fn pixel_color(&self, x: usize, y: usize) -> Color {
+        OUTPUT_PALETTE[usize::from(self.framebuffer[y * SCREEN_WIDTH + x])]
+    }
+
+    // Looks up the RGB color of every pixel in the framebuffer.
+    pub fn framebuffer_rgb(&self) -> Vec<u8> {
+        self.framebuffer
+            .iter()
+            .flat_map(|&pixel| {
+                let color = OUTPUT_PALETTE[usize::from(pixel)];
+                [color.r, color.g, color.b]
+            })
+            .collect()
     }
 
     // pre-render scanline happens at 261
     // dot 0 is cycle 0
     pub fn step(&mut self) -> bool {
         // If we're at the part of the screen to be rendering:
-        if self.scanline <= 239 {
-            if self.cycle >= 2 && self.cycle <= 257 {
-                /* Psuedo-draw */
-
-                let dot = self.cycle - 2;
-                let curr_pixel_color = self.get_current_pixel(self.scanline, dot);
-
-                self.canvas.set_draw_color(curr_pixel_color);
-                self.canvas
-                    .fill_rect(Rect::new(
-                        dot as i32 * SCALE as i32,
-                        self.scanline as i32 * SCALE as i32,
-                        SCALE,
-                        SCALE,
-                    ))
-                    .unwrap();
+        if self.scanline <= 239 && self.cycle >= 2 && self.cycle <= 257 {
+            /* Psuedo-draw */
+
+            let dot = self.cycle - 2;
+            let mut curr_pixel = u16::from(self.get_current_pixel(dot));
+
+            // Greyscale keeps only the grey column of the palette, then the color emphasis bits
+            // go on top
+            if self.ppumask & 1 != 0 {
+                curr_pixel &= 0x30;
             }
+            curr_pixel |= u16::from(self.ppumask & 0xE0) << 1;
+
+            self.framebuffer[usize::from(self.scanline) * SCREEN_WIDTH + usize::from(dot)] =
+                curr_pixel;
         }
 
         // If rendering is enabled:
         if self.ppumask & 0x18 != 0 {
             // We only make memory accesses to PPU when rendering is active and on scanline 0-239
             // or 261 (pre-render scanline)
-            if self.scanline <= 239 || self.scanline == 261 {
-                if (self.cycle >= 2 && self.cycle <= 257)
-                    || (self.cycle >= 322 && self.cycle <= 337)
-                {
-                    self.pattern_table_shift_low <<= 1;
-                    self.pattern_table_shift_high <<= 1;
-
-                    // If latch is set, make sure we set the bit that would be shifted in
-                    self.attribute_table_palette_shift_low <<= 1;
-                    if self.attribute_table_palette_latch_low {
-                        self.attribute_table_palette_shift_low |= 0x01;
-                    }
+            if (self.scanline <= 239 || self.scanline == 261)
+                && ((self.cycle >= 2 && self.cycle <= 257)
+                    || (self.cycle >= 322 && self.cycle <= 337))
+            {
+                self.pattern_table_shift_low <<= 1;
+                self.pattern_table_shift_high <<= 1;
 
-                    self.attribute_table_palette_shift_high <<= 1;
-                    if self.attribute_table_palette_latch_high {
-                        self.attribute_table_palette_shift_high |= 0x01;
-                    }
+                // If latch is set, make sure we set the bit that would be shifted in
+                self.attribute_table_palette_shift_low <<= 1;
+                if self.attribute_table_palette_latch_low {
+                    self.attribute_table_palette_shift_low |= 0x01;
+                }
 
-                    if self.cycle % 8 == 0 {
-                        self.coarse_x_increment();
-                        self.decode_pattern_table_high();
-                    } else if self.cycle % 8 == 1 {
-                        self.reload_shift_registers();
-                    } else if self.cycle % 8 == 3 {
-                        self.decode_nametable_byte();
-                    } else if self.cycle % 8 == 4 {
-                        self.decode_attribute_table_byte();
-                    } else if self.cycle % 8 == 6 {
-                        self.decode_pattern_table_low();
-                    }
+                self.attribute_table_palette_shift_high <<= 1;
+                if self.attribute_table_palette_latch_high {
+                    self.attribute_table_palette_shift_high |= 0x01;
+                }
+
+                if self.cycle % 8 == 0 {
+                    self.coarse_x_increment();
+                    self.decode_pattern_table_high();
+                } else if self.cycle % 8 == 1 {
+                    self.reload_shift_registers();
+                } else if self.cycle % 8 == 3 {
+                    self.decode_nametable_byte();
+                } else if self.cycle % 8 == 4 {
+                    self.decode_attribute_table_byte();
+                } else if self.cycle % 8 == 6 {
+                    self.decode_pattern_table_low();
                 }
             }
 
+            if self.scanline <= 239 {
+                self.evaluate_sprites();
+            }
+
+            if (self.scanline <= 239 || self.scanline == 261)
+                && self.cycle >= 257
+                && self.cycle <= 320
+            {
+                self.fetch_sprites();
+            }
+
             if self.scanline <= 239 || self.scanline == 261 {
                 if self.cycle == 256 {
                     self.fine_y_increment();
@@ -786,12 +912,25 @@ impl Ppu {
             }
         }
 
+        // The sprites evaluated on this scanline are the ones drawn on the next. Nothing gets
+        // evaluated on the pre-render scanline or with rendering disabled, so no sprites then.
+        if self.cycle == 257 && (self.scanline <= 239 || self.scanline == 261) {
+            if self.scanline <= 239 && self.ppumask & 0x18 != 0 {
+                self.sprite_count = self.evaluation.secondary_addr / 4;
+                self.sprite_zero = self.evaluation.sprite_zero;
+            } else {
+                self.sprite_count = 0;
+                self.sprite_zero = false;
+            }
+        }
+
         if self.cycle == 1 {
             if self.scanline == 241 {
                 self.ppustatus |= 1 << 7; // set vblank at cycle 1 of scanline 241
                 self.nmi_waiting = (self.ppuctrl >> 7) & 0x1 != 0; //Nmi only occurs on vblank if ppuctrl bit 7 is set
             } else if self.scanline == 261 {
                 self.ppustatus &= !(1 << 6); // clear sprite 0 hit at cycle 1 of scaline 261 (pre-render line)
+                self.ppustatus &= !(1 << 5); // clear sprite overflow at cycle 1 of scanline 261 (pre-render line)
                 self.ppustatus &= !(1 << 7); // clear vblank at cycle 1 of scanline 261 (pre-render line)
             }
         }
@@ -811,7 +950,7 @@ impl Ppu {
             if self.scanline > 261 {
                 self.scanline = 0;
                 self.even_frame = !self.even_frame;
-                self.canvas.present();
+                self.frame += 1;
                 return true;
             }
         }
@@ -819,6 +958,149 @@ impl Ppu {
         false
     }
 
+    // Sprite evaluation for the next scanline, run on every dot of visible scanlines while
+    // rendering is enabled:
+    //
+    //   dots 1-64    secondary OAM is cleared to $FF
+    //   dots 65-256  sprites in range of the scanline are copied from OAM into secondary OAM
+    //
+    // Once 8 sprites are found the PPU keeps looking for a 9th to set the sprite overflow flag,
+    // but it wrongly increments m along with n for every sprite not in range, so it ends up
+    // comparing tile numbers, attributes and X coordinates against the scanline instead of Ys.
+    fn evaluate_sprites(&mut self) {
+        match self.cycle {
+            1..=64 if self.cycle % 2 == 0 => {
+                self.secondary_oam[usize::from(self.cycle / 2 - 1)] = 0xFF;
+            }
+            65..=256 => {
+                if self.cycle == 65 {
+                    self.evaluation = SpriteEvaluation {
+                        n: self.oamaddr >> 2,
+                        m: self.oamaddr & 0x3,
+                        ..SpriteEvaluation::default()
+                    };
+                }
+
+                let evaluation = &mut self.evaluation;
+                if self.cycle % 2 == 1 {
+                    evaluation.latch =
+                        self.oam[usize::from((evaluation.n * 4).wrapping_add(evaluation.m))];
+                    return;
+                }
+
+                if evaluation.done {
+                    // Keeps reading the Y of every sprite, but nothing is written anymore
+                    evaluation.n = (evaluation.n + 1) & 0x3F;
+                    evaluation.m = 0;
+                    return;
+                }
+
+                let height = if self.ppuctrl & (1 << 5) != 0 { 16 } else { 8 };
+                let y = u16::from(evaluation.latch);
+                let in_range = self.scanline >= y && self.scanline < y + height;
+
+                if evaluation.secondary_addr < 0x20 {
+                    // The Y coordinate is always written, but secondary OAM only moves on to the
+                    // next byte for sprites in range
+                    self.secondary_oam[usize::from(evaluation.secondary_addr)] = evaluation.latch;
+                    if !evaluation.copying && in_range {
+                        evaluation.copying = true;
+                        if self.cycle == 66 {
+                            evaluation.sprite_zero = true;
+                        }
+                    }
+
+                    if evaluation.copying {
+                        evaluation.m += 1;
+                        evaluation.secondary_addr += 1;
+                        if evaluation.secondary_addr % 4 != 0 {
+                            return;
+                        }
+                        evaluation.copying = false;
+                    }
+                    evaluation.n = (evaluation.n + 1) & 0x3F;
+                    evaluation.m = 0;
+                    evaluation.done = evaluation.n == 0;
+                } else if in_range {
+                    self.ppustatus |= 1 << 5;
+                    evaluation.done = true;
+                } else {
+                    // The hardware bug: m goes up too (without carrying into n)
+                    evaluation.n = (evaluation.n + 1) & 0x3F;
+                    evaluation.m = (evaluation.m + 1) & 0x3;
+                    evaluation.done = evaluation.n == 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Sprite fetches for the next scanline, 8 dots for each of the 8 slots of secondary OAM during
+    // dots 257-320. Each slot gets its attributes latched and X counter loaded, then the pattern
+    // fetches fill its shift registers (flipped horizontally already if need be). Slots past the
+    // sprites found still make their fetches, for tile $FF, but load transparent patterns.
+    fn fetch_sprites(&mut self) {
+        let slot = usize::from((self.cycle - 257) / 8);
+        let sprite = &self.secondary_oam[slot * 4..slot * 4 + 4];
+        let (y, tile, attributes, x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+        let found = slot < usize::from(self.evaluation.secondary_addr / 4) && self.scanline != 261;
+
+        match (self.cycle - 257) % 8 {
+            2 => self.sprite_attributes[slot] = attributes,
+            3 => self.sprite_x_counters[slot] = x,
+            5 | 7 => {
+                let tall_sprites = self.ppuctrl & (1 << 5) != 0;
+                let height = if tall_sprites { 16 } else { 8 };
+                let mut row = self.scanline.wrapping_sub(u16::from(y)) & (height - 1);
+                if attributes & (1 << 7) != 0 {
+                    row = height - 1 - row;
+                }
+
+                let pattern_addr = if tall_sprites {
+                    ((u16::from(tile) << 12) & 0x1000)
+                        | ((u16::from(tile) << 4) & 0x0FE0)
+                        | ((row << 1) & 0x10)
+                        | (row & 0x7)
+                } else {
+                    ((u16::from(self.ppuctrl) << 9) & 0x1000) | (u16::from(tile) << 4) | row
+                };
+
+                let high = (self.cycle - 257) % 8 == 7;
+                let mut pattern = self.get_vram_byte_at(pattern_addr + if high { 8 } else { 0 });
+                if !found {
+                    pattern = 0;
+                } else if attributes & (1 << 6) != 0 {
+                    pattern = pattern.reverse_bits();
+                }
+
+                if high {
+                    self.sprite_pattern_shift_high[slot] = pattern;
+                } else {
+                    self.sprite_pattern_shift_low[slot] = pattern;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // What reading OAMDATA returns. While sprite evaluation is running, that's whatever the PPU is
+    // reading itself rather than OAM at OAMADDR.
+    pub fn read_oamdata(&self) -> u8 {
+        if self.scanline <= 239 && self.ppumask & 0x18 != 0 {
+            match self.cycle {
+                1..=64 => return 0xFF,
+                65..=256 => return self.evaluation.latch,
+                257..=320 => {
+                    let slot = usize::from((self.cycle - 257) / 8);
+                    let byte = usize::from((self.cycle - 257) % 8).min(3);
+                    return self.secondary_oam[slot * 4 + byte];
+                }
+                _ => {}
+            }
+        }
+        self.oam[usize::from(self.oamaddr)]
+    }
+
     fn reload_shift_registers(&mut self) {
         // Clear the low 8 bits of the shift registers.
         self.pattern_table_shift_low &= 0xFF00;
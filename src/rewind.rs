@@ -0,0 +1,286 @@
+// Rewind history. Every `interval` frames the whole machine is snapshotted (as a save state), and
+// the controller input of every frame is kept. Rewinding to a frame loads the nearest snapshot at
+// or before it and re-emulates the frames in between with the recorded input, so any frame can be
+// reached, not just the snapshotted ones.
+//
+// Only the newest snapshot is kept whole. Every other one is stored as the XOR against the snapshot
+// after it, packed with run length encoding: most of the machine doesn't change within a few
+// frames, so the XOR is mostly zeros. Older snapshots are rebuilt by walking back from the newest.
+// Once the history is over its memory budget the oldest snapshots are dropped.
+
+use crate::controller::{Buttons, Pointer, PLAYERS};
+use crate::expansion::KeyMatrix;
+use crate::nes::Nes;
+
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RewindConfig {
+    pub interval: u64,    // frames between snapshots
+    pub max_bytes: usize, // memory budget for snapshots
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        Self {
+            interval: 10,
+            max_bytes: 32 * 1024 * 1024,
+        }
+    }
+}
+
+struct FrameInput {
+    frame: u64,
+    buttons: [Buttons; PLAYERS],
+    pointers: [Option<Pointer>; 2],
+    mats: [Option<u16>; 2],
+    keys: Option<KeyMatrix>,
+    microphone: bool,
+}
+
+impl FrameInput {
+    // Nothing pressed, aimed or stepped on, for a frame with no recorded input: every device that's
+    // plugged in reads the way it does at power on.
+    fn neutral(nes: &Nes, frame: u64) -> Self {
+        Self {
+            frame,
+            buttons: [Buttons::NONE; PLAYERS],
+            pointers: nes
+                .all_pointers()
+                .map(|pointer| pointer.map(|_| Pointer::default())),
+            mats: nes.all_mats().map(|mat| mat.map(|_| 0)),
+            keys: nes.keys().map(|_| KeyMatrix::default()),
+            microphone: false,
+        }
+    }
+
+    fn apply(&self, nes: &mut Nes) {
+        nes.set_all_buttons(self.buttons);
+        nes.set_all_pointers(self.pointers);
+        nes.set_all_mats(self.mats);
+        if let Some(keys) = self.keys {
+            nes.set_keys(keys);
+        }
+        nes.set_microphone(self.microphone);
+    }
+}
+
+struct Snapshot {
+    frame: u64,
+    len: usize,
+    // This snapshot XORed with the next newer one, run length encoded. Empty for the newest, which
+    // is kept whole in Rewind::newest instead.
+    delta: Vec<u8>,
+}
+
+pub struct Rewind {
+    pub config: RewindConfig,
+    snapshots: VecDeque<Snapshot>,
+    newest: Vec<u8>,
+    // Every frame's input since the oldest snapshot
+    inputs: VecDeque<FrameInput>,
+    bytes_used: usize,
+}
+
+impl Rewind {
+    pub fn new(config: RewindConfig) -> Self {
+        Self {
+            config,
+            snapshots: VecDeque::new(),
+            newest: Vec::new(),
+            inputs: VecDeque::new(),
+            bytes_used: 0,
+        }
+    }
+
+    // Called at the start of every frame, before it is emulated.
+    pub fn record_frame(&mut self, nes: &Nes) {
+        let frame = nes.ppu().frame;
+
+        // Loading a save state can jump back in time, past what's been recorded.
+        if self
+            .snapshots
+            .back()
+            .is_some_and(|newest| newest.frame > frame)
+        {
+            self.clear();
+        }
+
+        // After a rewind, record_frame sees the frames it already has again.
+        self.inputs.retain(|input| input.frame < frame);
+        self.inputs.push_back(FrameInput {
+            frame,
+            buttons: nes.all_buttons(),
+            pointers: nes.all_pointers(),
+            mats: nes.all_mats(),
+            keys: nes.keys(),
+            microphone: nes.microphone(),
+        });
+
+        let due = match self.snapshots.back() {
+            Some(newest) => frame >= newest.frame + self.config.interval,
+            None => true,
+        };
+        if due {
+            self.push_snapshot(frame, nes.save_state());
+        }
+    }
+
+    // Moves the machine back by one frame. Returns false if the history doesn't go back that far.
+    pub fn rewind_frame(&mut self, nes: &mut Nes) -> bool {
+        let target = match nes.ppu().frame.checked_sub(1) {
+            Some(target) => target,
+            None => return false,
+        };
+        self.rewind_to(nes, target)
+    }
+
+    // Puts the machine at the start of the given frame. Everything recorded after it is dropped.
+    pub fn rewind_to(&mut self, nes: &mut Nes, target: u64) -> bool {
+        match self.snapshots.front() {
+            Some(oldest) if oldest.frame <= target => {}
+            _ => return false,
+        }
+
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|newest| newest.frame > target)
+        {
+            self.pop_snapshot();
+        }
+        let snapshot_frame = self.snapshots.back().unwrap().frame;
+
+        nes.load_state(&self.newest)
+            .expect("rewind snapshot should always load");
+
+        for frame in snapshot_frame..target {
+            match self.inputs.iter().find(|input| input.frame == frame) {
+                Some(input) => input.apply(nes),
+                None => FrameInput::neutral(nes, frame).apply(nes),
+            }
+            nes.run_frame();
+        }
+
+        self.inputs.retain(|input| input.frame < target);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.newest = Vec::new();
+        self.inputs.clear();
+        self.bytes_used = 0;
+    }
+
+    // How far back the history currently goes.
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.snapshots.front().map(|snapshot| snapshot.frame)
+    }
+
+    pub fn bytes_used(&self) -> usize {
+        self.bytes_used + self.newest.len()
+    }
+
+    fn push_snapshot(&mut self, frame: u64, state: Vec<u8>) {
+        if let Some(previous) = self.snapshots.back_mut() {
+            previous.delta = run_length_encode(&xor(&self.newest, &state));
+            self.bytes_used += previous.delta.len();
+        }
+
+        self.snapshots.push_back(Snapshot {
+            frame,
+            len: state.len(),
+            delta: Vec::new(),
+        });
+        self.newest = state;
+
+        while self.bytes_used() > self.config.max_bytes && self.snapshots.len() > 1 {
+            let oldest = self.snapshots.pop_front().unwrap();
+            self.bytes_used -= oldest.delta.len();
+        }
+        let oldest_frame = self.snapshots.front().unwrap().frame;
+        self.inputs.retain(|input| input.frame >= oldest_frame);
+    }
+
+    // Drops the newest snapshot, rebuilding the one before it as the new newest.
+    fn pop_snapshot(&mut self) {
+        self.snapshots.pop_back();
+
+        if let Some(previous) = self.snapshots.back_mut() {
+            let mut state = xor(&self.newest, &run_length_decode(&previous.delta));
+            state.truncate(previous.len);
+
+            self.bytes_used -= previous.delta.len();
+            previous.delta = Vec::new();
+            self.newest = state;
+        } else {
+            self.newest = Vec::new();
+        }
+    }
+}
+
+// XORs two buffers, treating the shorter one as if it were padded with zeros.
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).unwrap_or(&0) ^ b.get(i).unwrap_or(&0))
+        .collect()
+}
+
+// PackBits: a header byte n of 0-127 is followed by n + 1 literal bytes, a header of 129-255 by a
+// single byte repeated 257 - n times.
+fn run_length_encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(128)
+            .take_while(|&&byte| byte == data[i])
+            .count();
+
+        if run >= 2 {
+            encoded.push((257 - run) as u8);
+            encoded.push(data[i]);
+            i += run;
+        } else {
+            // Literals continue until the next run of at least 2.
+            let start = i;
+            while i < data.len()
+                && i - start < 128
+                && !(i + 1 < data.len() && data[i] == data[i + 1])
+            {
+                i += 1;
+            }
+            encoded.push((i - start - 1) as u8);
+            encoded.extend_from_slice(&data[start..i]);
+        }
+    }
+
+    encoded
+}
+
+fn run_length_decode(encoded: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut i = 0;
+
+    while i < encoded.len() {
+        let header = encoded[i];
+        match header {
+            0..=127 => {
+                let len = usize::from(header) + 1;
+                data.extend_from_slice(&encoded[i + 1..i + 1 + len]);
+                i += 1 + len;
+            }
+            128 => i += 1,
+            _ => {
+                let len = 257 - usize::from(header);
+                data.extend(std::iter::repeat_n(encoded[i + 1], len));
+                i += 2;
+            }
+        }
+    }
+
+    data
+}
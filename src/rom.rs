@@ -1,3 +1,5 @@
+use crate::hash;
+
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{Error, ErrorKind, Read};
@@ -10,6 +12,7 @@ pub struct Rom {
     pub mirroring: MirroringType,
     pub battery_backed_ram: bool,
     pub trainer: bool,
+    pub hash: u64, // of the PRG and CHR data as loaded, identifies the game in save states
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -52,8 +55,8 @@ impl Rom {
             ));
         }
 
-        let prg_bytes = u32::try_from(prg_rom_banks).unwrap() * 0x4000;
-        let chr_bytes = u32::try_from(chr_rom_banks).unwrap() * 0x2000;
+        let prg_bytes = u32::from(prg_rom_banks) * 0x4000;
+        let chr_bytes = u32::from(chr_rom_banks) * 0x2000;
 
         let mut prg_rom = vec![0u8; usize::try_from(prg_bytes).unwrap()];
         f.read_exact(&mut prg_rom)?;
@@ -66,6 +69,8 @@ impl Rom {
         let file_metadata = f.metadata()?;
         assert!(file_metadata.len() == u64::from(prg_bytes + chr_bytes + 0x10));
 
+        let hash = hash::fnv1a_continue(hash::fnv1a(&prg_rom), &chr_rom);
+
         let res = Rom {
             prg_rom,
             chr_rom,
@@ -79,6 +84,7 @@ impl Rom {
             },
             battery_backed_ram: (rom_ctrl_byte_1 & (1 << 1)) != 0,
             trainer: (rom_ctrl_byte_1 & (1 << 2)) != 0,
+            hash,
         };
         println!("Mirroring: {:?}", res.mirroring);
         println!("Mapper Number: {}", res.mapper_number);
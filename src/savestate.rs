@@ -0,0 +1,223 @@
+// The save state file format. A state starts with a header:
+//
+//   8 bytes  "NESSTATE"
+//   u16      format version
+//   u64      hash of the ROM the state was made with (Rom::hash)
+//
+// followed by chunks, each a 4 byte tag, a u32 length and that many bytes of data. Every part of
+// the machine gets its own chunk. Loading skips chunks it doesn't recognize and leaves a part of the
+// machine alone if its chunk is missing, so adding state in a new chunk keeps old states loadable.
+// Changing the layout of an existing chunk bumps VERSION, and the loader for that chunk has to
+// handle both layouts (StateReader::version says which one it's looking at).
+//
+// All numbers are little endian.
+
+use std::io::{Error, ErrorKind};
+
+pub const MAGIC: &[u8; 8] = b"NESSTATE";
+pub const VERSION: u16 = 1;
+
+pub type Tag = [u8; 4];
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    // Starts a new state with its header filled in.
+    pub fn new(rom_hash: u64) -> Self {
+        let mut writer = Self { data: Vec::new() };
+        writer.write_bytes(MAGIC);
+        writer.write_u16(VERSION);
+        writer.write_u64(rom_hash);
+        writer
+    }
+
+    // Writes a whole chunk, with whatever `write` puts in it as the data.
+    pub fn chunk(&mut self, tag: &Tag, write: impl FnOnce(&mut StateWriter)) {
+        self.write_bytes(tag);
+        let length_at = self.data.len();
+        self.write_u32(0);
+
+        write(self);
+
+        let length = (self.data.len() - length_at - 4) as u32;
+        self.data[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.data.push(val);
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.write_u8(val as u8);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.write_bytes(&val.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, val: u32) {
+        self.write_bytes(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.write_bytes(&val.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    // A u16 length, then the UTF-8 bytes.
+    pub fn write_str(&mut self, val: &str) {
+        self.write_u16(val.len() as u16);
+        self.write_bytes(val.as_bytes());
+    }
+
+    pub fn write_option_u8(&mut self, val: Option<u8>) {
+        self.write_bool(val.is_some());
+        self.write_u8(val.unwrap_or(0));
+    }
+
+    pub fn write_option_u16(&mut self, val: Option<u16>) {
+        self.write_bool(val.is_some());
+        self.write_u16(val.unwrap_or(0));
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[derive(Clone)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            version: VERSION,
+        }
+    }
+
+    // The format version of the state this data came from.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_slice(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    // Fills the whole of `bytes`.
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<(), Error> {
+        bytes.copy_from_slice(self.read_slice(bytes.len())?);
+        Ok(())
+    }
+
+    pub fn read_string(&mut self) -> Result<String, Error> {
+        let length = usize::from(self.read_u16()?);
+        String::from_utf8(self.read_slice(length)?.to_vec())
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "save state has an invalid string"))
+    }
+
+    pub fn read_option_u8(&mut self) -> Result<Option<u8>, Error> {
+        let present = self.read_bool()?;
+        let val = self.read_u8()?;
+        Ok(if present { Some(val) } else { None })
+    }
+
+    pub fn read_option_u16(&mut self) -> Result<Option<u16>, Error> {
+        let present = self.read_bool()?;
+        let val = self.read_u16()?;
+        Ok(if present { Some(val) } else { None })
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        self.read_bytes(&mut array)?;
+        Ok(array)
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let slice = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "save state is truncated"))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+// A save state that has been checked against the loaded ROM and split into its chunks.
+pub struct SaveState<'a> {
+    pub version: u16,
+    pub chunks: Vec<(Tag, StateReader<'a>)>,
+}
+
+impl<'a> SaveState<'a> {
+    pub fn parse(data: &'a [u8], rom_hash: u64) -> Result<Self, Error> {
+        let mut reader = StateReader::new(data);
+
+        let mut magic = [0; 8];
+        reader.read_bytes(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "not a save state"));
+        }
+
+        let version = reader.read_u16()?;
+        if version > VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "save state is version {}, newest supported is {}",
+                    version, VERSION
+                ),
+            ));
+        }
+
+        if reader.read_u64()? != rom_hash {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "save state was made with a different ROM",
+            ));
+        }
+
+        let mut chunks = Vec::new();
+        while !reader.is_empty() {
+            let tag = reader.read_array()?;
+            let length = reader.read_u32()? as usize;
+            let mut chunk = StateReader::new(reader.read_slice(length)?);
+            chunk.version = version;
+            chunks.push((tag, chunk));
+        }
+
+        Ok(Self { version, chunks })
+    }
+}
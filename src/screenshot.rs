@@ -0,0 +1,77 @@
+// Saving and loading screenshots of the framebuffer as PNG, and comparing them.
+
+use crate::hash;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+// A short way to tell two frames apart in a log.
+pub fn hash(pixels: &[u8]) -> u64 {
+    hash::fnv1a(pixels)
+}
+
+// Writes a full frame of RGB pixels (as returned by Ppu::framebuffer_rgb) to a PNG file.
+pub fn save_png(path: &Path, rgb: &[u8]) -> io::Result<()> {
+    assert_eq!(rgb.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(writer, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(rgb).map_err(io::Error::other)
+}
+
+// Reads back a PNG written by save_png as RGB pixels.
+pub fn load_png(path: &Path) -> io::Result<Vec<u8>> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+
+    let mut rgb = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut rgb).map_err(io::Error::other)?;
+    if info.color_type != png::ColorType::Rgb
+        || info.width as usize != SCREEN_WIDTH
+        || info.height as usize != SCREEN_HEIGHT
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} isn't a {}x{} RGB image",
+                path.display(),
+                SCREEN_WIDTH,
+                SCREEN_HEIGHT
+            ),
+        ));
+    }
+
+    rgb.truncate(info.buffer_size());
+    Ok(rgb)
+}
+
+// Builds an image highlighting where two frames differ: matching pixels are a dimmed copy of the
+// expected frame, differing ones are solid red.
+pub fn diff_image(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    expected
+        .chunks(3)
+        .zip(actual.chunks(3))
+        .flat_map(|(expected, actual)| {
+            if expected == actual {
+                [expected[0] / 4, expected[1] / 4, expected[2] / 4]
+            } else {
+                [0xFF, 0x00, 0x00]
+            }
+        })
+        .collect()
+}
+
+// Number of pixels that differ between two frames.
+pub fn count_differences(expected: &[u8], actual: &[u8]) -> usize {
+    expected
+        .chunks(3)
+        .zip(actual.chunks(3))
+        .filter(|(expected, actual)| expected != actual)
+        .count()
+}
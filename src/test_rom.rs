@@ -0,0 +1,116 @@
+// Runs test ROMs headless and reads back their result using blargg's protocol:
+//
+// $6000       status: 0x80 while running, 0x81 if the ROM wants to be reset, otherwise the final
+//             result code (0x00 means passed)
+// $6001-$6003 0xDE 0xB0 0x61 once $6000 is valid
+// $6004-      zero terminated text output
+
+use crate::nes::Nes;
+use crate::rom::Rom;
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+pub const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET_REQUESTED: u8 = 0x81;
+
+// The ROM wants at least 100ms between asking for a reset and getting one.
+const RESET_DELAY_FRAMES: u64 = 6;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TestRomStatus {
+    Passed,
+    Failed(u8), // the result code the ROM reported
+    TimedOut,   // still running, or never wrote the signature, when the frame limit was hit
+    Crashed(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct TestRomResult {
+    pub status: TestRomStatus,
+    pub message: String,
+    pub frames: u64,
+}
+
+// Runs the ROM until it reports a result or max_frames have gone by.
+pub fn run(rom: Rom, max_frames: u64) -> TestRomResult {
+    // Unsupported cartridges can panic while being loaded.
+    let mut nes = match panic::catch_unwind(AssertUnwindSafe(|| Nes::new(rom))) {
+        Ok(nes) => nes,
+        Err(panic) => {
+            return TestRomResult {
+                status: TestRomStatus::Crashed(panic_reason(panic)),
+                message: String::new(),
+                frames: 0,
+            }
+        }
+    };
+    nes.reset();
+
+    let mut frames = 0;
+    let mut reset_at = None;
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        while frames < max_frames {
+            nes.run_frame();
+            frames += 1;
+
+            if !has_signature(&nes) {
+                continue;
+            }
+
+            match nes.cpu.bus.prg_ram[0] {
+                STATUS_RUNNING => {}
+                STATUS_RESET_REQUESTED => match reset_at {
+                    None => reset_at = Some(frames + RESET_DELAY_FRAMES),
+                    Some(frame) if frames >= frame => {
+                        reset_at = None;
+                        // Let the ROM see that it was reset rather than powered on.
+                        nes.cpu.bus.prg_ram[0] = STATUS_RUNNING;
+                        nes.reset();
+                    }
+                    Some(_) => {}
+                },
+                0x00 => return TestRomStatus::Passed,
+                code => return TestRomStatus::Failed(code),
+            }
+        }
+
+        TestRomStatus::TimedOut
+    }));
+
+    let status = outcome.unwrap_or_else(|panic| TestRomStatus::Crashed(panic_reason(panic)));
+
+    TestRomResult {
+        status,
+        message: message(&nes),
+        frames,
+    }
+}
+
+fn panic_reason(panic: Box<dyn Any + Send>) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|reason| reason.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default()
+}
+
+fn has_signature(nes: &Nes) -> bool {
+    nes.cpu.bus.prg_ram[1..4] == SIGNATURE
+}
+
+// The text the ROM has written starting at $6004.
+fn message(nes: &Nes) -> String {
+    if !has_signature(nes) {
+        return String::new();
+    }
+
+    nes.cpu.bus.prg_ram[4..]
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| char::from(byte))
+        .collect()
+}
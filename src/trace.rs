@@ -0,0 +1,332 @@
+use crate::cpu::{Cpu, CpuBus, Instruction};
+use crate::disassembler;
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
+
+// Snapshot of the machine right before an instruction executes.
+#[derive(Clone, Debug)]
+pub struct TraceState {
+    pub pc: u16,
+    pub accumulator: u8,
+    pub x: u8,
+    pub y: u8,
+    pub status: u8,
+    pub sp: u8,
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+    pub cycles: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    // Exactly what nestest.log contains: raw bytes, disassembly with operand values, registers,
+    // PPU position and CPU cycle count.
+    Nestest,
+    // Just the PC and registers. Much cheaper, since nothing has to be disassembled.
+    Registers,
+}
+
+pub enum TraceSink {
+    File(BufWriter<File>),
+    Callback(Box<dyn FnMut(&str)>),
+    // Only keeps the most recent lines, so they can be dumped after a crash.
+    RingBuffer {
+        lines: VecDeque<String>,
+        capacity: usize,
+    },
+}
+
+pub enum TraceCondition {
+    PcRange(RangeInclusive<u16>),
+    FrameRange(RangeInclusive<u64>),
+    Expression(Box<dyn Fn(&TraceState) -> bool>),
+}
+
+impl TraceCondition {
+    pub fn matches(&self, state: &TraceState) -> bool {
+        match self {
+            TraceCondition::PcRange(range) => range.contains(&state.pc),
+            TraceCondition::FrameRange(range) => range.contains(&state.frame),
+            TraceCondition::Expression(expression) => expression(state),
+        }
+    }
+}
+
+pub struct Tracer {
+    pub sink: TraceSink,
+    pub format: TraceFormat,
+    // Tracing turns on once the start condition matches (immediately if there is none), and turns
+    // back off once the stop condition matches.
+    pub start: Option<TraceCondition>,
+    pub stop: Option<TraceCondition>,
+    pub active: bool,
+}
+
+impl Tracer {
+    pub fn new(sink: TraceSink, format: TraceFormat) -> Self {
+        Self {
+            sink,
+            format,
+            start: None,
+            stop: None,
+            active: true,
+        }
+    }
+
+    pub fn to_file(path: &str, format: TraceFormat) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(TraceSink::File(BufWriter::new(file)), format))
+    }
+
+    pub fn to_callback(callback: Box<dyn FnMut(&str)>, format: TraceFormat) -> Self {
+        Self::new(TraceSink::Callback(callback), format)
+    }
+
+    // Keeps the last `capacity` lines, which has to be at least 1.
+    pub fn ring_buffer(capacity: usize, format: TraceFormat) -> Self {
+        assert!(
+            capacity > 0,
+            "a trace ring buffer needs room for at least one line"
+        );
+        Self::new(
+            TraceSink::RingBuffer {
+                lines: VecDeque::with_capacity(capacity),
+                capacity,
+            },
+            format,
+        )
+    }
+
+    pub fn start_when(mut self, condition: TraceCondition) -> Self {
+        self.start = Some(condition);
+        self.active = false;
+        self
+    }
+
+    pub fn stop_when(mut self, condition: TraceCondition) -> Self {
+        self.stop = Some(condition);
+        self
+    }
+
+    // Records the instruction the CPU is about to execute, if the start/stop conditions allow it.
+    pub fn trace<B: CpuBus>(&mut self, cpu: &Cpu<B>, instruction: &Instruction) {
+        let state = capture_state(cpu);
+
+        if !self.active {
+            match &self.start {
+                Some(start) if start.matches(&state) => self.active = true,
+                _ => return,
+            }
+        }
+
+        if let Some(stop) = &self.stop {
+            if stop.matches(&state) {
+                self.active = false;
+                return;
+            }
+        }
+
+        let line = match self.format {
+            TraceFormat::Nestest => format_nestest_line(cpu, instruction, &state),
+            TraceFormat::Registers => format_registers(&state),
+        };
+
+        match &mut self.sink {
+            TraceSink::File(writer) => {
+                // Tracing is a debugging aid, so failing to write shouldn't bring the emulator down.
+                let _ = writeln!(writer, "{}", line);
+            }
+            TraceSink::Callback(callback) => callback(&line),
+            TraceSink::RingBuffer { lines, capacity } => {
+                while lines.len() >= *capacity {
+                    lines.pop_front();
+                }
+                lines.push_back(line);
+            }
+        }
+    }
+
+    // Writes out everything currently held in the ring buffer, oldest line first. Does nothing for
+    // other sinks.
+    pub fn dump(&self, writer: &mut dyn Write) -> std::io::Result<()> {
+        if let TraceSink::RingBuffer { lines, .. } = &self.sink {
+            for line in lines {
+                writeln!(writer, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.sink {
+            TraceSink::File(writer) => writer.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub fn capture_state<B: CpuBus>(cpu: &Cpu<B>) -> TraceState {
+    let (scanline, dot, frame) = cpu.bus.ppu_position();
+    TraceState {
+        pc: cpu.pc,
+        accumulator: cpu.accumulator,
+        x: cpu.x,
+        y: cpu.y,
+        status: cpu.get_status(false),
+        sp: cpu.sp,
+        scanline,
+        dot,
+        frame,
+        cycles: cpu.cycles_completed,
+    }
+}
+
+fn format_registers(state: &TraceState) -> String {
+    format!(
+        "{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        state.pc,
+        state.accumulator,
+        state.x,
+        state.y,
+        state.status,
+        state.sp,
+        state.scanline,
+        state.dot,
+        state.cycles
+    )
+}
+
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+pub fn format_nestest_line<B: CpuBus>(
+    cpu: &Cpu<B>,
+    instruction: &Instruction,
+    state: &TraceState,
+) -> String {
+    let opcode = cpu.bus.peek_byte_at(state.pc);
+    let bytes = (0..instruction.length())
+        .map(|offset| {
+            format!(
+                "{:02X}",
+                cpu.bus.peek_byte_at(state.pc.wrapping_add(offset))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let prefix = if disassembler::is_unofficial(opcode, instruction.opcode) {
+        '*'
+    } else {
+        ' '
+    };
+    let text = disassembler::format_instruction_with_state(cpu, instruction, state.pc);
+
+    format!(
+        "{:04X}  {:<8} {}{:<31} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        state.pc,
+        bytes,
+        prefix,
+        text,
+        state.accumulator,
+        state.x,
+        state.y,
+        state.status,
+        state.sp,
+        state.scanline,
+        state.dot,
+        state.cycles
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::FlatRam;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Runs NOPs from $8000 up to `end` with the tracer attached.
+    fn run(tracer: Tracer, end: u16) -> Cpu<FlatRam> {
+        let mut ram = FlatRam::new();
+        ram.memory[0x8000..].fill(0xEA);
+        let mut cpu = Cpu::new(ram);
+        cpu.pc = 0x8000;
+        cpu.tracer = Some(tracer);
+        while cpu.pc < end {
+            cpu.step();
+        }
+        cpu
+    }
+
+    // The PC of every traced line.
+    fn traced_pcs(text: &str) -> Vec<u16> {
+        text.lines()
+            .map(|line| u16::from_str_radix(&line[..4], 16).unwrap())
+            .collect()
+    }
+
+    fn collect_lines(tracer: impl FnOnce(Box<dyn FnMut(&str)>) -> Tracer, end: u16) -> Vec<u16> {
+        let lines = Rc::new(RefCell::new(String::new()));
+        let sink = Rc::clone(&lines);
+        run(
+            tracer(Box::new(move |line| {
+                sink.borrow_mut().push_str(line);
+                sink.borrow_mut().push('\n');
+            })),
+            end,
+        );
+        let text = lines.borrow();
+        traced_pcs(&text)
+    }
+
+    #[test]
+    fn traces_everything_without_conditions() {
+        let pcs = collect_lines(
+            |callback| Tracer::to_callback(callback, TraceFormat::Registers),
+            0x8004,
+        );
+        assert_eq!(pcs, [0x8000, 0x8001, 0x8002, 0x8003]);
+    }
+
+    #[test]
+    fn start_and_stop_conditions() {
+        let pcs = collect_lines(
+            |callback| {
+                Tracer::to_callback(callback, TraceFormat::Registers)
+                    .start_when(TraceCondition::PcRange(0x8002..=0x8002))
+                    .stop_when(TraceCondition::PcRange(0x8005..=0x8005))
+            },
+            0x8008,
+        );
+        // The stopping instruction isn't traced, and nothing starts it again
+        assert_eq!(pcs, [0x8002, 0x8003, 0x8004]);
+
+        let pcs = collect_lines(
+            |callback| {
+                Tracer::to_callback(callback, TraceFormat::Nestest).start_when(
+                    TraceCondition::Expression(Box::new(|state| state.pc & 1 != 0)),
+                )
+            },
+            0x8004,
+        );
+        assert_eq!(pcs, [0x8001, 0x8002, 0x8003]);
+    }
+
+    #[test]
+    fn ring_buffer_keeps_the_newest_lines() {
+        let cpu = run(Tracer::ring_buffer(3, TraceFormat::Registers), 0x8006);
+        let mut dump = Vec::new();
+        cpu.tracer.as_ref().unwrap().dump(&mut dump).unwrap();
+        let pcs = traced_pcs(&String::from_utf8(dump).unwrap());
+        assert_eq!(pcs, [0x8003, 0x8004, 0x8005]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn ring_buffer_needs_room() {
+        Tracer::ring_buffer(0, TraceFormat::Registers);
+    }
+}
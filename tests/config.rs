@@ -0,0 +1,156 @@
+use emulator::config::{Config, KeyChord};
+use emulator::controller::Device;
+use emulator::expansion::Expansion;
+use emulator::input::TurboRate;
+
+fn overrides(bindings: &[&str]) -> Vec<String> {
+    bindings.iter().map(|binding| binding.to_string()).collect()
+}
+
+#[test]
+fn defaults_are_valid() {
+    let config = Config::parse("", &[]).unwrap();
+
+    assert_eq!(config.player1.a, KeyChord::key("Z"));
+    assert_eq!(config.player2.up, KeyChord::key("W"));
+    assert_eq!(config.hotkeys.load_slot[0], KeyChord::key("F1"));
+    assert_eq!(config.hotkeys.save_slot[9].to_string(), "Shift+F10");
+}
+
+#[test]
+fn file_and_overrides_replace_defaults() {
+    let text = "[player1]\n\
+                a = \"Space\"\n\
+                \n\
+                [hotkeys]\n\
+                reset = \"Ctrl+Alt+R\"\n";
+    let config = Config::parse(text, &overrides(&["player1.b=Left Alt", "player1.a=N"])).unwrap();
+
+    assert_eq!(config.player1.a, KeyChord::key("N"));
+    assert_eq!(config.player1.b, KeyChord::key("Left Alt"));
+    assert_eq!(config.player1.start, KeyChord::key("Return"));
+    assert_eq!(config.hotkeys.reset.to_string(), "Ctrl+Alt+R");
+}
+
+#[test]
+fn rejects_invalid_configs() {
+    let error = |text: &str, bindings: &[&str]| {
+        Config::parse(text, &overrides(bindings))
+            .unwrap_err()
+            .to_string()
+    };
+
+    // The same key twice
+    assert!(error("", &["player2.a=Z"]).contains("player1.a and player2.a"));
+    // Unknown buttons and tables
+    assert!(error("[player1]\nturbo = \"T\"\n", &[]).contains("turbo"));
+    assert!(error("[player5]\n", &[]).contains("player5"));
+    // Modifiers only mean something for hotkeys
+    assert!(error("", &["player1.a=Shift+Z"]).contains("modifiers"));
+    assert!(error("", &["hotkeys.pause=Super+P"]).contains("Super"));
+    // Every slot needs a key
+    assert!(error("[hotkeys]\nsave_slot = [\"F1\"]\n", &[]).contains("needs 10"));
+    // Broken TOML and overrides
+    assert!(!error("[player1", &[]).is_empty());
+    assert!(error("", &["player1.a"]).contains("expected"));
+}
+
+#[test]
+fn parses_chords() {
+    let chord = KeyChord::parse("Ctrl+Shift+F5").unwrap();
+    assert!(chord.ctrl && chord.shift && !chord.alt);
+    assert_eq!(chord.key, "F5");
+
+    assert_eq!(KeyChord::parse("Shift++").unwrap().key, "+");
+    assert!(KeyChord::parse("Shift+").is_err());
+}
+
+#[test]
+fn turbo_and_macros() {
+    let text = "[player2]\n\
+                a = \"H\"\n\
+                \n\
+                [turbo.a]\n\
+                on = 3\n\
+                \n\
+                [macros]\n\
+                dash = \"Ctrl+1\"\n";
+    let config = Config::parse(
+        text,
+        &overrides(&["player1.turbo.a=N", "turbo.a.off=4", "turbo.b.on=1"]),
+    )
+    .unwrap();
+
+    // The rest of player 2 keeps player 2's defaults
+    assert_eq!(config.player2.a, KeyChord::key("H"));
+    assert_eq!(config.player2.b, KeyChord::key("F"));
+    assert_eq!(config.player2.turbo.a, Some(KeyChord::key("T")));
+
+    assert_eq!(config.player1.turbo.a, Some(KeyChord::key("N")));
+    assert_eq!(config.turbo.a, TurboRate { on: 3, off: 4 });
+    assert_eq!(config.turbo.b, TurboRate { on: 1, off: 2 });
+    assert_eq!(config.turbo.select, TurboRate::default());
+    assert_eq!(config.macros["dash"].to_string(), "Ctrl+1");
+
+    assert!(Config::parse("", &overrides(&["turbo.a.on=fast"])).is_err());
+    assert!(Config::parse("[turbo.b]\noff = 0\n", &[])
+        .unwrap_err()
+        .to_string()
+        .contains("turbo.b.off"));
+    assert!(Config::parse("[macros]\ndash = \"Z\"\n", &[]).is_err());
+}
+
+#[test]
+fn per_game_devices() {
+    let text = "[games.gauntlet2]\n\
+                port1 = \"four_score\"\n\
+                port2 = \"four_score\"\n";
+    let config = Config::parse(text, &[]).unwrap();
+
+    assert_eq!(config.games["gauntlet2"].port1, Some(Device::FourScore));
+    assert_eq!(config.player4.up, KeyChord::key("Keypad 8"));
+
+    assert!(Config::parse("[games.x]\nport1 = \"four_score\"\n", &[]).is_err());
+    assert!(Config::parse("[games.x]\nport1 = \"joystick\"\n", &[]).is_err());
+
+    let config = Config::parse(
+        "[games.family_basic]\nexpansion = \"family_basic_keyboard\"\n",
+        &[],
+    );
+    assert_eq!(
+        config.unwrap().games["family_basic"].expansion,
+        Some(Expansion::FamilyBasicKeyboard)
+    );
+    assert!(
+        Config::parse("", &overrides(&["hotkeys.microphone=Ctrl+M"]))
+            .unwrap_err()
+            .to_string()
+            .contains("modifiers")
+    );
+}
+
+#[test]
+fn power_pad_keys() {
+    let config = Config::parse("", &[]).unwrap();
+    assert_eq!(config.power_pad.buttons.len(), 12);
+    assert_eq!(config.power_pad.buttons[11], KeyChord::key("]"));
+
+    assert!(Config::parse("[power_pad]\nbuttons = [\"1\"]\n", &[])
+        .unwrap_err()
+        .to_string()
+        .contains("needs 12"));
+}
+
+#[test]
+fn digit_keys_can_be_bound_from_overrides() {
+    // Every digit has a default binding, so move player 3's select and start out of the way
+    let bindings = [
+        "player3.select=Page Up",
+        "player3.start=Page Down",
+        "player1.a=7",
+        "player2.turbo.b=8",
+    ];
+    let config = Config::parse("", &overrides(&bindings)).unwrap();
+    assert_eq!(config.player1.a, KeyChord::key("7"));
+    assert_eq!(config.player2.turbo.b, Some(KeyChord::key("8")));
+}
@@ -0,0 +1,417 @@
+use emulator::controller::{Buttons, Device, Pointer};
+use emulator::cpu::CpuBus;
+use emulator::expansion::{Expansion, KEYBOARD_ROWS};
+use emulator::nes::Nes;
+use emulator::ppu::SCREEN_WIDTH;
+use emulator::rom::Rom;
+
+fn balloon_fight() -> Nes {
+    let mut nes = Nes::new(Rom::new("roms/balloon.nes").unwrap());
+    nes.reset();
+    nes
+}
+
+// Strobes both ports and reads the 8 buttons of a port back, the way games do.
+fn read_port(nes: &mut Nes, addr: u16) -> u8 {
+    let bus = &mut nes.cpu.bus;
+    bus.set_byte_at(0x4016, 1);
+    bus.set_byte_at(0x4016, 0);
+    (0..8).fold(0, |buttons, bit| {
+        buttons | ((bus.get_byte_at(addr) & 1) << bit)
+    })
+}
+
+#[test]
+fn ports_are_read_independently() {
+    let mut nes = balloon_fight();
+    nes.set_buttons(0, Buttons::A | Buttons::LEFT);
+    nes.set_buttons(1, Buttons::START | Buttons::RIGHT);
+
+    assert_eq!(read_port(&mut nes, 0x4016), (Buttons::A | Buttons::LEFT).0);
+    assert_eq!(
+        read_port(&mut nes, 0x4017),
+        (Buttons::START | Buttons::RIGHT).0
+    );
+}
+
+#[test]
+fn unplugged_port_reads_nothing() {
+    let mut nes = balloon_fight();
+    nes.plug(1, Device::None.create(1));
+    nes.set_buttons(1, Buttons::START);
+
+    assert_eq!(read_port(&mut nes, 0x4017), 0);
+    assert_eq!(nes.buttons(1), Buttons::NONE);
+}
+
+// In a two player game of Balloon Fight, player 2's balloon fighter only moves if $4017 works.
+#[test]
+fn second_player_input_reaches_the_game() {
+    let run = |player_2: Buttons| {
+        let mut nes = balloon_fight();
+        for frame in 0..200 {
+            // Start a two player game, then have player 2 flap
+            nes.set_buttons(
+                0,
+                match frame {
+                    30..=34 => Buttons::SELECT,
+                    60..=64 => Buttons::START,
+                    _ => Buttons::NONE,
+                },
+            );
+            nes.set_buttons(
+                1,
+                if frame >= 120 {
+                    player_2
+                } else {
+                    Buttons::NONE
+                },
+            );
+            nes.run_frame();
+        }
+        nes.ppu().framebuffer.clone()
+    };
+
+    assert_ne!(run(Buttons::NONE), run(Buttons::A | Buttons::RIGHT));
+}
+
+#[test]
+fn reads_after_the_eighth_return_1() {
+    let mut nes = balloon_fight();
+    nes.set_buttons(0, Buttons::NONE);
+
+    assert_eq!(read_port(&mut nes, 0x4016), 0);
+    assert!((0..8).all(|_| nes.cpu.bus.get_byte_at(0x4016) & 1 == 1));
+}
+
+#[test]
+fn strobe_high_keeps_reporting_a() {
+    let mut nes = balloon_fight();
+    nes.set_buttons(0, Buttons::A);
+    let bus = &mut nes.cpu.bus;
+    bus.set_byte_at(0x4016, 1);
+
+    assert!((0..10).all(|_| bus.get_byte_at(0x4016) & 1 == 1));
+}
+
+// Only D0-D4 are driven, so the upper bits are left over from the last thing on the bus. For an
+// LDA $4016 that's the $40 of the address.
+#[test]
+fn upper_bits_are_open_bus() {
+    let mut nes = balloon_fight();
+    nes.set_buttons(1, Buttons::A);
+    let bus = &mut nes.cpu.bus;
+    bus.set_byte_at(0x4016, 1);
+    bus.set_byte_at(0x4016, 0);
+
+    bus.open_bus = 0x40;
+    assert_eq!(bus.get_byte_at(0x4016), 0x40);
+    bus.open_bus = 0x40;
+    assert_eq!(bus.get_byte_at(0x4017), 0x41);
+}
+
+// A DMC fetch landing on a controller read clocks the controller an extra time, so a button goes
+// missing.
+#[test]
+fn dmc_dma_deletes_a_bit() {
+    let mut nes = balloon_fight();
+    nes.set_buttons(0, Buttons::A | Buttons::SELECT);
+    let bus = &mut nes.cpu.bus;
+    bus.set_byte_at(0x4016, 1);
+    bus.set_byte_at(0x4016, 0);
+
+    assert_eq!(bus.get_byte_at(0x4016) & 1, 1); // A
+    bus.dma.request_dmc(0xC000);
+    let mut cycle = 0;
+    while bus.dma_cycle(cycle) {
+        cycle += 1;
+    }
+    // B was shifted out during the DMA, Select is next
+    assert_eq!(bus.get_byte_at(0x4016) & 1, 1);
+    assert_eq!(bus.get_byte_at(0x4016) & 1, 0);
+}
+
+// Reads a port `count` times after strobing, collecting D0 (or another data line) LSB first.
+fn read_bits(nes: &mut Nes, addr: u16, line: u8, count: usize) -> Vec<u8> {
+    let bus = &mut nes.cpu.bus;
+    bus.set_byte_at(0x4016, 1);
+    bus.set_byte_at(0x4016, 0);
+    (0..count)
+        .map(|_| (bus.get_byte_at(addr) >> line) & 1)
+        .collect()
+}
+
+fn bits(byte: u8) -> Vec<u8> {
+    (0..8).map(|bit| (byte >> bit) & 1).collect()
+}
+
+#[test]
+fn four_score_reads_two_controllers_then_a_signature() {
+    let mut nes = balloon_fight();
+    nes.plug(0, Device::FourScore.create(0));
+    nes.plug(1, Device::FourScore.create(1));
+    for (player, &buttons) in [Buttons::A, Buttons::B, Buttons::START, Buttons::RIGHT]
+        .iter()
+        .enumerate()
+    {
+        nes.set_buttons(player, buttons);
+    }
+    assert_eq!(nes.players(), 4);
+
+    let port1 = [
+        bits(Buttons::A.0),
+        bits(Buttons::START.0),
+        bits(0x08),
+        bits(0xFF),
+    ]
+    .concat();
+    let port2 = [
+        bits(Buttons::B.0),
+        bits(Buttons::RIGHT.0),
+        bits(0x04),
+        bits(0xFF),
+    ]
+    .concat();
+    assert_eq!(read_bits(&mut nes, 0x4016, 0, 32), port1);
+    assert_eq!(read_bits(&mut nes, 0x4017, 0, 32), port2);
+}
+
+#[test]
+fn famicom_four_player_uses_d1() {
+    let mut nes = balloon_fight();
+    nes.plug(0, Device::FamicomFourPlayer.create(0));
+    nes.set_buttons(0, Buttons::A);
+    nes.set_buttons(2, Buttons::SELECT);
+    // Player 4 has nowhere to go with a standard controller in port 2
+    nes.set_buttons(3, Buttons::START);
+    assert_eq!(nes.players(), 3);
+    assert_eq!(nes.buttons(3), Buttons::NONE);
+
+    assert_eq!(read_bits(&mut nes, 0x4016, 0, 8), bits(Buttons::A.0));
+    assert_eq!(read_bits(&mut nes, 0x4016, 1, 8), bits(Buttons::SELECT.0));
+}
+
+#[test]
+fn four_score_needs_both_ports() {
+    assert!(Device::check_ports([Device::FourScore, Device::Standard]).is_err());
+    assert!(Device::check_ports([Device::FourScore, Device::FourScore]).is_ok());
+    assert!(Device::check_ports([Device::FamicomFourPlayer, Device::Standard]).is_ok());
+}
+
+// A Zapper on port 2 aimed at (100, 50), with the frame drawn so far black except for a white
+// target around the aim. The PPU is put at the given point of the frame.
+fn zapper_at(button: bool, scanline: u16, cycle: u16) -> Nes {
+    let mut nes = balloon_fight();
+    nes.plug(1, Device::Zapper.create(1));
+    nes.set_pointer(
+        1,
+        Pointer {
+            x: 100,
+            y: 50,
+            button,
+        },
+    );
+
+    let ppu = &mut nes.cpu.bus.ppu;
+    for (i, pixel) in ppu.framebuffer.iter_mut().enumerate() {
+        let (x, y) = (i % SCREEN_WIDTH, i / SCREEN_WIDTH);
+        let on_target = (96..104).contains(&x) && (48..56).contains(&y);
+        *pixel = if on_target { 0x30 } else { 0x0F };
+    }
+    ppu.scanline = scanline;
+    ppu.cycle = cycle;
+    nes
+}
+
+const LIGHT: u8 = 1 << 3;
+const TRIGGER: u8 = 1 << 4;
+
+#[test]
+fn zapper_sees_light_just_after_the_beam_passes() {
+    let read = |scanline, cycle| {
+        zapper_at(false, scanline, cycle)
+            .cpu
+            .bus
+            .get_byte_at(0x4017)
+    };
+
+    // The target hasn't been drawn yet this frame
+    assert_eq!(read(40, 0) & LIGHT, LIGHT);
+    // Now it has
+    assert_eq!(read(50, 0) & LIGHT, 0);
+    assert_eq!(read(60, 200) & LIGHT, 0);
+    // And it's faded again
+    assert_eq!(read(120, 0) & LIGHT, LIGHT);
+}
+
+#[test]
+fn zapper_only_sees_what_its_aimed_at() {
+    let mut nes = zapper_at(false, 60, 0);
+    nes.set_pointer(
+        1,
+        Pointer {
+            x: 200,
+            y: 50,
+            button: false,
+        },
+    );
+    assert_eq!(nes.cpu.bus.get_byte_at(0x4017) & LIGHT, LIGHT);
+
+    // Aimed away from the screen
+    nes.set_pointer(
+        1,
+        Pointer {
+            x: 100,
+            y: 0xFF,
+            button: true,
+        },
+    );
+    assert_eq!(
+        nes.cpu.bus.get_byte_at(0x4017) & (LIGHT | TRIGGER),
+        LIGHT | TRIGGER
+    );
+}
+
+#[test]
+fn zapper_reports_the_trigger() {
+    let mut nes = zapper_at(true, 0, 0);
+    assert_eq!(nes.cpu.bus.get_byte_at(0x4017) & TRIGGER, TRIGGER);
+    nes.set_pointer(
+        1,
+        Pointer {
+            x: 100,
+            y: 50,
+            button: false,
+        },
+    );
+    assert_eq!(nes.cpu.bus.get_byte_at(0x4017) & TRIGGER, 0);
+    // Standard controllers don't take a pointer
+    assert_eq!(nes.pointer(0), None);
+}
+
+// Reads a serial value MSB first off one data line, the way Arkanoid reads the Vaus.
+fn read_serial(nes: &mut Nes, addr: u16, line: u8) -> u8 {
+    read_bits(nes, addr, line, 8)
+        .iter()
+        .fold(0, |value, &bit| (value << 1) | bit)
+}
+
+fn paddle(x: u8, button: bool) -> Pointer {
+    Pointer { x, y: 0, button }
+}
+
+#[test]
+fn arkanoid_reads_the_knob_inverted() {
+    let mut nes = balloon_fight();
+    nes.plug(1, Device::Arkanoid.create(1));
+
+    nes.set_pointer(1, paddle(0, false));
+    assert_eq!(!read_serial(&mut nes, 0x4017, 4), 0x54);
+    nes.set_pointer(1, paddle(255, true));
+    assert_eq!(!read_serial(&mut nes, 0x4017, 4), 0xF4);
+    assert_eq!(nes.cpu.bus.get_byte_at(0x4017) & (1 << 3), 1 << 3);
+}
+
+#[test]
+fn famicom_arkanoid_is_on_d1_of_both_registers() {
+    let mut nes = balloon_fight();
+    nes.plug(0, Device::FamicomArkanoid.create(0));
+    nes.plug(1, Device::FamicomArkanoid.create(1));
+    nes.set_buttons(0, Buttons::A);
+    for port in 0..2 {
+        nes.set_pointer(port, paddle(128, true));
+    }
+
+    assert_eq!(read_bits(&mut nes, 0x4016, 0, 8), bits(Buttons::A.0));
+    assert_eq!(read_bits(&mut nes, 0x4016, 1, 8), [1; 8]);
+    assert_eq!(!read_serial(&mut nes, 0x4017, 1), 0x54 + 80);
+}
+
+#[test]
+fn power_pad_shifts_out_on_d3_and_d4() {
+    let mut nes = balloon_fight();
+    nes.plug(1, Device::PowerPad.create(1));
+    // Buttons 1, 7 and 12
+    nes.set_mat(1, (1 << 0) | (1 << 6) | (1 << 11));
+
+    // D3 has 2, 1, 5, 9, 6, 10, 11, 7 and D4 has 4, 3, 12, 8, then 1s
+    assert_eq!(
+        read_bits(&mut nes, 0x4017, 3, 10),
+        [0, 1, 0, 0, 0, 0, 0, 1, 1, 1]
+    );
+    assert_eq!(read_bits(&mut nes, 0x4017, 4, 6), [0, 0, 1, 0, 1, 1]);
+}
+
+#[test]
+fn family_trainer_is_scanned_by_row() {
+    let mut nes = balloon_fight();
+    nes.plug(1, Device::FamilyTrainer.create(1));
+    // Buttons 1 and 8
+    nes.set_mat(1, (1 << 0) | (1 << 7));
+
+    let mut scan = |out: u8| {
+        let bus = &mut nes.cpu.bus;
+        bus.set_byte_at(0x4016, out);
+        bus.get_byte_at(0x4017) & 0x1E
+    };
+    // Pressed buttons read 0: button 1 on D4, button 8 on D1
+    assert_eq!(scan(0b110), 0x0E);
+    assert_eq!(scan(0b101), 0x1C);
+    assert_eq!(scan(0b011), 0x1E);
+    assert_eq!(scan(0b111), 0x1E);
+}
+
+#[test]
+fn some_devices_need_particular_ports() {
+    assert!(Device::check_ports([Device::FamicomArkanoid, Device::Standard]).is_err());
+    assert!(Device::check_ports([Device::FamicomArkanoid, Device::FamicomArkanoid]).is_ok());
+    assert!(Device::check_ports([Device::FamilyTrainer, Device::Standard]).is_err());
+    assert!(Device::check_ports([Device::Standard, Device::FamilyTrainer]).is_ok());
+    assert!(Device::check_ports([Device::Standard, Device::Arkanoid]).is_ok());
+}
+
+#[test]
+fn family_basic_keyboard_is_scanned_row_by_row() {
+    let mut nes = balloon_fight();
+    nes.plug_expansion(Expansion::FamilyBasicKeyboard.create());
+    let mut keys = [0; KEYBOARD_ROWS];
+    keys[0] = 1 << 2; // Return
+    keys[1] = 1 << 5; // -
+    nes.set_keys(keys);
+
+    let mut scan = |out: u8| {
+        let bus = &mut nes.cpu.bus;
+        bus.set_byte_at(0x4016, out);
+        bus.get_byte_at(0x4017) & 0x1E
+    };
+    // Enabled, back to row 0, column 0: Return is on D2
+    assert_eq!(scan(0b101), 0x1A);
+    // Column 1 of row 0, nothing held
+    assert_eq!(scan(0b110), 0x1E);
+    // Column 0 of row 1, then column 1 with - on D3
+    assert_eq!(scan(0b100), 0x1E);
+    assert_eq!(scan(0b110), 0x16);
+    // Disabled, the keyboard drives nothing
+    assert_eq!(scan(0b000), 0);
+}
+
+#[test]
+fn expansion_port_leaves_d0_to_the_controllers() {
+    let mut nes = balloon_fight();
+    nes.plug_expansion(Expansion::FamilyBasicKeyboard.create());
+    nes.set_buttons(1, Buttons::A);
+    let bus = &mut nes.cpu.bus;
+    bus.set_byte_at(0x4016, 0b101);
+    bus.set_byte_at(0x4016, 0b100);
+
+    assert_eq!(bus.get_byte_at(0x4017) & 0x1F, 0x1F);
+}
+
+#[test]
+fn microphone_is_on_4016_d2() {
+    let mut nes = balloon_fight();
+    nes.set_microphone(true);
+    assert_eq!(nes.cpu.bus.get_byte_at(0x4016) & (1 << 2), 1 << 2);
+    nes.set_microphone(false);
+    assert_eq!(nes.cpu.bus.get_byte_at(0x4016) & (1 << 2), 0);
+}
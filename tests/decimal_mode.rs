@@ -0,0 +1,96 @@
+// ADC and SBC with the decimal flag set, on the NES's 2A03 (which ignores it) and on a stock NMOS
+// 6502. The NMOS results follow Bruce Clark's "Decimal Mode" tutorial, including the flags and
+// what happens to digits that aren't valid BCD.
+
+use emulator::bus::FlatRam;
+use emulator::cpu::{Cpu, Variant};
+
+const ADC_IMMEDIATE: u8 = 0x69;
+const SBC_IMMEDIATE: u8 = 0xE9;
+
+// Runs one immediate mode ADC or SBC with the decimal flag set. Returns the accumulator and the
+// carry, zero, sign and overflow flags as "CZNV", with a '.' for each one that's clear.
+fn run(variant: Variant, opcode: u8, accumulator: u8, operand: u8, carry: bool) -> (u8, String) {
+    let mut ram = FlatRam::new();
+    ram.memory[0x8000] = opcode;
+    ram.memory[0x8001] = operand;
+
+    let mut cpu = Cpu::with_variant(ram, variant);
+    cpu.pc = 0x8000;
+    cpu.accumulator = accumulator;
+    cpu.carry = carry;
+    cpu.decimal = true;
+    cpu.step();
+
+    let flags = [
+        (cpu.carry, 'C'),
+        (cpu.zero, 'Z'),
+        (cpu.sign, 'N'),
+        (cpu.overflow, 'V'),
+    ];
+    let flags = flags
+        .iter()
+        .map(|&(set, name)| if set { name } else { '.' })
+        .collect();
+    (cpu.accumulator, flags)
+}
+
+fn outcome(accumulator: u8, flags: &str) -> (u8, String) {
+    (accumulator, flags.to_string())
+}
+
+#[test]
+fn ricoh_2a03_ignores_the_decimal_flag() {
+    let adc = |a, operand, carry| run(Variant::Ricoh2A03, ADC_IMMEDIATE, a, operand, carry);
+    let sbc = |a, operand, carry| run(Variant::Ricoh2A03, SBC_IMMEDIATE, a, operand, carry);
+
+    assert_eq!(adc(0x99, 0x01, false), outcome(0x9A, "..N."));
+    assert_eq!(adc(0x58, 0x46, true), outcome(0x9F, "..NV"));
+    assert_eq!(sbc(0x00, 0x01, true), outcome(0xFF, "..N."));
+    assert_eq!(sbc(0x46, 0x12, true), outcome(0x34, "C..."));
+}
+
+#[test]
+fn nmos_adc_in_decimal_mode() {
+    let adc = |a, operand, carry| run(Variant::Nmos6502, ADC_IMMEDIATE, a, operand, carry);
+
+    assert_eq!(adc(0x12, 0x34, false), outcome(0x46, "...."));
+    assert_eq!(adc(0x58, 0x46, true), outcome(0x05, "C.NV"));
+    // Z comes from the binary sum ($9A), N from the sum before the high digit is adjusted ($A0)
+    assert_eq!(adc(0x99, 0x01, false), outcome(0x00, "C.N."));
+    // ...so a decimal result of $00 can leave Z clear and $00 + $00 sets it
+    assert_eq!(adc(0x00, 0x00, false), outcome(0x00, ".Z.."));
+    // V is set like for a signed binary add of the half-adjusted sum: $79 + $01 = $80
+    assert_eq!(adc(0x79, 0x01, false), outcome(0x80, "..NV"));
+}
+
+#[test]
+fn nmos_adc_with_invalid_bcd_digits() {
+    let adc = |a, operand, carry| run(Variant::Nmos6502, ADC_IMMEDIATE, a, operand, carry);
+
+    assert_eq!(adc(0x1A, 0x00, false), outcome(0x20, "...."));
+    assert_eq!(adc(0x0F, 0x01, false), outcome(0x16, "...."));
+    assert_eq!(adc(0xFF, 0xFF, true), outcome(0x55, "C.N."));
+}
+
+#[test]
+fn nmos_sbc_in_decimal_mode() {
+    let sbc = |a, operand, carry| run(Variant::Nmos6502, SBC_IMMEDIATE, a, operand, carry);
+
+    assert_eq!(sbc(0x46, 0x12, true), outcome(0x34, "C..."));
+    assert_eq!(sbc(0x40, 0x13, true), outcome(0x27, "C..."));
+    assert_eq!(sbc(0x32, 0x02, false), outcome(0x29, "C..."));
+    // All flags are the binary ones: $00 - $01 = $FF
+    assert_eq!(sbc(0x00, 0x01, true), outcome(0x99, "..N."));
+    assert_eq!(sbc(0x12, 0x12, true), outcome(0x00, "CZ.."));
+    // $80 - $01 overflows as a signed binary subtraction
+    assert_eq!(sbc(0x80, 0x01, true), outcome(0x79, "C..V"));
+}
+
+#[test]
+fn nmos_sbc_with_invalid_bcd_digits() {
+    let sbc = |a, operand, carry| run(Variant::Nmos6502, SBC_IMMEDIATE, a, operand, carry);
+
+    assert_eq!(sbc(0x0A, 0x00, true), outcome(0x0A, "C..."));
+    assert_eq!(sbc(0x20, 0x0A, true), outcome(0x10, "C..."));
+}
@@ -0,0 +1,64 @@
+use emulator::nes::Nes;
+use emulator::rom::Rom;
+
+// Runs `STA $4014` from RAM and counts the CPU cycles from the end of the STA to the start of the
+// next instruction, i.e. how long the OAM DMA kept the CPU halted. Get cycles are the even CPU
+// cycles, so `halt_on_get_cycle` picks which parity the DMA starts on. `dmc_at` requests a DMC
+// fetch that many cycles into the OAM DMA.
+fn oam_dma_cycles(halt_on_get_cycle: bool, dmc_at: Option<u64>) -> u64 {
+    let mut nes = Nes::new(Rom::new("roms/balloon.nes").unwrap());
+    nes.reset();
+    let cpu = &mut nes.cpu;
+    cpu.bus.ram[0x300..0x304].copy_from_slice(&[0x8D, 0x14, 0x40, 0xEA]); // STA $4014, NOP
+    cpu.bus.ppu.ppuctrl = 0; // no NMI in the middle
+    cpu.pc = 0x300;
+    cpu.accumulator = 0x02;
+    cpu.cycles_left = 0;
+
+    // The STA takes 4 cycles, so the DMA halts the CPU on a cycle of the same parity it started on
+    if (cpu.cycles_completed % 2 == 0) != halt_on_get_cycle {
+        cpu.cycles_completed += 1;
+    }
+    for _ in 0..4 {
+        cpu.step();
+    }
+    assert_eq!(cpu.pc, 0x303);
+
+    let dma_start = cpu.cycles_completed;
+    let mut cycle = 0;
+    while cpu.pc == 0x303 {
+        if Some(cycle) == dmc_at {
+            cpu.bus.dma.request_dmc(0xC000);
+        }
+        cpu.step();
+        cycle += 1;
+    }
+    // Leaving out the cycle that ran the NOP
+    cpu.cycles_completed - 1 - dma_start
+}
+
+#[test]
+fn oam_dma_takes_513_or_514_cycles() {
+    // Halt, then 256 get/put pairs
+    assert_eq!(oam_dma_cycles(false, None), 513);
+    // Halt, an alignment cycle, then 256 get/put pairs
+    assert_eq!(oam_dma_cycles(true, None), 514);
+}
+
+#[test]
+fn dmc_fetch_during_oam_dma_costs_2_cycles() {
+    // The DMC's halt and dummy cycles overlap OAM cycles, so all it costs is its own get cycle
+    // and the put cycle the OAM DMA needs to realign
+    for halt_on_get_cycle in [false, true] {
+        let without = oam_dma_cycles(halt_on_get_cycle, None);
+        for dmc_at in (0..500).step_by(7).chain(500..505) {
+            assert_eq!(
+                oam_dma_cycles(halt_on_get_cycle, Some(dmc_at)),
+                without + 2,
+                "DMC fetch {} cycles in, halted on a {} cycle",
+                dmc_at,
+                if halt_on_get_cycle { "get" } else { "put" }
+            );
+        }
+    }
+}
@@ -0,0 +1,28 @@
+// The undocumented NOPs that take an immediate operand: they read the byte after the opcode and
+// throw it away, so each one is 2 bytes long and takes 2 cycles.
+
+use emulator::bus::FlatRam;
+use emulator::cpu::Cpu;
+
+const IMMEDIATE_NOPS: [u8; 5] = [0x80, 0x82, 0x89, 0xC2, 0xE2];
+
+#[test]
+fn immediate_nops_take_2_bytes_and_2_cycles() {
+    for &opcode in &IMMEDIATE_NOPS {
+        let mut ram = FlatRam::new();
+        ram.memory[0x8000..0x8003].copy_from_slice(&[opcode, 0x44, 0x12]);
+
+        let mut cpu = Cpu::new(ram);
+        cpu.pc = 0x8000;
+        cpu.x = 0xFF; // would cost an extra cycle if it were indexed across a page
+        let status = cpu.get_status(false);
+        cpu.step();
+        while cpu.cycles_left > 0 {
+            cpu.step();
+        }
+
+        assert_eq!(cpu.pc, 0x8002, "opcode {:02X}", opcode);
+        assert_eq!(cpu.cycles_completed, 2, "opcode {:02X}", opcode);
+        assert_eq!(cpu.get_status(false), status, "opcode {:02X}", opcode);
+    }
+}
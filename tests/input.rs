@@ -0,0 +1,137 @@
+use emulator::controller::Buttons;
+use emulator::input::{Macro, PlayerInput, TurboRate, TurboRates};
+use emulator::movie::{Movie, MovieSession, MovieStart};
+use emulator::nes::Nes;
+use emulator::rom::Rom;
+
+const RATE: TurboRate = TurboRate { on: 2, off: 1 };
+
+// Runs the input layer for a few frames with the same keys held, returning what it pressed.
+fn frames(input: &mut PlayerInput, held: Buttons, turbo_held: Buttons, count: usize) -> Vec<bool> {
+    (0..count)
+        .map(|_| input.frame(held, turbo_held).contains(Buttons::A))
+        .collect()
+}
+
+#[test]
+fn turbo_fires_at_its_rate_while_held() {
+    let mut input = PlayerInput::new(TurboRates::all(RATE));
+
+    assert_eq!(
+        frames(&mut input, Buttons::NONE, Buttons::A, 6),
+        [true, true, false, true, true, false]
+    );
+    assert_eq!(
+        frames(&mut input, Buttons::NONE, Buttons::NONE, 2),
+        [false, false]
+    );
+    // Letting go and pressing again starts over with the pressed frames
+    assert_eq!(frames(&mut input, Buttons::NONE, Buttons::A, 1), [true]);
+    // Holding the normal button too keeps it pressed throughout
+    assert!(frames(&mut input, Buttons::A, Buttons::A, 6)
+        .iter()
+        .all(|&a| a));
+}
+
+#[test]
+fn every_button_fires_at_its_own_rate() {
+    let mut input = PlayerInput::new(TurboRates {
+        a: TurboRate { on: 1, off: 1 },
+        b: TurboRate { on: 1, off: 3 },
+        ..TurboRates::default()
+    });
+
+    let pressed = (0..8)
+        .map(|_| input.frame(Buttons::NONE, Buttons::A | Buttons::B))
+        .collect::<Vec<_>>();
+    let a = pressed.iter().map(|buttons| buttons.contains(Buttons::A));
+    let b = pressed.iter().map(|buttons| buttons.contains(Buttons::B));
+    assert_eq!(
+        a.collect::<Vec<_>>(),
+        [true, false, true, false, true, false, true, false]
+    );
+    assert_eq!(
+        b.collect::<Vec<_>>(),
+        [true, false, false, false, true, false, false, false]
+    );
+
+    // A button that joins later starts with its own pressed frames, whatever the others are doing
+    let mut input = PlayerInput::new(TurboRates::all(RATE));
+    frames(&mut input, Buttons::NONE, Buttons::A, 2);
+    let joined = input.frame(Buttons::NONE, Buttons::A | Buttons::B);
+    assert_eq!(joined, Buttons::B);
+}
+
+#[test]
+fn autofire_is_toggled_by_tapping() {
+    let mut input = PlayerInput::new(TurboRates::all(RATE));
+    input.set_autofire(true);
+
+    frames(&mut input, Buttons::NONE, Buttons::A, 1);
+    assert_eq!(
+        frames(&mut input, Buttons::NONE, Buttons::NONE, 5),
+        [true, false, true, true, false]
+    );
+
+    // Tapping again stops it
+    frames(&mut input, Buttons::NONE, Buttons::A, 1);
+    assert_eq!(
+        frames(&mut input, Buttons::NONE, Buttons::NONE, 3),
+        [false; 3]
+    );
+}
+
+#[test]
+fn macros_record_and_play_back() {
+    let mut input = PlayerInput::new(TurboRates::all(RATE));
+    input.start_recording();
+    input.frame(Buttons::RIGHT, Buttons::NONE);
+    input.frame(Buttons::RIGHT | Buttons::A, Buttons::NONE);
+    input.frame(Buttons::NONE, Buttons::NONE);
+    let recorded = input.finish_recording().unwrap();
+    assert!(!input.is_recording());
+
+    assert_eq!(
+        recorded.frames,
+        [Buttons::RIGHT, Buttons::RIGHT | Buttons::A, Buttons::NONE]
+    );
+    assert_eq!(Macro::from_text(&recorded.to_text()).unwrap(), recorded);
+    assert!(Macro::from_text("R......\n").is_err());
+
+    // Played on top of what's held, then gone
+    input.play(&recorded);
+    assert_eq!(
+        input.frame(Buttons::B, Buttons::NONE),
+        Buttons::B | Buttons::RIGHT
+    );
+    assert_eq!(
+        input.frame(Buttons::NONE, Buttons::NONE),
+        Buttons::RIGHT | Buttons::A
+    );
+    input.frame(Buttons::NONE, Buttons::NONE);
+    assert_eq!(input.frame(Buttons::NONE, Buttons::NONE), Buttons::NONE);
+}
+
+// Turbo happens before the console sees the buttons, so a movie records every press.
+#[test]
+fn movies_record_turbo() {
+    let mut nes = Nes::new(Rom::new("roms/galaga.nes").unwrap());
+    nes.reset();
+    let movie = Movie::new("galaga", nes.cpu.bus.rom.hash, MovieStart::PowerOn);
+    let mut session = MovieSession::record(&mut nes, movie).unwrap();
+
+    let mut input = PlayerInput::new(TurboRates::all(RATE));
+    for _ in 0..6 {
+        nes.set_buttons(0, input.frame(Buttons::NONE, Buttons::A));
+        session.begin_frame(&mut nes, 0).unwrap();
+        nes.run_frame();
+    }
+
+    let pressed = session
+        .movie
+        .frames
+        .iter()
+        .map(|frame| frame.players[0].contains(Buttons::A))
+        .collect::<Vec<_>>();
+    assert_eq!(pressed, [true, true, false, true, true, false]);
+}
@@ -0,0 +1,239 @@
+use emulator::controller::{Buttons, Device, Pointer};
+use emulator::movie::{Movie, MovieFrame, MovieMode, MovieSession, MovieStart, COMMAND_RESET};
+use emulator::nes::Nes;
+use emulator::rom::Rom;
+
+fn mario() -> Nes {
+    let mut nes = Nes::new(Rom::new("roms/mario.nes").unwrap());
+    nes.reset();
+    nes
+}
+
+// Records a short session, pressing start on the title screen and resetting once.
+fn record(start: MovieStart) -> (Movie, Vec<u8>) {
+    let mut nes = mario();
+    let movie = Movie::new("mario", nes.cpu.bus.rom.hash, start);
+    let mut session = MovieSession::record(&mut nes, movie).unwrap();
+
+    for frame in 0..70 {
+        let buttons = if (35..40).contains(&frame) {
+            Buttons::START
+        } else {
+            Buttons::NONE
+        };
+        nes.set_buttons(0, buttons);
+        let commands = if frame == 50 { COMMAND_RESET } else { 0 };
+        session.begin_frame(&mut nes, commands).unwrap();
+        nes.run_frame();
+    }
+
+    (session.movie, nes.save_state())
+}
+
+fn play(movie: Movie) -> Vec<u8> {
+    let mut nes = mario();
+    let frames = movie.frames.len();
+    let mut session = MovieSession::play(&mut nes, movie).unwrap();
+
+    for _ in 0..frames {
+        session.begin_frame(&mut nes, 0).unwrap();
+        nes.run_frame();
+    }
+
+    assert!(session.begin_frame(&mut nes, 0).is_ok());
+    assert_eq!(session.mode, MovieMode::Finished);
+    nes.save_state()
+}
+
+#[test]
+fn playback_through_fm2_matches_recording() {
+    let (movie, recorded) = record(MovieStart::PowerOn);
+    assert_eq!(movie.frames[50].commands, COMMAND_RESET);
+    assert_eq!(movie.frames[36].players[0], Buttons::START);
+
+    let movie = Movie::from_fm2(&movie.to_fm2()).unwrap();
+    assert!(play(movie) == recorded);
+}
+
+#[test]
+fn movies_can_start_from_a_save_state() {
+    let mut nes = mario();
+    for _ in 0..20 {
+        nes.run_frame();
+    }
+
+    let (movie, recorded) = record(MovieStart::SaveState(nes.save_state()));
+    let movie = Movie::from_fm2(&movie.to_fm2()).unwrap();
+    assert!(matches!(movie.start, MovieStart::SaveState(_)));
+    assert!(play(movie) == recorded);
+}
+
+#[test]
+fn desyncs_are_detected() {
+    let (mut movie, _) = record(MovieStart::PowerOn);
+    // Never pressing start keeps the game on the title screen, so RAM soon differs.
+    for frame in &mut movie.frames {
+        frame.players[0] = Buttons::NONE;
+    }
+
+    let mut nes = mario();
+    let mut session = MovieSession::play(&mut nes, movie).unwrap();
+    let desync = (0..70)
+        .find_map(|_| {
+            let result = session.begin_frame(&mut nes, 0);
+            nes.run_frame();
+            result.err()
+        })
+        .expect("playback should have desynced");
+    assert!(desync.frame > 35);
+}
+
+#[test]
+fn fm2_from_fceux_is_read() {
+    let fm2 = "version 3\n\
+               emuVersion 20604\n\
+               rerecordCount 42\n\
+               palFlag 0\n\
+               romFilename Super Mario Bros.\n\
+               romChecksum base64:jjYwGG411HcjG/j9UOVM3Q==\n\
+               guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\n\
+               fourscore 0\n\
+               microphone 0\n\
+               port0 1\n\
+               port1 1\n\
+               port2 0\n\
+               FDS 0\n\
+               NewPPU 0\n\
+               |1|........|........||\n\
+               |0|....T...|........||\n\
+               |0|R......A|.L......||\n\
+               |2|........|||\n";
+
+    let movie = Movie::from_fm2(fm2).unwrap();
+    assert_eq!(movie.rerecord_count, 42);
+    assert_eq!(movie.rom_filename, "Super Mario Bros.");
+    assert_eq!(movie.start, MovieStart::PowerOn);
+    assert_eq!(
+        movie.frames,
+        vec![
+            MovieFrame {
+                commands: 1,
+                players: [Buttons::NONE, Buttons::NONE, Buttons::NONE, Buttons::NONE],
+                ..MovieFrame::default()
+            },
+            MovieFrame {
+                commands: 0,
+                players: [Buttons::START, Buttons::NONE, Buttons::NONE, Buttons::NONE],
+                ..MovieFrame::default()
+            },
+            MovieFrame {
+                commands: 0,
+                players: [
+                    Buttons::A | Buttons::RIGHT,
+                    Buttons::LEFT,
+                    Buttons::NONE,
+                    Buttons::NONE
+                ],
+                ..MovieFrame::default()
+            },
+            MovieFrame {
+                commands: 2,
+                players: [Buttons::NONE, Buttons::NONE, Buttons::NONE, Buttons::NONE],
+                ..MovieFrame::default()
+            },
+        ]
+    );
+}
+
+#[test]
+fn rerecording_truncates_the_movie() {
+    let mut nes = mario();
+    let movie = Movie::new("mario", nes.cpu.bus.rom.hash, MovieStart::PowerOn);
+    let mut session = MovieSession::record(&mut nes, movie).unwrap();
+
+    let mut state = Vec::new();
+    for frame in 0..20 {
+        if frame == 10 {
+            state = nes.save_state();
+        }
+        session.begin_frame(&mut nes, 0).unwrap();
+        nes.run_frame();
+    }
+
+    nes.load_state(&state).unwrap();
+    assert!(session.sync_frame(&nes));
+    assert_eq!(session.movie.frames.len(), 10);
+    assert_eq!(session.movie.rerecord_count, 1);
+}
+
+#[test]
+fn four_score_movies_keep_all_players() {
+    let mut movie = Movie::new("gauntlet", 0, MovieStart::PowerOn);
+    movie.four_score = true;
+    movie.frames.push(MovieFrame {
+        commands: 0,
+        players: [Buttons::A, Buttons::B, Buttons::START, Buttons::LEFT],
+        ..MovieFrame::default()
+    });
+
+    let fm2 = movie.to_fm2();
+    assert!(fm2.contains("fourscore 1\n"));
+    assert!(fm2.contains("|0|.......A|......B.|....T...|.L......||\n"));
+
+    let read = Movie::from_fm2(&fm2).unwrap();
+    assert!(read.four_score);
+    assert_eq!(read.frames, movie.frames);
+}
+
+#[test]
+fn zapper_movies_keep_the_aim() {
+    let mut movie = Movie::new("duck_hunt", 0, MovieStart::PowerOn);
+    movie.zapper = [false, true];
+    movie.frames.push(MovieFrame {
+        commands: 0,
+        players: [Buttons::SELECT, Buttons::NONE, Buttons::NONE, Buttons::NONE],
+        pointers: [
+            Pointer::default(),
+            Pointer {
+                x: 128,
+                y: 96,
+                button: true,
+            },
+        ],
+    });
+
+    let fm2 = movie.to_fm2();
+    assert!(fm2.contains("port1 2\n"));
+    assert!(fm2.contains("|0|.....S..|128 96 1 0 0||\n"));
+    assert_eq!(Movie::from_fm2(&fm2).unwrap().frames, movie.frames);
+}
+
+#[test]
+fn fm2_cant_record_every_device() {
+    let mut nes = mario();
+    nes.plug(1, Device::PowerPad.create(1));
+    let movie = Movie::new("mario", nes.cpu.bus.rom.hash, MovieStart::PowerOn);
+    let err = MovieSession::record(&mut nes, movie).err().unwrap();
+    assert!(err.to_string().contains("power_pad"));
+}
+
+#[test]
+fn movies_need_the_devices_they_were_recorded_with() {
+    let mut nes = mario();
+    let mut movie = Movie::new("mario", nes.cpu.bus.rom.hash, MovieStart::PowerOn);
+    movie.four_score = true;
+
+    let err = MovieSession::play(&mut nes, movie.clone()).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "movie needs four_score or famicom_four_player in port 1, but standard is plugged in"
+    );
+
+    nes.plug(0, Device::FourScore.create(0));
+    nes.plug(1, Device::FourScore.create(1));
+    assert!(MovieSession::play(&mut nes, movie).is_ok());
+
+    // And the other way round
+    let movie = Movie::new("mario", nes.cpu.bus.rom.hash, MovieStart::PowerOn);
+    assert!(MovieSession::play(&mut nes, movie).is_err());
+}
@@ -0,0 +1,77 @@
+// Runs nestest in its automated mode (starting at $C000 instead of the reset vector, so no PPU or
+// controller is needed) and compares every traced instruction against roms/nestest.log.
+//
+// The log is in the same format as the well known nestest.log, and ends at the same place: the
+// RTS at $C66E on cycle 26554, with $02 and $03 both zero.
+
+use emulator::nes::Nes;
+use emulator::rom::Rom;
+use emulator::trace::{TraceFormat, Tracer};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const ROM_PATH: &str = "roms/nestest.nes";
+const LOG_PATH: &str = "roms/nestest.log";
+
+// How many lines around the first divergence to print.
+const CONTEXT_LINES: usize = 5;
+
+// nestest finishes in well under a second of emulated time; anything past this means it's stuck.
+const MAX_CPU_CYCLES: u64 = 100_000;
+
+#[test]
+fn nestest_matches_golden_log() {
+    let expected = std::fs::read_to_string(LOG_PATH).unwrap();
+    let expected = expected.lines().collect::<Vec<_>>();
+
+    let mut nes = Nes::new(Rom::new(ROM_PATH).unwrap());
+    nes.reset();
+    nes.cpu.pc = 0xC000;
+
+    let traced = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&traced);
+    nes.cpu.tracer = Some(Tracer::to_callback(
+        Box::new(move |line| sink.borrow_mut().push(line.to_string())),
+        TraceFormat::Nestest,
+    ));
+
+    while traced.borrow().len() < expected.len() && nes.cpu.cycles_completed < MAX_CPU_CYCLES {
+        nes.step();
+    }
+
+    let actual = traced.borrow();
+    if let Some(divergence) = first_divergence(&expected, &actual) {
+        panic!("{}", describe_divergence(&expected, &actual, divergence));
+    }
+
+    // nestest stores the number of the first failing official/unofficial opcode test here.
+    let official = nes.cpu.bus.ram[0x02];
+    let unofficial = nes.cpu.bus.ram[0x03];
+    assert_eq!(
+        (official, unofficial),
+        (0, 0),
+        "nestest reported failures: $02 = {:02X}, $03 = {:02X}",
+        official,
+        unofficial
+    );
+}
+
+fn first_divergence(expected: &[&str], actual: &[String]) -> Option<usize> {
+    (0..expected.len()).find(|&i| actual.get(i).map(String::as_str) != Some(expected[i]))
+}
+
+fn describe_divergence(expected: &[&str], actual: &[String], line: usize) -> String {
+    let mut message = format!("trace diverges from {} at line {}\n", LOG_PATH, line + 1);
+
+    for context in &expected[line.saturating_sub(CONTEXT_LINES)..line] {
+        message += &format!("            {}\n", context);
+    }
+    message += &format!("  expected: {}\n", expected[line]);
+    match actual.get(line) {
+        Some(actual) => message += &format!("       got: {}\n", actual),
+        None => message += "       got: nothing, the CPU stopped producing trace lines\n",
+    }
+
+    message
+}
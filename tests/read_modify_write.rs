@@ -0,0 +1,72 @@
+// Read-modify-write instructions read their target, write the old value back, then write the new
+// one. Memory mapped registers can tell the difference, so the order is checked access by access.
+
+use emulator::bus::FlatRam;
+use emulator::cpu::{Cpu, CpuBus};
+
+const TARGET: u16 = 0x0300;
+
+#[derive(Debug, PartialEq)]
+enum Access {
+    Read(u8),
+    Write(u8),
+}
+
+// Flat RAM that remembers every access the CPU makes to TARGET.
+struct RecordingRam {
+    ram: FlatRam,
+    accesses: Vec<Access>,
+}
+
+impl CpuBus for RecordingRam {
+    fn get_byte_at(&mut self, addr: u16) -> u8 {
+        let val = self.ram.get_byte_at(addr);
+        if addr == TARGET {
+            self.accesses.push(Access::Read(val));
+        }
+        val
+    }
+
+    fn set_byte_at(&mut self, addr: u16, val: u8) {
+        self.ram.set_byte_at(addr, val);
+        if addr == TARGET {
+            self.accesses.push(Access::Write(val));
+        }
+    }
+
+    fn peek_byte_at(&self, addr: u16) -> u8 {
+        self.ram.peek_byte_at(addr)
+    }
+}
+
+// Runs one instruction from $8000 with TARGET holding `value`, returning the accesses to TARGET.
+fn accesses(instruction: &[u8], x: u8, value: u8) -> Vec<Access> {
+    let mut bus = RecordingRam {
+        ram: FlatRam::new(),
+        accesses: Vec::new(),
+    };
+    bus.ram.memory[0x8000..0x8000 + instruction.len()].copy_from_slice(instruction);
+    bus.ram.memory[usize::from(TARGET)] = value;
+
+    let mut cpu = Cpu::new(bus);
+    cpu.pc = 0x8000;
+    cpu.x = x;
+    cpu.step();
+    cpu.bus.accesses
+}
+
+#[test]
+fn inc_absolute_writes_the_old_value_then_the_new_one() {
+    assert_eq!(
+        accesses(&[0xEE, 0x00, 0x03], 0, 0x41), // INC $0300
+        [Access::Read(0x41), Access::Write(0x41), Access::Write(0x42)]
+    );
+}
+
+#[test]
+fn asl_absolute_x_writes_the_old_value_then_the_new_one() {
+    assert_eq!(
+        accesses(&[0x1E, 0xFE, 0x02], 2, 0x81), // ASL $02FE,X
+        [Access::Read(0x81), Access::Write(0x81), Access::Write(0x02)]
+    );
+}
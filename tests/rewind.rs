@@ -0,0 +1,108 @@
+use emulator::controller::{Buttons, Device, Pointer};
+use emulator::nes::Nes;
+use emulator::rewind::{Rewind, RewindConfig};
+use emulator::rom::Rom;
+
+// Plays the start of Super Mario Bros. (title screen, start, then mashing right), recording rewind
+// history and keeping a save state from the start of every frame.
+fn play(rewind: &mut Rewind, frames: u64) -> (Nes, Vec<Vec<u8>>) {
+    let mut nes = Nes::new(Rom::new("roms/mario.nes").unwrap());
+    nes.reset();
+
+    let mut states = Vec::new();
+    for frame in 0..frames {
+        let buttons = match frame {
+            30..=34 => Buttons::START,
+            f if f >= 50 && f % 3 != 0 => Buttons::RIGHT,
+            _ => Buttons::NONE,
+        };
+        nes.set_buttons(0, buttons);
+
+        rewind.record_frame(&nes);
+        states.push(nes.save_state());
+        nes.run_frame();
+    }
+
+    // Nothing is pressed while rewinding, so only the recorded input can get the frames right.
+    nes.set_buttons(0, Buttons::NONE);
+    (nes, states)
+}
+
+#[test]
+fn rewinding_is_frame_exact() {
+    let mut rewind = Rewind::new(RewindConfig {
+        interval: 7,
+        ..RewindConfig::default()
+    });
+    let (mut nes, states) = play(&mut rewind, 90);
+
+    for target in [89, 71, 60, 52, 30] {
+        assert!(rewind.rewind_to(&mut nes, target));
+        assert!(
+            nes.save_state() == states[target as usize],
+            "machine differs after rewinding to frame {}",
+            target
+        );
+    }
+}
+
+#[test]
+fn rewinding_one_frame_at_a_time() {
+    let mut rewind = Rewind::new(RewindConfig::default());
+    let (mut nes, states) = play(&mut rewind, 60);
+
+    for target in (50..60).rev() {
+        assert!(rewind.rewind_frame(&mut nes));
+        assert!(nes.save_state() == states[target]);
+    }
+}
+
+#[test]
+fn history_stays_within_its_memory_budget() {
+    let max_bytes = 100 * 1024;
+    let mut rewind = Rewind::new(RewindConfig {
+        interval: 2,
+        max_bytes,
+    });
+    let (mut nes, _) = play(&mut rewind, 60);
+
+    assert!(rewind.bytes_used() <= max_bytes);
+    let oldest = rewind.oldest_frame().unwrap();
+    assert!(oldest > 0);
+    assert!(!rewind.rewind_to(&mut nes, oldest - 1));
+    assert!(rewind.rewind_to(&mut nes, oldest));
+}
+
+#[test]
+fn frames_without_recorded_input_replay_with_nothing_held() {
+    let mut nes = Nes::new(Rom::new("roms/mario.nes").unwrap());
+    nes.reset();
+    nes.plug(0, Device::PowerPad.create(0));
+    nes.plug(1, Device::Zapper.create(1));
+    let mut rewind = Rewind::new(RewindConfig::default());
+
+    // The mat and the trigger are held down for the one recorded frame
+    let held = Pointer {
+        x: 100,
+        y: 100,
+        button: true,
+    };
+    nes.set_mat(0, 0x0FFF);
+    nes.set_pointer(1, held);
+    let start = nes.ppu().frame;
+    rewind.record_frame(&nes);
+    nes.run_frame();
+
+    // ...and let go for the ones after it, which aren't recorded
+    nes.set_mat(0, 0);
+    nes.set_pointer(1, Pointer::default());
+    for _ in 1..5 {
+        nes.run_frame();
+    }
+    let state = nes.save_state();
+
+    assert!(rewind.rewind_to(&mut nes, start + 5));
+    assert_eq!(nes.mat(0), Some(0));
+    assert_eq!(nes.pointer(1), Some(Pointer::default()));
+    assert!(nes.save_state() == state);
+}
@@ -0,0 +1,114 @@
+use emulator::controller::{Buttons, Device};
+use emulator::expansion::Expansion;
+use emulator::nes::Nes;
+use emulator::rom::Rom;
+
+fn boot(path: &str) -> Nes {
+    let mut nes = Nes::new(Rom::new(path).unwrap());
+    nes.reset();
+    nes
+}
+
+#[test]
+fn loading_a_state_replays_identically() {
+    let mut nes = boot("roms/mario.nes");
+    for _ in 0..40 {
+        nes.run_frame();
+    }
+    let state = nes.save_state();
+
+    // Press start after saving, so the state has to bring back the title screen too.
+    nes.set_buttons(0, Buttons::START);
+    for _ in 0..30 {
+        nes.run_frame();
+    }
+    nes.set_buttons(0, Buttons::NONE);
+    let expected = (nes.cpu.cycles_completed, nes.ppu().framebuffer.clone());
+
+    nes.load_state(&state).unwrap();
+    nes.set_buttons(0, Buttons::START);
+    for _ in 0..30 {
+        nes.run_frame();
+    }
+    nes.set_buttons(0, Buttons::NONE);
+
+    assert_eq!(
+        (nes.cpu.cycles_completed, nes.ppu().framebuffer.clone()),
+        expected
+    );
+    assert_eq!(nes.save_state(), {
+        let mut fresh = boot("roms/mario.nes");
+        fresh.load_state(&nes.save_state()).unwrap();
+        fresh.save_state()
+    });
+}
+
+#[test]
+fn states_from_another_rom_are_rejected() {
+    let state = boot("roms/mario.nes").save_state();
+    assert!(boot("roms/donkey.nes").load_state(&state).is_err());
+}
+
+#[test]
+fn truncated_states_are_rejected() {
+    let mut nes = boot("roms/mario.nes");
+    let state = nes.save_state();
+    assert!(nes.load_state(&state[..state.len() - 1]).is_err());
+}
+
+#[test]
+fn states_need_the_same_devices_plugged_in() {
+    let mut nes = boot("roms/mario.nes");
+    nes.plug(1, Device::Zapper.create(1));
+    let state = nes.save_state();
+
+    let mut other = boot("roms/mario.nes");
+    let before = other.save_state();
+    let err = other.load_state(&state).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "save state has zapper in port 2, but standard is plugged in"
+    );
+    assert!(other.save_state() == before);
+
+    other.plug_expansion(Expansion::FamilyBasicKeyboard.create());
+    other.plug(1, Device::Zapper.create(1));
+    assert!(other.load_state(&state).is_err());
+    other.plug_expansion(Expansion::None.create());
+    other.load_state(&state).unwrap();
+}
+
+// Cuts the data of the chunk with the given tag down to `length` bytes.
+fn truncate_chunk(state: &[u8], tag: &[u8; 4], length: usize) -> Vec<u8> {
+    let mut truncated = state[..18].to_vec();
+    let mut rest = &state[18..];
+    while !rest.is_empty() {
+        let chunk_length = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let (chunk, after) = rest.split_at(8 + chunk_length);
+        if &chunk[..4] == tag {
+            truncated.extend_from_slice(tag);
+            truncated.extend_from_slice(&(length as u32).to_le_bytes());
+            truncated.extend_from_slice(&chunk[8..8 + length]);
+        } else {
+            truncated.extend_from_slice(chunk);
+        }
+        rest = after;
+    }
+    truncated
+}
+
+#[test]
+fn a_bad_chunk_leaves_the_machine_untouched() {
+    let mut nes = boot("roms/mario.nes");
+    for _ in 0..10 {
+        nes.run_frame();
+    }
+    // Port 2's chunk comes after the CPU, RAM and PPU ones: keep its device name, lose the rest
+    let state = truncate_chunk(&nes.save_state(), b"CTL2", 2 + "standard".len());
+
+    let mut other = boot("roms/mario.nes");
+    other.run_frame();
+    let before = other.save_state();
+    assert!(other.load_state(&state).is_err());
+    assert!(other.save_state() == before);
+}
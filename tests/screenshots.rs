@@ -0,0 +1,238 @@
+// Screenshot regression tests for ROMs that only report their results visually, and for a few
+// games. Each case runs a ROM headless for a fixed number of frames (optionally pressing buttons
+// along the way) and compares the final frame against tests/screenshots/<name>.png.
+//
+// On a mismatch the actual frame and a diff image (differing pixels in red) are written to
+// target/screenshot-diffs/. Once a change in output is intended, bless the new frames with
+//
+//     BLESS=1 cargo test --test screenshots
+
+use emulator::controller::Buttons;
+use emulator::nes::Nes;
+use emulator::rom::Rom;
+use emulator::screenshot;
+
+use std::path::{Path, PathBuf};
+
+const BASELINE_DIR: &str = "tests/screenshots";
+const DIFF_DIR: &str = "target/screenshot-diffs";
+
+struct Case {
+    name: &'static str,
+    rom: &'static str,
+    frames: u64,
+    // (frame, buttons) pairs: from the start of that frame on, hold down exactly those buttons.
+    input: &'static [(u64, Buttons)],
+}
+
+fn check_screenshot(case: Case) {
+    let mut nes = Nes::new(Rom::new(case.rom).unwrap());
+    nes.reset();
+
+    for frame in 0..case.frames {
+        if let Some(&(_, buttons)) = case.input.iter().find(|&&(at, _)| at == frame) {
+            nes.set_buttons(0, buttons);
+        }
+        nes.run_frame();
+    }
+
+    let actual = nes.ppu().framebuffer_rgb();
+    let baseline = Path::new(BASELINE_DIR).join(format!("{}.png", case.name));
+
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::create_dir_all(BASELINE_DIR).unwrap();
+        screenshot::save_png(&baseline, &actual).unwrap();
+        return;
+    }
+
+    let expected = screenshot::load_png(&baseline).unwrap_or_else(|err| {
+        panic!(
+            "couldn't load {}: {}\nrun with BLESS=1 to create it",
+            baseline.display(),
+            err
+        )
+    });
+    if expected == actual {
+        return;
+    }
+
+    let diff_dir = PathBuf::from(DIFF_DIR);
+    std::fs::create_dir_all(&diff_dir).unwrap();
+    let actual_path = diff_dir.join(format!("{}.actual.png", case.name));
+    let diff_path = diff_dir.join(format!("{}.diff.png", case.name));
+    screenshot::save_png(&actual_path, &actual).unwrap();
+    screenshot::save_png(&diff_path, &screenshot::diff_image(&expected, &actual)).unwrap();
+
+    panic!(
+        "{} after {} frames doesn't match {} ({} pixels differ, hash {:016X} instead of {:016X})\n\
+         see {} and {}",
+        case.rom,
+        case.frames,
+        baseline.display(),
+        screenshot::count_differences(&expected, &actual),
+        screenshot::hash(&actual),
+        screenshot::hash(&expected),
+        actual_path.display(),
+        diff_path.display()
+    );
+}
+
+#[test]
+fn color_test() {
+    check_screenshot(Case {
+        name: "color_test",
+        rom: "roms/color_test.nes",
+        frames: 30,
+        input: &[],
+    });
+}
+
+#[test]
+fn full_nes_palette() {
+    check_screenshot(Case {
+        name: "full_nes_palette",
+        rom: "roms/full_nes_palette.nes",
+        frames: 30,
+        input: &[],
+    });
+}
+
+#[test]
+fn scanline() {
+    check_screenshot(Case {
+        name: "scanline",
+        rom: "roms/scanline.nes",
+        frames: 30,
+        input: &[],
+    });
+}
+
+#[test]
+fn ntsc_torture() {
+    check_screenshot(Case {
+        name: "ntsc_torture",
+        rom: "roms/ntsc_torture.nes",
+        frames: 30,
+        input: &[],
+    });
+}
+
+#[test]
+fn mario_title() {
+    check_screenshot(Case {
+        name: "mario_title",
+        rom: "roms/mario.nes",
+        frames: 60,
+        input: &[],
+    });
+}
+
+#[test]
+fn mario_start() {
+    check_screenshot(Case {
+        name: "mario_start",
+        rom: "roms/mario.nes",
+        frames: 120,
+        input: &[(40, Buttons::START), (45, Buttons::NONE)],
+    });
+}
+
+#[test]
+fn nestress() {
+    check_screenshot(Case {
+        name: "nestress",
+        rom: "roms/NEStress.NES",
+        frames: 60,
+        input: &[],
+    });
+}
+
+#[test]
+fn paltest() {
+    check_screenshot(Case {
+        name: "paltest",
+        rom: "roms/paltest.nes",
+        frames: 60,
+        input: &[],
+    });
+}
+
+#[test]
+fn bkg() {
+    check_screenshot(Case {
+        name: "bkg",
+        rom: "roms/bkg.nes",
+        frames: 60,
+        input: &[],
+    });
+}
+
+#[test]
+fn demo_ntsc() {
+    check_screenshot(Case {
+        name: "demo_ntsc",
+        rom: "roms/demo_ntsc.nes",
+        frames: 60,
+        input: &[],
+    });
+}
+
+#[test]
+fn demo_pal() {
+    check_screenshot(Case {
+        name: "demo_pal",
+        rom: "roms/demo_pal.nes",
+        frames: 60,
+        input: &[],
+    });
+}
+
+#[test]
+fn balloon_demo() {
+    check_screenshot(Case {
+        name: "balloon_demo",
+        rom: "roms/balloon.nes",
+        frames: 300,
+        input: &[],
+    });
+}
+
+#[test]
+fn donkey_title() {
+    check_screenshot(Case {
+        name: "donkey_title",
+        rom: "roms/donkey.nes",
+        frames: 300,
+        input: &[],
+    });
+}
+
+#[test]
+fn donkey_3_title() {
+    check_screenshot(Case {
+        name: "donkey_3_title",
+        rom: "roms/donkey_3.nes",
+        frames: 300,
+        input: &[],
+    });
+}
+
+#[test]
+fn galaga_title() {
+    check_screenshot(Case {
+        name: "galaga_title",
+        rom: "roms/galaga.nes",
+        frames: 300,
+        input: &[],
+    });
+}
+
+#[test]
+fn ice_climber_title() {
+    check_screenshot(Case {
+        name: "ice_climber_title",
+        rom: "roms/ice_climber.nes",
+        frames: 300,
+        input: &[],
+    });
+}
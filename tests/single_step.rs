@@ -0,0 +1,173 @@
+// Runs the CPU against single-step test vectors: one JSON file per opcode (00.json through
+// ff.json), each holding a list of cases with the registers and RAM before and after a single
+// instruction, plus every bus access made along the way. This is the format of the widely used
+// "ProcessorTests" 6502/NES vectors. Only the final state and the cycle count are checked, since
+// the CPU does all of an instruction's bus accesses on its first cycle rather than one per cycle.
+//
+// The vectors are far too large to check in, so the test is ignored by default. Run it with
+// `cargo test --test single_step -- --ignored` after putting them in tests/single_step/ (or the
+// directory in $SINGLE_STEP_TESTS).
+
+use emulator::bus::FlatRam;
+use emulator::cpu::{Cpu, CpuBus, Instruction};
+
+use serde::Deserialize;
+
+use std::fmt::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_TEST_DIR: &str = "tests/single_step";
+
+// Stop listing failures for an opcode after this many; the rest are only counted.
+const MAX_REPORTED_FAILURES: usize = 3;
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: CpuState,
+    #[serde(rename = "final")]
+    expected: CpuState,
+    cycles: Vec<BusAccess>,
+}
+
+#[derive(Deserialize)]
+struct CpuState {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+// One bus cycle: address, data, and "read" or "write".
+type BusAccess = (u16, u8, String);
+
+#[test]
+#[ignore = "needs the single step test vectors"]
+fn single_step_tests() {
+    let test_dir = std::env::var("SINGLE_STEP_TESTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_TEST_DIR));
+    assert!(
+        test_dir.is_dir(),
+        "no single step test vectors: {} doesn't exist",
+        test_dir.display()
+    );
+
+    let mut report = String::new();
+    let mut failed_opcodes = 0;
+
+    for opcode in 0..=0xFFu8 {
+        if Instruction::decode(opcode, 0, 0).is_none() {
+            writeln!(report, "{:02X}: not implemented, skipped", opcode).unwrap();
+            continue;
+        }
+
+        let path = test_dir.join(format!("{:02x}.json", opcode));
+        if !path.exists() {
+            writeln!(report, "{:02X}: no test file, skipped", opcode).unwrap();
+            continue;
+        }
+
+        let cases = load_cases(&path);
+        let failures = cases
+            .iter()
+            .filter_map(|case| run_case(case).err().map(|err| (&case.name, err)))
+            .collect::<Vec<_>>();
+
+        if !failures.is_empty() {
+            failed_opcodes += 1;
+            writeln!(
+                report,
+                "{:02X}: {} of {} cases failed",
+                opcode,
+                failures.len(),
+                cases.len()
+            )
+            .unwrap();
+            for (name, err) in failures.iter().take(MAX_REPORTED_FAILURES) {
+                writeln!(report, "    [{}] {}", name, err).unwrap();
+            }
+        }
+    }
+
+    eprint!("{}", report);
+    assert_eq!(failed_opcodes, 0, "{} opcodes failed", failed_opcodes);
+}
+
+fn load_cases(path: &Path) -> Vec<TestCase> {
+    let json = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| panic!("couldn't read {}: {}", path.display(), err));
+    serde_json::from_str(&json)
+        .unwrap_or_else(|err| panic!("couldn't parse {}: {}", path.display(), err))
+}
+
+// Runs one instruction from the initial state, returning a description of the first mismatch.
+fn run_case(case: &TestCase) -> Result<(), String> {
+    let mut bus = FlatRam::new();
+    for &(addr, val) in &case.initial.ram {
+        bus.set_byte_at(addr, val);
+    }
+
+    let mut cpu = Cpu::new(bus);
+    cpu.pc = case.initial.pc;
+    cpu.sp = case.initial.s;
+    cpu.accumulator = case.initial.a;
+    cpu.x = case.initial.x;
+    cpu.y = case.initial.y;
+    cpu.set_status(case.initial.p);
+
+    // The first step executes the whole instruction, the rest just burn its remaining cycles.
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        cpu.step();
+        while cpu.cycles_left > 0 {
+            cpu.step();
+        }
+    }))
+    .map_err(|_| String::from("CPU panicked"))?;
+
+    let expected = &case.expected;
+    let registers = [
+        ("pc", expected.pc, cpu.pc),
+        ("s", u16::from(expected.s), u16::from(cpu.sp)),
+        ("a", u16::from(expected.a), u16::from(cpu.accumulator)),
+        ("x", u16::from(expected.x), u16::from(cpu.x)),
+        ("y", u16::from(expected.y), u16::from(cpu.y)),
+        (
+            "p",
+            u16::from(expected.p & !0x30),
+            u16::from(cpu.get_status(false) & !0x30),
+        ),
+    ];
+    for (name, expected, actual) in registers {
+        if expected != actual {
+            return Err(format!(
+                "{}: expected {:02X}, got {:02X}",
+                name, expected, actual
+            ));
+        }
+    }
+
+    for &(addr, val) in &expected.ram {
+        let actual = cpu.bus.peek_byte_at(addr);
+        if actual != val {
+            return Err(format!(
+                "ram[{:04X}]: expected {:02X}, got {:02X}",
+                addr, val, actual
+            ));
+        }
+    }
+
+    if cpu.cycles_completed as usize != case.cycles.len() {
+        return Err(format!(
+            "took {} cycles, expected {}",
+            cpu.cycles_completed,
+            case.cycles.len()
+        ));
+    }
+
+    Ok(())
+}
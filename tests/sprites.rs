@@ -0,0 +1,243 @@
+use emulator::ppu::{Ppu, OUTPUT_PALETTE, PALETTE, SCREEN_WIDTH};
+
+const SCANLINE: u16 = 50;
+const OVERFLOW: u8 = 1 << 5;
+const SPRITE_ZERO_HIT: u8 = 1 << 6;
+
+// OAM with every byte $FF, so no sprite is in range of any scanline.
+fn empty_oam() -> [u8; 0x100] {
+    [0xFF; 0x100]
+}
+
+// Runs sprite evaluation for SCANLINE over the given OAM, and the fetches of the sprites found for
+// the next scanline.
+fn evaluate(oam: [u8; 0x100], oamaddr: u8, tall_sprites: bool) -> Ppu {
+    evaluate_with_vram(oam, oamaddr, tall_sprites, [0; 0x4000])
+}
+
+fn evaluate_with_vram(
+    oam: [u8; 0x100],
+    oamaddr: u8,
+    tall_sprites: bool,
+    vram: [u8; 0x4000],
+) -> Ppu {
+    let mut ppu = Ppu::new();
+    ppu.vram = vram;
+    ppu.oam = oam;
+    ppu.oamaddr = oamaddr;
+    ppu.ppumask = 0x18;
+    ppu.ppuctrl = if tall_sprites { 1 << 5 } else { 0 };
+    ppu.scanline = SCANLINE;
+    ppu.cycle = 0;
+    while ppu.cycle <= 320 {
+        ppu.step();
+    }
+    ppu
+}
+
+fn sprite(oam: &mut [u8; 0x100], n: usize, y: u8, x: u8) {
+    oam[n * 4..n * 4 + 4].copy_from_slice(&[y, n as u8, 0, x]);
+}
+
+#[test]
+fn only_eight_sprites_per_scanline() {
+    let mut oam = empty_oam();
+    for n in 0..10 {
+        sprite(&mut oam, n, SCANLINE as u8 - 3, n as u8 * 10);
+    }
+
+    let ppu = evaluate(oam, 0, false);
+    assert_eq!(ppu.sprite_count, 8);
+    assert_eq!(&ppu.secondary_oam[..], &oam[..0x20]);
+    assert!(ppu.sprite_zero);
+    assert_ne!(ppu.ppustatus & OVERFLOW, 0);
+}
+
+#[test]
+fn sprites_out_of_range_are_skipped() {
+    let mut oam = empty_oam();
+    sprite(&mut oam, 0, SCANLINE as u8 - 8, 0);
+    sprite(&mut oam, 3, SCANLINE as u8, 10);
+    sprite(&mut oam, 20, SCANLINE as u8 - 7, 20);
+
+    let ppu = evaluate(oam, 0, false);
+    assert_eq!(ppu.sprite_count, 2);
+    assert_eq!(&ppu.secondary_oam[..4], &oam[12..16]);
+    assert_eq!(&ppu.secondary_oam[4..8], &oam[80..84]);
+    assert!(!ppu.sprite_zero);
+    assert_eq!(ppu.ppustatus & OVERFLOW, 0);
+
+    // 8x16 sprites stay in range for twice as many scanlines
+    let ppu = evaluate(oam, 0, true);
+    assert_eq!(ppu.sprite_count, 3);
+    assert!(ppu.sprite_zero);
+}
+
+#[test]
+fn overflow_check_goes_diagonally_through_oam() {
+    let mut oam = empty_oam();
+    for n in 0..8 {
+        sprite(&mut oam, n, SCANLINE as u8, 0);
+    }
+    // After sprite 8's Y, the search looks at sprite 9's tile number, sprite 10's attributes...
+    oam[9 * 4 + 1] = SCANLINE as u8;
+
+    let ppu = evaluate(oam, 0, false);
+    assert_eq!(ppu.sprite_count, 8);
+    assert_ne!(ppu.ppustatus & OVERFLOW, 0);
+
+    // ...so a 9th sprite that is really on the scanline can go unnoticed
+    let mut oam = empty_oam();
+    for n in 0..8 {
+        sprite(&mut oam, n, SCANLINE as u8, 0);
+    }
+    oam[9 * 4] = SCANLINE as u8;
+
+    let ppu = evaluate(oam, 0, false);
+    assert_eq!(ppu.sprite_count, 8);
+    assert_eq!(ppu.ppustatus & OVERFLOW, 0);
+}
+
+#[test]
+fn misaligned_oamaddr_starts_evaluation_mid_sprite() {
+    let mut oam = empty_oam();
+    sprite(&mut oam, 0, 0xFF, 0);
+    oam[1] = SCANLINE as u8;
+
+    // With OAMADDR at 1, sprite 0's tile number is taken for a Y coordinate
+    let ppu = evaluate(oam, 1, false);
+    assert_eq!(ppu.sprite_count, 1);
+    assert_eq!(&ppu.secondary_oam[..4], &oam[1..5]);
+    assert!(ppu.sprite_zero);
+
+    let ppu = evaluate(oam, 0, false);
+    assert_eq!(ppu.sprite_count, 0);
+}
+
+#[test]
+fn no_sprites_with_rendering_disabled() {
+    let mut oam = empty_oam();
+    sprite(&mut oam, 0, SCANLINE as u8, 0);
+
+    let mut ppu = Ppu::new();
+    ppu.oam = oam;
+    ppu.scanline = SCANLINE;
+    while ppu.cycle <= 257 {
+        ppu.step();
+    }
+    assert_eq!(ppu.sprite_count, 0);
+}
+
+#[test]
+fn sprite_fetches_load_the_shift_registers() {
+    let mut oam = empty_oam();
+    oam[..4].copy_from_slice(&[SCANLINE as u8 - 2, 1, 0x03, 30]);
+    oam[4..8].copy_from_slice(&[SCANLINE as u8 - 2, 1, 0x40, 60]);
+    let mut vram = [0; 0x4000];
+    vram[0x10 + 2] = 0b1100_0001; // tile 1, row 2, low bits
+    vram[0x10 + 2 + 8] = 0b0000_0111; // and high bits
+
+    let ppu = evaluate_with_vram(oam, 0, false, vram);
+    assert_eq!(ppu.sprite_count, 2);
+    assert_eq!(&ppu.sprite_attributes[..2], &[0x03, 0x40]);
+    assert_eq!(&ppu.sprite_x_counters[..2], &[30, 60]);
+    assert_eq!(ppu.sprite_pattern_shift_low[0], 0b1100_0001);
+    assert_eq!(ppu.sprite_pattern_shift_high[0], 0b0000_0111);
+
+    // The second sprite is flipped horizontally
+    assert_eq!(ppu.sprite_pattern_shift_low[1], 0b1000_0011);
+    assert_eq!(ppu.sprite_pattern_shift_high[1], 0b1110_0000);
+
+    // Empty slots are transparent
+    assert!(ppu.sprite_pattern_shift_low[2..]
+        .iter()
+        .all(|&pattern| pattern == 0));
+    assert!(ppu.sprite_pattern_shift_high[2..]
+        .iter()
+        .all(|&pattern| pattern == 0));
+}
+
+#[test]
+fn tall_sprites_flip_across_both_tiles() {
+    let mut oam = empty_oam();
+    // Tile 3: the bottom half of the 8x16 sprite is tile 3 of the $1000 pattern table. Flipped
+    // vertically, row 2 comes from row 13 of the sprite (row 5 of the bottom tile).
+    oam[..4].copy_from_slice(&[SCANLINE as u8 - 2, 3, 0x80, 0]);
+    let mut vram = [0; 0x4000];
+    vram[0x1030 + 5] = 0xAA;
+
+    let ppu = evaluate_with_vram(oam, 0, true, vram);
+    assert_eq!(ppu.sprite_count, 1);
+    assert_eq!(ppu.sprite_pattern_shift_low[0], 0xAA);
+}
+
+// Renders the scanline after SCANLINE with an opaque background everywhere and sprite 0 (solid,
+// 8x8) at the given X, up to the end of its visible pixels.
+fn render_line(sprite_x: u8, ppumask: u8) -> Ppu {
+    let mut oam = empty_oam();
+    oam[..4].copy_from_slice(&[SCANLINE as u8, 1, 0, sprite_x]);
+
+    let mut ppu = Ppu::new();
+    ppu.vram[..8].copy_from_slice(&[0xFF; 8]); // tile 0, the whole nametable
+    ppu.vram[0x10..0x18].copy_from_slice(&[0xFF; 8]); // tile 1
+    ppu.vram[0x3F01] = 0x16;
+    ppu.vram[0x3F11] = 0x2A;
+    ppu.oam = oam;
+    ppu.ppumask = ppumask;
+    ppu.scanline = SCANLINE;
+    while ppu.scanline == SCANLINE || ppu.cycle <= 257 {
+        ppu.step();
+    }
+    ppu
+}
+
+fn line_pixels(ppu: &Ppu) -> &[u16] {
+    let start = usize::from(SCANLINE + 1) * SCREEN_WIDTH;
+    &ppu.framebuffer[start..start + SCREEN_WIDTH]
+}
+
+#[test]
+fn left_column_clipping() {
+    let ppu = render_line(0, 0x1E);
+    assert_eq!(
+        &line_pixels(&ppu)[..9],
+        &[0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x2A, 0x16]
+    );
+    assert_ne!(ppu.ppustatus & SPRITE_ZERO_HIT, 0);
+
+    // Sprites hidden, the background shows through
+    let ppu = render_line(0, 0x1A);
+    assert!(line_pixels(&ppu)[..8].iter().all(|&pixel| pixel == 0x16));
+    assert_eq!(ppu.ppustatus & SPRITE_ZERO_HIT, 0);
+
+    // Background hidden, it's the backdrop color behind the sprite
+    let ppu = render_line(0, 0x1C);
+    assert!(line_pixels(&ppu)[..8].iter().all(|&pixel| pixel == 0x2A));
+    assert_eq!(line_pixels(&ppu)[8], 0x16);
+    assert_eq!(ppu.ppustatus & SPRITE_ZERO_HIT, 0);
+}
+
+#[test]
+fn no_sprite_zero_hit_on_the_last_pixel() {
+    let ppu = render_line(254, 0x1E);
+    assert_ne!(ppu.ppustatus & SPRITE_ZERO_HIT, 0);
+
+    let ppu = render_line(255, 0x1E);
+    assert_eq!(line_pixels(&ppu)[255], 0x2A);
+    assert_eq!(ppu.ppustatus & SPRITE_ZERO_HIT, 0);
+}
+
+#[test]
+fn greyscale_and_color_emphasis() {
+    // Greyscale keeps the grey column ($x0), emphasis goes into bits 6-8
+    let ppu = render_line(100, 0x1E | 0x01 | 0xA0);
+    assert_eq!(line_pixels(&ppu)[50], 0x10 | 0x140);
+    assert_eq!(line_pixels(&ppu)[100], 0x20 | 0x140);
+
+    // Red emphasis dims green and blue
+    let plain = PALETTE[0x10];
+    let emphasized = OUTPUT_PALETTE[0x050];
+    assert_eq!(emphasized.r, plain.r);
+    assert!(emphasized.g < plain.g && emphasized.b < plain.b);
+    assert_eq!(OUTPUT_PALETTE[0x10], plain);
+}